@@ -1,112 +1,3093 @@
+use std::collections::{BTreeSet, HashSet};
 use std::fmt::Write;
+use std::ops::Range;
 use std::path::Path;
-use std::{fs::File, io};
+use std::{
+    fs,
+    io::{self, Cursor},
+};
 
-use docx_rs::{Docx, Paragraph};
+use super::embed;
+use super::xml_patch::{self, edit_part, escape_xml};
 
-use crate::stylemgr::structural::StyledParagraph;
-#[allow(unused_imports)]
-use crate::stylemgr::style::Style;
-#[allow(unused_imports)]
+mod events;
+mod revisions;
+use docx_rs::{Docx, IndentLevel, NumberingId, Paragraph, Run, RunFonts, XMLDocx};
+pub use events::ChangeEvent;
+use events::{ChangeObserver, Modified};
+use revisions::offset_after_delete;
+pub use revisions::{Revision, RevisionKind};
+use thiserror::Error;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::stylemgr::image::Image;
+use crate::stylemgr::structural::{
+    ApplicableStyles, ListKind, ParagraphModifyError, ParagraphStyle, SHADING_MARKER,
+    StyledParagraph,
+};
+use crate::stylemgr::style::{Style, StyleError};
+use crate::stylemgr::table::Table;
 use crate::stylemgr::text::StyledText;
+use crate::stylemgr::text::{LINE_BREAK, RUN_MARKER, escape_html};
+
+#[derive(Debug, Error)]
+pub enum DocumentError {
+    #[error("Failed to package docx: {0}")]
+    DocxPackaging(String),
+    #[error("Unexpected docx-rs output: found no '{0}' to patch")]
+    DocxPatch(String),
+    #[error("Failed to read docx: {0}")]
+    DocxRead(String),
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Paragraph index {0} is out of bounds")]
+    IndexOutOfBounds(usize),
+    #[error("Block {0} is not a paragraph")]
+    NotAParagraph(usize),
+    #[error("The document has no content to export")]
+    EmptyDocument,
+    #[error(transparent)]
+    Paragraph(#[from] ParagraphModifyError),
+    #[error(transparent)]
+    Style(#[from] StyleError),
+    #[cfg(feature = "html")]
+    #[error("Failed to parse HTML: {0}")]
+    Html(String),
+    #[cfg(feature = "serde")]
+    #[error("Invalid JSON: {0}")]
+    Json(String),
+}
+
+/// Paragraph-level difference between two documents, as reported by `Document::diff`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParagraphDiff {
+    /// Paragraph only present in the new document, at this index
+    Added(usize),
+    /// Paragraph only present in the old document, at this index
+    Removed(usize),
+    /// Paragraph whose text was edited. The index refers to the new document
+    Changed {
+        index: usize,
+        from_text: String,
+        to_text: String,
+    },
+}
+
+/// Match found by `Document::search`. `paragraph` is the block index; for a match inside a table,
+/// `cell` says which paragraph of which cell. Offsets are characters within that paragraph's text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DocumentMatch {
+    pub paragraph: usize,
+    pub cell: Option<CellPosition>,
+    pub start_char: usize,
+    pub end_char: usize,
+}
+
+/// Paragraph `paragraph` of the cell at `row`, `column` of a table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CellPosition {
+    pub row: usize,
+    pub column: usize,
+    pub paragraph: usize,
+}
+
+/// Top-level element of a document
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Block {
+    Paragraph(StyledParagraph),
+    Table(Table),
+    Image(Image),
+}
+
+impl Block {
+    pub fn as_paragraph(&self) -> Option<&StyledParagraph> {
+        match self {
+            Block::Paragraph(sp) => Some(sp),
+            _ => None,
+        }
+    }
+
+    pub fn as_paragraph_mut(&mut self) -> Option<&mut StyledParagraph> {
+        match self {
+            Block::Paragraph(sp) => Some(sp),
+            _ => None,
+        }
+    }
+
+    /// The paragraph itself, or every paragraph of the table row by row. Images have none
+    pub fn paragraphs(&self) -> Box<dyn Iterator<Item = &StyledParagraph> + '_> {
+        match self {
+            Block::Paragraph(sp) => Box::new(std::iter::once(sp)),
+            Block::Table(table) => Box::new(table.paragraphs()),
+            Block::Image(_) => Box::new(std::iter::empty()),
+        }
+    }
+
+    pub fn paragraphs_mut(&mut self) -> Box<dyn Iterator<Item = &mut StyledParagraph> + '_> {
+        match self {
+            Block::Paragraph(sp) => Box::new(std::iter::once(sp)),
+            Block::Table(table) => Box::new(table.paragraphs_mut()),
+            Block::Image(_) => Box::new(std::iter::empty()),
+        }
+    }
+
+    /// Plain text of the block. An image stands for its alt text
+    pub fn text(&self) -> String {
+        match self {
+            Block::Paragraph(sp) => sp.text(),
+            Block::Table(table) => table.text(),
+            Block::Image(image) => image.alt_text().unwrap_or("").to_string(),
+        }
+    }
+}
+
+impl From<StyledParagraph> for Block {
+    fn from(paragraph: StyledParagraph) -> Self {
+        Block::Paragraph(paragraph)
+    }
+}
+
+impl From<Table> for Block {
+    fn from(table: Table) -> Self {
+        Block::Table(table)
+    }
+}
+
+impl From<Image> for Block {
+    fn from(image: Image) -> Self {
+        Block::Image(image)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "SerializedDocument"))]
+pub struct Document {
+    content: Vec<Block>,
+    metadata: Metadata,
+    comments: Vec<Comment>,
+    revisions: Vec<Revision>,
+    /// Author of the tracked changes, while tracking
+    tracking: Option<String>,
+    /// Style written as the docx document defaults, see `set_default_style`
+    #[cfg_attr(feature = "serde", serde(default))]
+    default_style: Style,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    on_change: ChangeObserver,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    modified: Modified,
+}
+
+/// Fields of a serialized `Document`. Comments are checked against their paragraphs like in
+/// `add_comment`; styles and images check themselves
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct SerializedDocument {
+    content: Vec<Block>,
+    metadata: Metadata,
+    comments: Vec<Comment>,
+    revisions: Vec<Revision>,
+    tracking: Option<String>,
+    #[serde(default)]
+    default_style: Style,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<SerializedDocument> for Document {
+    type Error = DocumentError;
+
+    fn try_from(document: SerializedDocument) -> Result<Self, Self::Error> {
+        let mut doc = Document {
+            content: document.content,
+            metadata: document.metadata,
+            comments: Vec::new(),
+            revisions: document.revisions,
+            tracking: document.tracking,
+            default_style: document.default_style,
+            on_change: ChangeObserver::default(),
+            modified: Modified::default(),
+        };
+        for comment in document.comments {
+            doc.add_comment(comment)?;
+        }
+        doc.mark_saved();
+        Ok(doc)
+    }
+}
+
+/// Comment attached to a character range of a paragraph. Edits through `Document` keep the range
+/// on the same text; ranges past the end of the paragraph are clamped on export
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Comment {
+    pub author: String,
+    pub text: String,
+    pub paragraph: usize,
+    pub range: Range<usize>,
+}
+
+impl Comment {
+    pub fn new(
+        author: impl Into<String>,
+        text: impl Into<String>,
+        paragraph: usize,
+        range: Range<usize>,
+    ) -> Self {
+        Self {
+            author: author.into(),
+            text: text.into(),
+            paragraph,
+            range,
+        }
+    }
+}
+
+/// Counts reported by `Document::statistics`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DocumentStatistics {
+    pub paragraphs: usize,
+    /// Runs of non-whitespace characters
+    pub words: usize,
+    pub characters: usize,
+    pub characters_no_spaces: usize,
+}
+
+/// Characters on a typical manuscript page, for `Document::estimated_page_count`
+pub const DEFAULT_CHARS_PER_PAGE: usize = 1_800;
+
+/// Range of sizes and set of fonts and colors used in a document, see `Document::style_summary`.
+/// Sizes are None for a document without text
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StyleSummary {
+    pub min_size: Option<u8>,
+    pub max_size: Option<u8>,
+    pub fonts: HashSet<String>,
+    /// Font colors, highlights not included
+    pub colors: HashSet<String>,
+}
+
+/// Options for `Document::save_as_docx_with_options`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DocxExportOptions {
+    /// Embed the fonts used by the document, so it renders the same where they aren't installed
+    pub embed_fonts: bool,
+    /// Give empty paragraphs an empty run, as some readers drop paragraphs with no runs at all.
+    /// On by default
+    pub preserve_empty_paragraphs: bool,
+    /// Fail with `DocumentError::EmptyDocument` instead of writing a document with no content
+    pub reject_empty: bool,
+}
+
+impl Default for DocxExportOptions {
+    fn default() -> Self {
+        DocxExportOptions {
+            embed_fonts: false,
+            preserve_empty_paragraphs: true,
+            reject_empty: false,
+        }
+    }
+}
+
+/// Bounds enforced by `Document::sanitize` on imported content
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SanitizeLimits {
+    pub min_size: u8,
+    pub max_size: u8,
+    /// Longest chunk kept, in characters; the rest is cut off. None keeps every chunk whole
+    pub max_chunk_chars: Option<usize>,
+}
+
+impl Default for SanitizeLimits {
+    fn default() -> Self {
+        SanitizeLimits {
+            min_size: 6,
+            max_size: 96,
+            max_chunk_chars: None,
+        }
+    }
+}
+
+/// Read-only snapshot of a document for rendering, obtained through `Document::view`
+#[derive(Debug, Clone, Copy)]
+pub struct DocumentView<'a> {
+    document: &'a Document,
+}
+
+impl<'a> DocumentView<'a> {
+    pub fn metadata(&self) -> &'a Metadata {
+        &self.document.metadata
+    }
+
+    /// See `Document::block_count`
+    pub fn block_count(&self) -> usize {
+        self.document.content.len()
+    }
+
+    /// See `Document::paragraph_count`
+    pub fn paragraph_count(&self) -> usize {
+        self.document.paragraph_count()
+    }
+
+    /// Paragraph at block index `idx`. None for tables
+    pub fn paragraph(&self, idx: usize) -> Option<&'a StyledParagraph> {
+        self.document.content.get(idx)?.as_paragraph()
+    }
+
+    /// Every paragraph in document order, including the ones inside tables
+    pub fn paragraphs(&self) -> impl Iterator<Item = &'a StyledParagraph> + 'a {
+        self.document.content.iter().flat_map(Block::paragraphs)
+    }
+
+    pub fn blocks(&self) -> &'a [Block] {
+        &self.document.content
+    }
+
+    /// Every chunk in document order, along with the index of the block holding it
+    pub fn chunks(&self) -> impl Iterator<Item = (usize, &'a StyledText)> + 'a {
+        self.document
+            .content
+            .iter()
+            .enumerate()
+            .flat_map(|(idx, block)| {
+                block
+                    .paragraphs()
+                    .flat_map(|sp| sp.chunks().iter())
+                    .map(move |st| (idx, st))
+            })
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Default, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Metadata {
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "String::is_empty"))]
+    title: String,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    authors: Option<Vec<String>>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    description: Option<String>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    category: Option<String>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    version: Option<String>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    status: Option<String>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    language: Option<String>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    keywords: Option<Vec<String>>,
+    /// Short description of the document for assistive technology
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    alt_text: Option<String>,
+}
+
+impl Metadata {
+    pub fn new(title: &str) -> Self {
+        Self {
+            title: title.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    /// Language of the document as a BCP 47 tag, e.g. "en-US"
+    pub fn language(&self) -> Option<&str> {
+        self.language.as_deref()
+    }
+
+    pub fn set_language(&mut self, language: Option<String>) {
+        self.language = language;
+    }
+
+    pub fn alt_text(&self) -> Option<&str> {
+        self.alt_text.as_deref()
+    }
+
+    pub fn set_alt_text(&mut self, alt_text: Option<String>) {
+        self.alt_text = alt_text;
+    }
+
+    pub fn add_keyword(&mut self, keyword: impl Into<String>) {
+        self.keywords
+            .get_or_insert_with(Vec::new)
+            .push(keyword.into());
+    }
+
+    /// Remove the first matching keyword. Returns whether anything was removed
+    pub fn remove_keyword(&mut self, keyword: &str) -> bool {
+        remove_from_list(&mut self.keywords, keyword)
+    }
+
+    pub fn add_author(&mut self, author: impl Into<String>) {
+        self.authors
+            .get_or_insert_with(Vec::new)
+            .push(author.into());
+    }
+
+    /// Remove the first matching author. Returns whether anything was removed
+    pub fn remove_author(&mut self, author: &str) -> bool {
+        remove_from_list(&mut self.authors, author)
+    }
+
+    /// Metadata as a JSON object. Fields that aren't set are left out
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> String {
+        // Plain strings and lists can't fail to serialize
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    /// Metadata from a JSON object as written by `to_json`. Missing fields are left unset
+    #[cfg(feature = "serde")]
+    pub fn from_json(json: &str) -> Result<Self, DocumentError> {
+        serde_json::from_str(json).map_err(|e| DocumentError::Json(e.to_string()))
+    }
+}
+
+fn remove_from_list(list: &mut Option<Vec<String>>, item: &str) -> bool {
+    let Some(items) = list else {
+        return false;
+    };
+    match items.iter().position(|x| x == item) {
+        Some(idx) => {
+            items.remove(idx);
+            true
+        }
+        None => false,
+    }
+}
+
+impl Document {
+    /// Create a blank document
+    pub fn new(title: &str) -> Self {
+        Self {
+            content: Vec::new(),
+            metadata: Metadata::new(title),
+            comments: Vec::new(),
+            revisions: Vec::new(),
+            tracking: None,
+            default_style: Style::new(),
+            on_change: ChangeObserver::default(),
+            modified: Modified::default(),
+        }
+    }
+
+    /// Build a document from plain text, one paragraph per line. Windows (`\r\n`) and old Mac
+    /// (`\r`) line endings are treated as `\n`, and a final line ending adds no empty paragraph
+    pub fn from_plain_text(title: &str, text: &str) -> Self {
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        let mut doc = Document::new(title);
+        for line in text.lines() {
+            let mut sp = StyledParagraph::new();
+            sp.add(StyledText::new(line.to_string(), Style::new()));
+            doc.add_block(sp);
+        }
+        doc.mark_saved();
+        doc
+    }
+
+    /// Build a document from HTML. Only paragraphs, line breaks and inline formatting are kept
+    #[cfg(feature = "html")]
+    pub fn from_html(title: &str, html: &str) -> Result<Self, DocumentError> {
+        Ok(Self {
+            content: super::html::parse(html)?
+                .into_iter()
+                .map(Block::Paragraph)
+                .collect(),
+            ..Self::new(title)
+        })
+    }
+
+    /// Build a document from docx bytes. Only paragraph text with bold, italic and underline is
+    /// kept
+    pub fn from_docx_bytes(title: &str, bytes: &[u8]) -> Result<Self, DocumentError> {
+        Ok(Self {
+            content: super::docx_reader::parse(bytes)?
+                .into_iter()
+                .map(Block::Paragraph)
+                .collect(),
+            ..Self::new(title)
+        })
+    }
+
+    /// Read a docx file, titled after the file name
+    pub fn load_from_docx<P: AsRef<Path>>(path: P) -> Result<Self, DocumentError> {
+        let path = path.as_ref();
+        let title = path
+            .file_stem()
+            .map_or_else(String::new, |s| s.to_string_lossy().into_owned());
+        Self::from_docx_bytes(&title, &fs::read(path)?)
+    }
+
+    /// Whole document (content, metadata, comments and revisions) as JSON bytes, for the
+    /// clipboard or sending to another process
+    #[cfg(feature = "serde")]
+    pub fn to_bytes(&self) -> Result<Vec<u8>, DocumentError> {
+        serde_json::to_vec(self).map_err(|e| DocumentError::Json(e.to_string()))
+    }
+
+    /// Read a document written by `to_bytes`
+    #[cfg(feature = "serde")]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DocumentError> {
+        serde_json::from_slice(bytes).map_err(|e| DocumentError::Json(e.to_string()))
+    }
+
+    /// Borrow the document read-only, e.g. for rendering
+    pub fn view(&self) -> DocumentView<'_> {
+        DocumentView { document: self }
+    }
+
+    pub fn get_metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Mutable metadata. Reports `ChangeEvent::MetadataChanged` to the observer right away
+    pub fn get_metadata_mut(&mut self) -> &mut Metadata {
+        self.notify(ChangeEvent::MetadataChanged);
+        &mut self.metadata
+    }
+
+    /// Number of blocks (paragraphs, tables and images), the range of the block indices the
+    /// other methods take
+    pub fn block_count(&self) -> usize {
+        self.content.len()
+    }
+
+    /// Number of paragraphs, counting every paragraph of every table cell. Images are not
+    /// paragraphs. This is the count `statistics` reports; without tables or images it is the
+    /// same as `block_count`
+    pub fn paragraph_count(&self) -> usize {
+        self.all_paragraphs().count()
+    }
+
+    pub fn default_style(&self) -> &Style {
+        &self.default_style
+    }
+
+    /// Style whose font and size become the docx document defaults (the base of Word's Normal
+    /// style). Runs matching it leave those properties out, which keeps the file small. Colors
+    /// are still written on every run, as docx-rs can't set a default color
+    pub fn set_default_style(&mut self, style: Style) {
+        self.default_style = style;
+        self.notify_styles_changed();
+    }
+
+    pub fn blocks(&self) -> &[Block] {
+        &self.content
+    }
+
+    /// True when there is nothing to show: no images and no text in any paragraph or table cell
+    pub fn is_empty(&self) -> bool {
+        self.content.iter().all(|block| {
+            !matches!(block, Block::Image(_)) && block.paragraphs().all(StyledParagraph::is_empty)
+        })
+    }
+
+    /// Guard for exports where an empty file is useless, see `is_empty`
+    pub fn require_non_empty(&self) -> Result<(), DocumentError> {
+        if self.is_empty() {
+            return Err(DocumentError::EmptyDocument);
+        }
+        Ok(())
+    }
+
+    /// Append a paragraph or table at the end of the document
+    pub fn add_block(&mut self, block: impl Into<Block>) {
+        self.content.push(block.into());
+        self.notify(ChangeEvent::ParagraphAdded(self.content.len() - 1));
+    }
+
+    /// Insert a page break after block `after_paragraph`: an empty paragraph that starts a new
+    /// page. Comments and revisions on later paragraphs move along
+    pub fn insert_page_break(&mut self, after_paragraph: usize) -> Result<(), DocumentError> {
+        if after_paragraph >= self.content.len() {
+            return Err(DocumentError::IndexOutOfBounds(after_paragraph));
+        }
+        let mut page_break = StyledParagraph::new();
+        page_break.style = ParagraphStyle::new().set_page_break_before(true);
+        self.content.insert(after_paragraph + 1, page_break.into());
+
+        for c in self
+            .comments
+            .iter_mut()
+            .filter(|c| c.paragraph > after_paragraph)
+        {
+            c.paragraph += 1;
+        }
+        for r in self
+            .revisions
+            .iter_mut()
+            .filter(|r| r.paragraph > after_paragraph)
+        {
+            r.paragraph += 1;
+        }
+        self.notify(ChangeEvent::ParagraphAdded(after_paragraph + 1));
+        Ok(())
+    }
+
+    /// Paragraph at block index `idx`
+    fn paragraph(&self, idx: usize) -> Result<&StyledParagraph, DocumentError> {
+        self.content
+            .get(idx)
+            .ok_or(DocumentError::IndexOutOfBounds(idx))?
+            .as_paragraph()
+            .ok_or(DocumentError::NotAParagraph(idx))
+    }
+
+    fn paragraph_mut(&mut self, idx: usize) -> Result<&mut StyledParagraph, DocumentError> {
+        self.content
+            .get_mut(idx)
+            .ok_or(DocumentError::IndexOutOfBounds(idx))?
+            .as_paragraph_mut()
+            .ok_or(DocumentError::NotAParagraph(idx))
+    }
+
+    /// Every paragraph in document order, including the ones inside tables
+    fn all_paragraphs(&self) -> impl Iterator<Item = &StyledParagraph> {
+        self.content.iter().flat_map(Block::paragraphs)
+    }
+
+    /// Replace the paragraph at `index`, returning the old one. Pending revisions and comments on
+    /// it are discarded since they describe the old text
+    pub fn set_paragraph(
+        &mut self,
+        index: usize,
+        paragraph: StyledParagraph,
+    ) -> Result<StyledParagraph, DocumentError> {
+        let slot = self.paragraph_mut(index)?;
+        let old = std::mem::replace(slot, paragraph);
+        self.revisions.retain(|r| r.paragraph != index);
+        self.comments.retain(|c| c.paragraph != index);
+        self.notify(ChangeEvent::TextChanged(index));
+        Ok(old)
+    }
+
+    /// Insert `text` into a paragraph at a character offset, inheriting the surrounding style.
+    /// Recorded as a revision while tracking changes
+    pub fn insert_text(
+        &mut self,
+        para: usize,
+        char_offset: usize,
+        text: &str,
+    ) -> Result<(), DocumentError> {
+        self.paragraph_mut(para)?.insert_text(char_offset, text)?;
+        self.revisions_after_insert(para, char_offset, text.chars().count());
+        self.comments_after_insert(para, char_offset, text.chars().count());
+        self.notify(ChangeEvent::TextChanged(para));
+        Ok(())
+    }
+
+    /// Append a chunk of text to the last block, for streaming text in. A new paragraph is added
+    /// first when the document is empty or ends with a table or image. Recorded as a revision
+    /// while tracking changes
+    pub fn append_run(&mut self, text: &str, style: Style) {
+        if self.content.last().and_then(Block::as_paragraph).is_none() {
+            self.add_block(StyledParagraph::new());
+        }
+        let para = self.content.len() - 1;
+        let paragraph = self.content[para]
+            .as_paragraph_mut()
+            .expect("last block is a paragraph");
+        let offset = paragraph.char_len();
+        paragraph.add(StyledText::new(text.to_string(), style));
+        self.revisions_after_insert(para, offset, text.chars().count());
+        self.comments_after_insert(para, offset, text.chars().count());
+        self.notify(ChangeEvent::TextChanged(para));
+    }
+
+    /// Delete a character range within a paragraph, returning the removed text. While tracking
+    /// changes the text is only marked as deleted (and still returned)
+    pub fn delete_range(
+        &mut self,
+        para: usize,
+        start_char: usize,
+        end_char: usize,
+    ) -> Result<String, DocumentError> {
+        let len = self.paragraph(para)?.char_len();
+        if start_char > end_char || end_char > len {
+            return Err(ParagraphModifyError::InvalidRange(start_char, end_char, len).into());
+        }
+        if self.track_deletion(para, start_char..end_char) {
+            self.notify(ChangeEvent::TextChanged(para));
+            let text = self.paragraph(para)?.text();
+            return Ok(text
+                .chars()
+                .skip(start_char)
+                .take(end_char - start_char)
+                .collect());
+        }
+
+        let deleted = self
+            .paragraph_mut(para)?
+            .delete_range(start_char, end_char)?;
+        self.revisions_after_delete(para, start_char..end_char);
+        self.comments_after_delete(para, start_char..end_char);
+        self.notify(ChangeEvent::TextChanged(para));
+        Ok(deleted)
+    }
+
+    /// Apply `style` to a selection within one paragraph, given as character offsets
+    pub fn style_selection(
+        &mut self,
+        para: usize,
+        start_char: usize,
+        end_char: usize,
+        style: Style,
+    ) -> Result<(), DocumentError> {
+        self.paragraph_mut(para)?
+            .modify_spanning(style, start_char, end_char)?;
+        self.notify(ChangeEvent::StyleChanged(para));
+        Ok(())
+    }
+
+    /// Apply a toolbar command to a selection within one paragraph. Each chunk in the selection
+    /// is changed on its own, so toggles flip every chunk. Nothing changes if the command fails
+    pub fn apply_command(
+        &mut self,
+        para: usize,
+        start_char: usize,
+        end_char: usize,
+        command: ApplicableStyles,
+    ) -> Result<(), DocumentError> {
+        let paragraph = self.paragraph_mut(para)?;
+        let len = paragraph.char_len();
+        if start_char > end_char || end_char > len {
+            return Err(ParagraphModifyError::InvalidRange(start_char, end_char, len).into());
+        }
+
+        let mut updated = paragraph.clone();
+        let first = updated.split_at_char(start_char);
+        let last = updated.split_at_char(end_char);
+        for st in &mut updated.chunks_mut()[first..last] {
+            st.change_style(command.clone())?;
+        }
+        updated.normalize();
+        *paragraph = updated;
+        self.notify(ChangeEvent::StyleChanged(para));
+        Ok(())
+    }
+
+    /// Every chunk of the top-level paragraphs as (block index, char offset of the chunk within
+    /// its paragraph, chunk), for mapping a flat text buffer back to the model. Tables and images
+    /// are skipped
+    pub fn chunks_with_offsets(&self) -> impl Iterator<Item = (usize, usize, &StyledText)> {
+        self.content
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, block)| Some((idx, block.as_paragraph()?)))
+            .flat_map(|(idx, sp)| {
+                sp.chunks().iter().scan(0, move |offset, st| {
+                    let start = *offset;
+                    *offset += st.char_count();
+                    Some((idx, start, st))
+                })
+            })
+    }
+
+    /// Style of the chunk holding the character at `char_offset`, for updating the toolbar as the
+    /// caret moves. On a chunk boundary this is the following chunk, at the very end the last one.
+    /// None for an offset past the end, an empty paragraph or a block that isn't a paragraph
+    pub fn style_at(&self, para: usize, char_offset: usize) -> Option<Style> {
+        let paragraph = self.paragraph(para).ok()?;
+        let (idx, _) = paragraph.locate(char_offset)?;
+        Some(paragraph.chunks()[idx].style().clone())
+    }
+
+    /// Char range `(start, end)` of the word holding the character at `char_offset`, following
+    /// the Unicode word boundary rules, for double-click selection. Punctuation is its own word.
+    /// None on whitespace, past the end or in a block that isn't a paragraph
+    pub fn word_at(&self, para: usize, char_offset: usize) -> Option<(usize, usize)> {
+        let text = self.paragraph(para).ok()?.text();
+        let mut start = 0;
+        for segment in text.split_word_bounds() {
+            let end = start + segment.chars().count();
+            if char_offset < end {
+                return (!segment.chars().all(char::is_whitespace)).then_some((start, end));
+            }
+            start = end;
+        }
+        None
+    }
+
+    /// Block indices of the paragraphs for which `is_heading` holds, e.g. a palette's
+    /// `StylePalette::is_heading`
+    pub fn headings<F: Fn(&StyledParagraph) -> bool>(&self, is_heading: F) -> Vec<usize> {
+        self.content
+            .iter()
+            .enumerate()
+            .filter(|(_, block)| block.as_paragraph().is_some_and(&is_heading))
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+
+    /// First heading after `from_para`, for jumping forward through the outline. Only the blocks
+    /// up to it are looked at
+    pub fn next_heading<F: Fn(&StyledParagraph) -> bool>(
+        &self,
+        from_para: usize,
+        is_heading: F,
+    ) -> Option<usize> {
+        let start = from_para.saturating_add(1);
+        self.content
+            .iter()
+            .skip(start)
+            .position(|block| block.as_paragraph().is_some_and(&is_heading))
+            .map(|offset| start + offset)
+    }
+
+    /// Last heading before `from_para`
+    pub fn prev_heading<F: Fn(&StyledParagraph) -> bool>(
+        &self,
+        from_para: usize,
+        is_heading: F,
+    ) -> Option<usize> {
+        self.content[..from_para.min(self.content.len())]
+            .iter()
+            .rposition(|block| block.as_paragraph().is_some_and(&is_heading))
+    }
+
+    /// Characters `start_char..end_char` of a paragraph in the tagged-text format, for putting a
+    /// styled copy on the clipboard. `StyledParagraph::from_tagged_text` reads it back
+    pub fn selection_as_tagged(
+        &self,
+        para: usize,
+        start_char: usize,
+        end_char: usize,
+    ) -> Result<String, DocumentError> {
+        let paragraph = self.paragraph(para)?;
+        let len = paragraph.char_len();
+        if start_char > end_char || end_char > len {
+            return Err(ParagraphModifyError::InvalidRange(start_char, end_char, len).into());
+        }
+
+        let mut selection = paragraph.clone();
+        let first = selection.split_at_char(start_char);
+        let last = selection.split_at_char(end_char);
+        Ok(selection.chunks()[first..last]
+            .iter()
+            .map(StyledText::apply_style_tagging)
+            .collect())
+    }
+
+    /// Paragraph, word and character counts over the plain text. Paragraphs inside tables count
+    /// too, as in `paragraph_count`
+    pub fn statistics(&self) -> DocumentStatistics {
+        let mut stats = DocumentStatistics::default();
+        for sp in self.all_paragraphs() {
+            stats.paragraphs += 1;
+            let text = sp.text();
+            stats.words += text.split_whitespace().count();
+            for c in text.chars() {
+                stats.characters += 1;
+                if !c.is_whitespace() {
+                    stats.characters_no_spaces += 1;
+                }
+            }
+        }
+        stats
+    }
+
+    /// Line and column, both from 1, of the cursor at `char_offset` in paragraph `para`, for the
+    /// status bar. Every paragraph and soft line break starts a line; a table takes a line per
+    /// cell paragraph and an image one line. None when `para` isn't a paragraph or the offset is
+    /// past its end
+    pub fn line_column(&self, para: usize, char_offset: usize) -> Option<(usize, usize)> {
+        let paragraph = self.view().paragraph(para)?;
+        if char_offset > paragraph.char_len() {
+            return None;
+        }
+        let lines = |sp: &StyledParagraph| 1 + sp.text().matches(LINE_BREAK).count();
+        let before: usize = self.content[..para]
+            .iter()
+            .map(|block| block.paragraphs().map(lines).sum::<usize>().max(1))
+            .sum();
+        let head: String = paragraph.text().chars().take(char_offset).collect();
+        let breaks = head.matches(LINE_BREAK).count();
+        let column = head.chars().rev().take_while(|&c| c != LINE_BREAK).count();
+        Some((before + breaks + 1, column + 1))
+    }
+
+    /// Total number of characters (Unicode scalar values) in the plain text
+    pub fn char_len(&self) -> usize {
+        self.view().chunks().map(|(_, st)| st.char_count()).sum()
+    }
+
+    /// Rough page count for the status bar: the character count divided by `chars_per_page`
+    /// (usually `DEFAULT_CHARS_PER_PAGE`), rounded up. This is a heuristic, not a layout; it
+    /// ignores font sizes, paragraph breaks and images. A document without text has 0 pages
+    pub fn estimated_page_count(&self, chars_per_page: usize) -> usize {
+        self.char_len().div_ceil(chars_per_page.max(1))
+    }
+
+    /// Number of words (runs of non-whitespace) in the plain text. Words can span chunks but not
+    /// paragraphs
+    pub fn word_len(&self) -> usize {
+        let mut words = 0;
+        for sp in self.all_paragraphs() {
+            let mut in_word = false;
+            for c in sp.chunks().iter().flat_map(|st| st.text.chars()) {
+                let starts = !c.is_whitespace() && !in_word;
+                words += usize::from(starts);
+                in_word = !c.is_whitespace();
+            }
+        }
+        words
+    }
+
+    /// Sizes, fonts and colors used across every chunk
+    pub fn style_summary(&self) -> StyleSummary {
+        let mut summary = StyleSummary::default();
+        for (_, st) in self.view().chunks() {
+            let size = st.style().size();
+            summary.min_size = Some(summary.min_size.map_or(size, |min| min.min(size)));
+            summary.max_size = Some(summary.max_size.map_or(size, |max| max.max(size)));
+            summary.fonts.insert(st.style().font().to_string());
+            summary.colors.insert(st.style().font_color().to_string());
+        }
+        summary
+    }
+
+    /// Find every non-overlapping occurrence of `needle`, paragraph by paragraph. Table cells
+    /// are searched too, row by row
+    pub fn search(&self, needle: &str, case_sensitive: bool) -> Vec<DocumentMatch> {
+        let needle: Vec<char> = needle.chars().collect();
+        if needle.is_empty() {
+            return Vec::new();
+        }
+        let same =
+            |a: char, b: char| a == b || (!case_sensitive && a.to_lowercase().eq(b.to_lowercase()));
+
+        let mut matches = Vec::new();
+        let mut search_in = |sp: &StyledParagraph, paragraph: usize, cell: Option<CellPosition>| {
+            let haystack: Vec<char> = sp.text().chars().collect();
+            let mut start = 0;
+            while start + needle.len() <= haystack.len() {
+                let window = &haystack[start..start + needle.len()];
+                if window.iter().zip(&needle).all(|(&a, &b)| same(a, b)) {
+                    matches.push(DocumentMatch {
+                        paragraph,
+                        cell,
+                        start_char: start,
+                        end_char: start + needle.len(),
+                    });
+                    start += needle.len();
+                } else {
+                    start += 1;
+                }
+            }
+        };
+        for (idx, block) in self.content.iter().enumerate() {
+            match block {
+                Block::Paragraph(sp) => search_in(sp, idx, None),
+                Block::Table(table) => {
+                    for (row, cells) in table.rows().iter().enumerate() {
+                        for (column, cell) in cells.iter().enumerate() {
+                            for (paragraph, sp) in cell.iter().enumerate() {
+                                let position = CellPosition {
+                                    row,
+                                    column,
+                                    paragraph,
+                                };
+                                search_in(sp, idx, Some(position));
+                            }
+                        }
+                    }
+                }
+                Block::Image(_) => {}
+            }
+        }
+        matches
+    }
+
+    /// Remove paragraphs that are empty or hold only whitespace, returning how many were removed.
+    /// With `keep_trailing`, a blank last paragraph is kept so the cursor has somewhere to go.
+    /// Comments and revisions follow their paragraphs; those on removed paragraphs are dropped
+    pub fn trim_empty_paragraphs(&mut self, keep_trailing: bool) -> usize {
+        let last = self.content.len().saturating_sub(1);
+        let blank = |idx: usize, block: &Block| {
+            block.as_paragraph().is_some_and(|sp| {
+                sp.is_empty() || sp.chunks().iter().all(StyledText::is_whitespace)
+            }) && !(keep_trailing && idx == last)
+        };
+
+        // New index of every block, None for the removed ones
+        let mut next = 0;
+        let mapping: Vec<Option<usize>> = self
+            .content
+            .iter()
+            .enumerate()
+            .map(|(idx, block)| {
+                if blank(idx, block) {
+                    return None;
+                }
+                next += 1;
+                Some(next - 1)
+            })
+            .collect();
+        let removed = mapping.iter().filter(|m| m.is_none()).count();
+        if removed == 0 {
+            return 0;
+        }
+
+        let mut kept = mapping.iter().map(Option::is_some);
+        self.content.retain(|_| kept.next().unwrap_or(true));
+        self.comments
+            .retain_mut(|c| match mapping.get(c.paragraph) {
+                Some(Some(new)) => {
+                    c.paragraph = *new;
+                    true
+                }
+                _ => false,
+            });
+        self.revisions
+            .retain_mut(|r| match mapping.get(r.paragraph) {
+                Some(Some(new)) => {
+                    r.paragraph = *new;
+                    true
+                }
+                _ => false,
+            });
+        for (idx, _) in mapping
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, m)| m.is_none())
+        {
+            self.notify(ChangeEvent::ParagraphRemoved(idx));
+        }
+        removed
+    }
+
+    /// Fonts used in the document that are not installed, sorted and without duplicates, so the
+    /// user can be asked for substitutions once
+    pub fn missing_fonts(&self) -> Vec<String> {
+        self.all_paragraphs()
+            .flat_map(|sp| sp.chunks().iter().map(|st| st.style().font()))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .filter(|font| !Style::font_is_available(font))
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Split into sections, each starting at a paragraph for which `is_boundary` holds (e.g. a
+    /// heading). Sections copy the metadata, titled after their boundary paragraph; blocks before
+    /// the first boundary form a leading section that keeps the original title. Comments and
+    /// revisions go with their paragraphs
+    pub fn split_by<F: Fn(&StyledParagraph) -> bool>(&self, is_boundary: F) -> Vec<Document> {
+        if self.content.is_empty() {
+            return Vec::new();
+        }
+        let boundary = |block: &Block| block.as_paragraph().is_some_and(&is_boundary);
+        let starts: Vec<usize> = (0..self.content.len())
+            .filter(|&idx| idx == 0 || boundary(&self.content[idx]))
+            .collect();
+        let ends: Vec<usize> = starts
+            .iter()
+            .skip(1)
+            .copied()
+            .chain([self.content.len()])
+            .collect();
+
+        starts
+            .into_iter()
+            .zip(ends)
+            .map(|(start, end)| {
+                let mut metadata = self.metadata.clone();
+                if boundary(&self.content[start]) {
+                    let heading = self.content[start].text();
+                    if !heading.trim().is_empty() {
+                        metadata.title = heading.trim().to_string();
+                    }
+                }
+                let range = start..end;
+                Document {
+                    content: self.content[start..end].to_vec(),
+                    metadata,
+                    comments: self
+                        .comments
+                        .iter()
+                        .filter(|c| range.contains(&c.paragraph))
+                        .map(|c| Comment {
+                            paragraph: c.paragraph - start,
+                            ..c.clone()
+                        })
+                        .collect(),
+                    revisions: self
+                        .revisions
+                        .iter()
+                        .filter(|r| range.contains(&r.paragraph))
+                        .map(|r| Revision {
+                            paragraph: r.paragraph - start,
+                            ..r.clone()
+                        })
+                        .collect(),
+                    tracking: self.tracking.clone(),
+                    default_style: self.default_style.clone(),
+                    on_change: ChangeObserver::default(),
+                    modified: Modified::default(),
+                }
+            })
+            .collect()
+    }
+
+    /// Turn straight quotes into typographic ones in every paragraph, table cells included. See
+    /// `StyledParagraph::apply_smart_quotes`. Returns the number of quotes changed
+    pub fn apply_smart_quotes(&mut self) -> usize {
+        let mut total = 0;
+        for idx in 0..self.content.len() {
+            let changed: usize = self.content[idx]
+                .paragraphs_mut()
+                .map(StyledParagraph::apply_smart_quotes)
+                .sum();
+            if changed > 0 {
+                self.notify(ChangeEvent::TextChanged(idx));
+            }
+            total += changed;
+        }
+        total
+    }
+
+    /// Run `f` on the style of every chunk, table cells included, e.g. to apply a theme
+    pub fn map_styles<F: FnMut(&mut Style)>(&mut self, mut f: F) {
+        for sp in self.content.iter_mut().flat_map(Block::paragraphs_mut) {
+            for chunk in sp.chunks_mut() {
+                let mut style = chunk.style().clone();
+                f(&mut style);
+                chunk.set_style(style);
+            }
+        }
+        self.notify_styles_changed();
+    }
+
+    /// Make content from an untrusted source safe to edit and export: sizes are clamped, invalid
+    /// colors and fonts reset (see `Style::sanitized` and `ParagraphStyle::sanitized`) and
+    /// overlong chunks truncated. Revisions on truncated paragraphs are dropped, like in
+    /// `set_paragraph`, and comment ranges are clipped to the text that is left. Returns the number
+    /// of chunks and paragraph styles changed
+    pub fn sanitize(&mut self, limits: SanitizeLimits) -> usize {
+        let mut changed = 0;
+        for idx in 0..self.content.len() {
+            let (mut restyled, mut cut) = (false, Vec::new());
+            for sp in self.content[idx].paragraphs_mut() {
+                let style = sp.style.sanitized();
+                if style != sp.style {
+                    sp.style = style;
+                    changed += 1;
+                    restyled = true;
+                }
+                // Offset of the chunk in the paragraph as truncated so far
+                let mut offset = 0;
+                for chunk in sp.chunks_mut() {
+                    let style = chunk.style().sanitized(limits.min_size, limits.max_size);
+                    let restyle = &style != chunk.style();
+                    if restyle {
+                        chunk.set_style(style);
+                    }
+                    let chars = chunk.char_count();
+                    let kept = limits.max_chunk_chars.map_or(chars, |max| chars.min(max));
+                    if let Some((byte, _)) = chunk.text.char_indices().nth(kept) {
+                        chunk.text.truncate(byte);
+                        cut.push(offset + kept..offset + chars);
+                    }
+                    offset += kept;
+                    changed += usize::from(restyle || kept < chars);
+                    restyled |= restyle;
+                }
+            }
+            if !cut.is_empty() {
+                self.revisions.retain(|r| r.paragraph != idx);
+                for range in cut {
+                    self.comments_after_delete(idx, range);
+                }
+                self.notify(ChangeEvent::TextChanged(idx));
+            } else if restyled {
+                self.notify(ChangeEvent::StyleChanged(idx));
+            }
+        }
+        changed
+    }
+
+    /// Remove all character formatting from every paragraph, tables included
+    pub fn strip_formatting(&mut self) {
+        for sp in self.content.iter_mut().flat_map(Block::paragraphs_mut) {
+            sp.clear_style();
+        }
+        self.notify_styles_changed();
+    }
+
+    fn notify_styles_changed(&self) {
+        for (idx, block) in self.content.iter().enumerate() {
+            if !matches!(block, Block::Image(_)) {
+                self.notify(ChangeEvent::StyleChanged(idx));
+            }
+        }
+    }
+
+    /// Attach a comment to a character range of one of the paragraphs
+    pub fn add_comment(&mut self, comment: Comment) -> Result<(), DocumentError> {
+        let len = self.paragraph(comment.paragraph)?.char_len();
+        if comment.range.start > comment.range.end || comment.range.end > len {
+            return Err(ParagraphModifyError::InvalidRange(
+                comment.range.start,
+                comment.range.end,
+                len,
+            )
+            .into());
+        }
+        let para = comment.paragraph;
+        self.comments.push(comment);
+        self.notify(ChangeEvent::CommentAdded(para));
+        Ok(())
+    }
+
+    /// Keep comment ranges on the same text after `len` characters were inserted at `offset`.
+    /// Text inserted inside a range becomes part of it
+    fn comments_after_insert(&mut self, para: usize, offset: usize, len: usize) {
+        for c in self.comments.iter_mut().filter(|c| c.paragraph == para) {
+            if c.range.start >= offset {
+                c.range.start += len;
+                c.range.end += len;
+            } else if c.range.end > offset {
+                c.range.end += len;
+            }
+        }
+    }
+
+    /// Keep comment ranges on the same text after `range` was removed from paragraph `para`.
+    /// Comments whose text was removed entirely are dropped
+    fn comments_after_delete(&mut self, para: usize, range: Range<usize>) {
+        self.comments.retain_mut(|c| {
+            if c.paragraph != para {
+                return true;
+            }
+            let point = c.range.is_empty();
+            c.range = offset_after_delete(c.range.start, &range)
+                ..offset_after_delete(c.range.end, &range);
+            point || !c.range.is_empty()
+        });
+    }
+
+    pub fn comments(&self) -> &[Comment] {
+        &self.comments
+    }
+
+    /// Comments attached to paragraph `para`, in the order they were added
+    pub fn comments_for(&self, para: usize) -> impl Iterator<Item = &Comment> {
+        self.comments.iter().filter(move |c| c.paragraph == para)
+    }
+
+    /// Remove every paragraph, keeping the metadata as is
+    pub fn clear_content(&mut self) {
+        let blocks = self.content.len();
+        self.content.clear();
+        self.comments.clear();
+        self.revisions.clear();
+        for idx in (0..blocks).rev() {
+            self.notify(ChangeEvent::ParagraphRemoved(idx));
+        }
+    }
+
+    /// Remove every paragraph and reset the metadata, keeping only the title
+    pub fn clear_all(&mut self) {
+        self.clear_content();
+        self.tracking = None;
+        self.metadata = Metadata::new(&self.metadata.title);
+        self.notify(ChangeEvent::MetadataChanged);
+    }
+    /// Get full document as string
+    pub fn get_text(&self, tagged: bool) -> String {
+        let mut buffer = String::with_capacity(self.content.len() * 100);
+
+        for sp in self.all_paragraphs() {
+            for x in sp.chunks() {
+                if tagged {
+                    let _ = write!(buffer, "{}", x.apply_style_tagging());
+                } else {
+                    buffer.push_str(&x.text);
+                }
+            }
+        }
+        buffer
+    }
+
+    /// Compare against a newer version of the document. Paragraphs are matched by their plain text
+    /// (LCS), and an addition directly followed by a removal is reported as a single change
+    pub fn diff(&self, other: &Document) -> Vec<ParagraphDiff> {
+        let old: Vec<String> = self.content.iter().map(Block::text).collect();
+        let new: Vec<String> = other.content.iter().map(Block::text).collect();
+
+        // lcs[i][j] = length of the LCS of old[i..] and new[j..]
+        let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+        for i in (0..old.len()).rev() {
+            for j in (0..new.len()).rev() {
+                lcs[i][j] = if old[i] == new[j] {
+                    lcs[i + 1][j + 1] + 1
+                } else {
+                    lcs[i + 1][j].max(lcs[i][j + 1])
+                };
+            }
+        }
+
+        let mut diffs = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < old.len() || j < new.len() {
+            if i < old.len() && j < new.len() && old[i] == new[j] {
+                i += 1;
+                j += 1;
+            } else if i < old.len() && (j == new.len() || lcs[i + 1][j] > lcs[i][j + 1]) {
+                match diffs.last() {
+                    Some(ParagraphDiff::Added(a)) => {
+                        let index = *a;
+                        diffs.pop();
+                        diffs.push(ParagraphDiff::Changed {
+                            index,
+                            from_text: old[i].clone(),
+                            to_text: new[index].clone(),
+                        });
+                    }
+                    _ => diffs.push(ParagraphDiff::Removed(i)),
+                }
+                i += 1;
+            } else {
+                diffs.push(ParagraphDiff::Added(j));
+                j += 1;
+            }
+        }
+        diffs
+    }
+
+    /// Document body as HTML, one `<p>` per paragraph
+    pub fn to_html(&self) -> String {
+        let mut buffer = String::with_capacity(self.content.len() * 200);
+
+        buffer.push_str("<!DOCTYPE html>\n");
+        match self.metadata.language() {
+            Some(lang) => {
+                let _ = writeln!(buffer, "<html lang=\"{}\">", escape_html(lang));
+            }
+            None => buffer.push_str("<html>\n"),
+        }
+        buffer.push_str("<head>\n<meta charset=\"utf-8\">\n");
+        let _ = writeln!(
+            buffer,
+            "<title>{}</title>",
+            escape_html(self.metadata.title())
+        );
+        if let Some(alt) = self.metadata.alt_text() {
+            let _ = writeln!(
+                buffer,
+                "<meta name=\"description\" content=\"{}\">",
+                escape_html(alt)
+            );
+        }
+        buffer.push_str("</head>\n<body>\n");
+        for block in &self.content {
+            match block {
+                Block::Paragraph(sp) => buffer.push_str(&sp.to_html()),
+                Block::Table(table) => buffer.push_str(&table.to_html()),
+                Block::Image(image) => buffer.push_str(&image.to_html()),
+            }
+            buffer.push('\n');
+        }
+        buffer.push_str("</body>\n</html>\n");
+        buffer
+    }
+
+    /// Document body as Markdown. Paragraphs are separated by a blank line, list items by a newline
+    pub fn to_markdown(&self) -> String {
+        let mut buffer = String::with_capacity(self.content.len() * 100);
+        let mut previous_list = None;
+        for block in &self.content {
+            let list = block
+                .as_paragraph()
+                .is_some_and(|sp| sp.style.list().is_some());
+            match previous_list {
+                Some(true) if list => buffer.push('\n'),
+                Some(_) => buffer.push_str("\n\n"),
+                None => {}
+            }
+            match block {
+                Block::Paragraph(sp) => buffer.push_str(&sp.to_markdown()),
+                Block::Table(table) => buffer.push_str(&table.to_markdown()),
+                Block::Image(image) => buffer.push_str(&image.to_markdown()),
+            }
+            previous_list = Some(list);
+        }
+        if !buffer.is_empty() {
+            buffer.push('\n');
+        }
+        buffer
+    }
+
+    /// Document body as LaTeX, paragraphs separated by blank lines and tables as `tabular`. There
+    /// is no preamble; colored text needs `\usepackage{xcolor}`. Images can't be inlined, so they
+    /// are left as a comment with their alt text
+    #[cfg(feature = "latex")]
+    pub fn to_latex(&self) -> String {
+        let blocks: Vec<String> = self
+            .content
+            .iter()
+            .map(|block| match block {
+                Block::Paragraph(sp) => sp.to_latex(),
+                Block::Table(table) => table.to_latex(),
+                Block::Image(image) => format!(
+                    "% Image: {}",
+                    image.alt_text().unwrap_or("").replace(['\n', '\r'], " ")
+                ),
+            })
+            .collect();
+        let mut latex = blocks.join("\n\n");
+        if !latex.is_empty() {
+            latex.push('\n');
+        }
+        latex
+    }
+
+    /// Document styled with ANSI escapes for a terminal preview, one line per paragraph. Tables
+    /// are shown as tab-separated text and images by their alt text
+    pub fn to_ansi(&self) -> String {
+        let mut buffer = String::with_capacity(self.content.len() * 100);
+        for block in &self.content {
+            match block {
+                Block::Paragraph(sp) => buffer.push_str(&sp.to_ansi()),
+                Block::Table(table) => buffer.push_str(&table.text()),
+                Block::Image(image) => {
+                    let _ = write!(buffer, "[image: {}]", image.alt_text().unwrap_or(""));
+                }
+            }
+            buffer.push('\n');
+        }
+        buffer
+    }
+
+    /// Plain text with every paragraph word-wrapped at `width` characters, one paragraph per line
+    /// group. Whitespace runs collapse to a single space and words longer than `width` are split
+    pub fn to_wrapped_text(&self, width: usize) -> String {
+        let width = width.max(1);
+        let mut lines = Vec::new();
+        for block in &self.content {
+            for line in block.text().split('\n') {
+                wrap_line(line, width, &mut lines);
+            }
+        }
+        let mut buffer = lines.join("\n");
+        if !buffer.is_empty() {
+            buffer.push('\n');
+        }
+        buffer
+    }
+
+    /// Plain text with each paragraph indented by its `ParagraphStyle::indent_level`, using
+    /// `tab_width` spaces per level, or a tab character per level when `tab_width` is 0
+    pub fn to_indented_text(&self, tab_width: usize) -> String {
+        let unit = if tab_width == 0 {
+            "\t".to_string()
+        } else {
+            " ".repeat(tab_width)
+        };
+        let mut buffer = String::with_capacity(self.content.len() * 100);
+        for block in &self.content {
+            if let Block::Paragraph(sp) = block {
+                buffer.push_str(&unit.repeat(sp.style.indent_level()));
+            }
+            buffer.push_str(&block.text());
+            buffer.push('\n');
+        }
+        buffer
+    }
+
+    /// `to_markdown` preceded by YAML front matter with the populated metadata fields. Without
+    /// any metadata only the body is returned
+    pub fn to_markdown_with_frontmatter(&self) -> String {
+        let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+        let mut front = String::new();
+        let m = &self.metadata;
+        if !m.title.is_empty() {
+            let _ = writeln!(front, "title: {}", quote(&m.title));
+        }
+        for (key, list) in [("authors", &m.authors), ("keywords", &m.keywords)] {
+            if let Some(items) = list.as_ref().filter(|items| !items.is_empty()) {
+                let _ = writeln!(front, "{}:", key);
+                for item in items {
+                    let _ = writeln!(front, "  - {}", quote(item));
+                }
+            }
+        }
+        for (key, value) in [("description", &m.description), ("lang", &m.language)] {
+            if let Some(value) = value {
+                let _ = writeln!(front, "{}: {}", key, quote(value));
+            }
+        }
+
+        if front.is_empty() {
+            return self.to_markdown();
+        }
+        format!("---\n{}---\n\n{}", front, self.to_markdown())
+    }
+
+    /// Build the in-memory docx representation of the document. It still holds the markers for
+    /// what docx-rs can't write, so it is only handed out packaged, by `to_docx_bytes`. Font
+    /// embedding also happens when packaging, so `embed_fonts` is ignored here.
+    ///
+    /// Each list (a run of consecutive list paragraphs of one kind) gets its own numbering
+    /// instance, so every numbered list counts from 1. Lists inside table cells share the
+    /// instance of their kind
+    fn to_docx(&self, options: DocxExportOptions) -> Docx {
+        let mut document = Docx::new()
+            .default_fonts(RunFonts::new().ascii(self.default_style.font()))
+            .default_size(self.default_style.size() as usize * 2);
+
+        for kind in [ListKind::Bullet, ListKind::Number] {
+            if self
+                .all_paragraphs()
+                .any(|sp| sp.style.list().is_some_and(|item| item.kind == kind))
+            {
+                let (abstract_numbering, numbering) = kind.numbering_definition();
+                document = document
+                    .add_abstract_numbering(abstract_numbering)
+                    .add_numbering(numbering);
+            }
+        }
+
+        // Instance ids up to the last kind's are the shared ones added above
+        let mut next_numbering = ListKind::Number.numbering_id() + 1;
+        let mut list: Option<(ListKind, usize)> = None;
+        for (idx, block) in self.content.iter().enumerate() {
+            let styled_paragraph = match block {
+                Block::Paragraph(sp) => sp,
+                Block::Table(table) => {
+                    document = document
+                        .add_table(table.apply_to_raw_with_defaults(Some(&self.default_style)));
+                    list = None;
+                    continue;
+                }
+                Block::Image(image) => {
+                    document = document.add_paragraph(image.apply_to_raw());
+                    list = None;
+                    continue;
+                }
+            };
+            let annotated = self.comments_for(idx).next().is_some()
+                || self.revisions.iter().any(|r| r.paragraph == idx);
+            let mut paragraph = if annotated {
+                self.annotated_paragraph(idx, styled_paragraph)
+            } else {
+                styled_paragraph.apply_to_raw_with_defaults(Some(&self.default_style))
+            };
+            if options.preserve_empty_paragraphs && styled_paragraph.chunk_count() == 0 {
+                paragraph = paragraph.add_run(Run::new().add_text(""));
+            }
+            match styled_paragraph.style.list() {
+                Some(item) => {
+                    let id = match list {
+                        Some((kind, id)) if kind == item.kind => id,
+                        _ => {
+                            let id = next_numbering;
+                            next_numbering += 1;
+                            document = document.add_numbering(item.kind.numbering(id));
+                            list = Some((item.kind, id));
+                            id
+                        }
+                    };
+                    paragraph =
+                        paragraph.numbering(NumberingId::new(id), IndentLevel::new(item.level()));
+                }
+                None => list = None,
+            }
+            document = document.add_paragraph(paragraph);
+        }
+
+        document
+    }
+
+    /// Docx paragraph `idx` with its comment ranges and revisions marked. Chunks are split on the
+    /// range bounds so every marker falls between runs. Comment ids are their position in `comments`
+    fn annotated_paragraph(&self, idx: usize, sp: &StyledParagraph) -> Paragraph {
+        let mut sp = sp.clone();
+        let len = sp.char_len();
+        let comments: Vec<(usize, Range<usize>, &Comment)> = self
+            .comments
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.paragraph == idx)
+            .map(|(id, c)| {
+                let end = c.range.end.min(len);
+                (id, c.range.start.min(end)..end, c)
+            })
+            .collect();
+        let revisions = self.revisions.iter().filter(|r| r.paragraph == idx);
+        for range in comments
+            .iter()
+            .map(|(_, range, _)| range)
+            .chain(revisions.map(|r| &r.range))
+        {
+            sp.split_at_char(range.start);
+            sp.split_at_char(range.end);
+        }
+
+        // At each position, close the ranges ending there before opening new ones
+        let markers = |mut paragraph: Paragraph, pos: usize| {
+            for (id, range, _) in &comments {
+                if range.end == pos && range.start < pos {
+                    paragraph = paragraph.add_comment_end(*id);
+                }
+            }
+            for (id, range, comment) in &comments {
+                if range.start == pos {
+                    let raw = docx_rs::Comment::new(*id)
+                        .author(comment.author.as_str())
+                        .add_paragraph(
+                            Paragraph::new().add_run(Run::new().add_text(&comment.text)),
+                        );
+                    paragraph = paragraph.add_comment_start(raw);
+                    if range.is_empty() {
+                        paragraph = paragraph.add_comment_end(*id);
+                    }
+                }
+            }
+            paragraph
+        };
+
+        let run = |chunk: &StyledText| chunk.apply_to_raw_with_defaults(Some(&self.default_style));
+        let mut paragraph = Paragraph::new();
+        let mut pos = 0;
+        for chunk in sp.chunks() {
+            paragraph = markers(paragraph, pos);
+            let end = pos + chunk.char_count();
+            paragraph = match self.revision_at(idx, &(pos..end)) {
+                Some(revision) => revision.add_run_to(paragraph, run(chunk)),
+                None => paragraph.add_run(run(chunk)),
+            };
+            pos = end;
+        }
+        paragraph = markers(paragraph, pos);
+        sp.style.apply_to_raw(paragraph)
+    }
+
+    /// Package the document as .docx and return the raw bytes
+    pub fn to_docx_bytes(&self) -> Result<Vec<u8>, DocumentError> {
+        self.to_docx_bytes_with_options(DocxExportOptions::default())
+    }
+
+    pub fn to_docx_bytes_with_options(
+        &self,
+        options: DocxExportOptions,
+    ) -> Result<Vec<u8>, DocumentError> {
+        if options.reject_empty {
+            self.require_non_empty()?;
+        }
+        let mut xml = self.to_docx(options).build();
+        self.describe_images(&mut xml)?;
+        resolve_markers(&mut xml)?;
+        if let Some(lang) = self.metadata.language() {
+            default_language(&mut xml, lang)?;
+        }
+        if options.embed_fonts {
+            let fonts = self
+                .all_paragraphs()
+                .flat_map(|sp| sp.chunks().iter().map(|st| st.style().font()));
+            return embed::pack_with_fonts(xml, fonts);
+        }
+
+        let mut buffer = Cursor::new(Vec::new());
+        xml.pack(&mut buffer)
+            .map_err(|e| DocumentError::DocxPackaging(e.to_string()))?;
+
+        Ok(buffer.into_inner())
+    }
+
+    /// docx-rs writes every drawing with the same id and no description. Give each image a unique
+    /// id and its alt text, in document order
+    fn describe_images(&self, xml: &mut XMLDocx) -> Result<(), DocumentError> {
+        const DOC_PR: &str = r#"<wp:docPr id="1" name="Figure" />"#;
+        let images = self.content.iter().filter_map(|block| match block {
+            Block::Image(image) => Some(image),
+            _ => None,
+        });
+        edit_part(&mut xml.document, |document| {
+            let mut from = 0;
+            for (idx, image) in images.enumerate() {
+                let at = xml_patch::find(document, DOC_PR, from)?;
+                let doc_pr = format!(
+                    r#"<wp:docPr id="{}" name="Picture {}" descr="{}" />"#,
+                    idx + 1,
+                    idx + 1,
+                    escape_xml(image.alt_text().unwrap_or(""))
+                );
+                document.replace_range(at..at + DOC_PR.len(), &doc_pr);
+                from = at + doc_pr.len();
+            }
+            Ok(())
+        })
+    }
+
+    /// Write `to_html` to a file: a standalone page with the styles inlined on each element
+    pub fn save_as_html<P: AsRef<Path>>(&self, path: P) -> Result<(), DocumentError> {
+        fs::write(path, self.to_html())?;
+        self.mark_saved();
+
+        Ok(())
+    }
+
+    pub fn save_as_docx<P: AsRef<Path>>(&self, path: P) -> Result<(), DocumentError> {
+        self.save_as_docx_with_options(path, DocxExportOptions::default())
+    }
+
+    pub fn save_as_docx_with_options<P: AsRef<Path>>(
+        &self,
+        path: P,
+        options: DocxExportOptions,
+    ) -> Result<(), DocumentError> {
+        fs::write(path, self.to_docx_bytes_with_options(options)?)?;
+        self.mark_saved();
+
+        Ok(())
+    }
+}
+
+/// Swap the style markers left for properties docx-rs can't write (see `RUN_MARKER`, and
+/// paragraph shading) for the real elements
+fn resolve_markers(xml: &mut XMLDocx) -> Result<(), DocumentError> {
+    edit_part(&mut xml.document, |document| {
+        let run_marker = format!(r#"<w:rStyle w:val="{}."#, RUN_MARKER);
+        while let Some(start) = document.find(&run_marker) {
+            let names_start = start + run_marker.len();
+            let names_end = xml_patch::find(document, "\"", names_start)?;
+            let end = xml_patch::find(document, "/>", names_end)? + 2;
+            let elements: String = document[names_start..names_end]
+                .split('.')
+                .map(|name| format!("<w:{} />", name))
+                .collect();
+            document.replace_range(start..end, &elements);
+        }
+        Ok(())
+    })?;
+
+    edit_part(&mut xml.document, |document| {
+        const PARA_ID: &str = r#"w14:paraId=""#;
+        let shading = format!(".{}.", SHADING_MARKER);
+        let mut from = 0;
+        while let Some(found) = document[from..].find(PARA_ID) {
+            let id_start = from + found + PARA_ID.len();
+            let id_end = xml_patch::find(document, "\"", id_start)?;
+            from = id_end;
+            let Some((id, fill)) = document[id_start..id_end].split_once(&shading) else {
+                continue;
+            };
+            let shd = format!(
+                r#"<w:shd w:val="clear" w:color="auto" w:fill="{}" />"#,
+                fill
+            );
+            let id = id.to_string();
+            document.replace_range(id_start..id_end, &id);
+            let properties = xml_patch::find(document, "<w:pPr>", id_start)? + "<w:pPr>".len();
+            document.insert_str(properties, &shd);
+            from = properties + shd.len();
+        }
+        Ok(())
+    })
+}
+
+/// Make `lang` the language of all text, for proofing, through the run property defaults.
+/// docx-rs can't write w:lang
+fn default_language(xml: &mut XMLDocx, lang: &str) -> Result<(), DocumentError> {
+    let element = format!(r#"<w:lang w:val="{}" />"#, escape_xml(lang));
+    edit_part(&mut xml.styles, |styles| {
+        xml_patch::insert_before(styles, "</w:rPr></w:rPrDefault>", &element)
+    })
+}
+
+/// Greedy word wrap of a single line, measuring in chars. An empty line stays one empty line
+fn wrap_line(text: &str, width: usize, lines: &mut Vec<String>) {
+    let mut current = String::new();
+    let mut current_len = 0;
+    for word in text.split_whitespace() {
+        let mut chars: Vec<char> = word.chars().collect();
+        if current_len > 0 && current_len + 1 + chars.len() > width {
+            lines.push(std::mem::take(&mut current));
+            current_len = 0;
+        }
+        // Hard-break words that cannot fit on a line of their own
+        while chars.len() > width {
+            if current_len > 0 {
+                lines.push(std::mem::take(&mut current));
+                current_len = 0;
+            }
+            lines.push(chars.drain(..width).collect());
+        }
+        if current_len > 0 {
+            current.push(' ');
+            current_len += 1;
+        }
+        current.extend(&chars);
+        current_len += chars.len();
+    }
+    if current_len > 0 || text.trim().is_empty() {
+        lines.push(current);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stylemgr::palette::StylePalette;
+    use crate::stylemgr::structural::{
+        Indentation, ListItem, ListKind, ParagraphStyle, StyledParagraph,
+    };
+    use crate::stylemgr::style::UnderlineStyle;
+    use docx_rs::DocumentChild;
+
+    /// Part `name` of the docx exported from `doc`, as text
+    fn packaged_part(doc: &Document, name: &str) -> String {
+        let bytes = doc.to_docx_bytes().unwrap();
+        let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+        let mut xml = String::new();
+        io::Read::read_to_string(&mut archive.by_name(name).unwrap(), &mut xml).unwrap();
+        xml
+    }
+
+    // Helper to create a document with some content for testing
+    fn create_test_document() -> Document {
+        let mut doc = Document::new("Test Title");
+
+        let style1 = Style::new();
+        let style2 = Style::new().switch_bold();
+
+        let mut para1 = StyledParagraph::new();
+        para1.add(StyledText::new(
+            "Paragraph 1, Sentence 1. ".to_string(),
+            style1.clone(),
+        ));
+        para1.add(StyledText::new("Bold bit.".to_string(), style2.clone()));
+
+        let mut para2 = StyledParagraph::new();
+        para2.add(StyledText::new("Paragraph 2.".to_string(), style1.clone()));
+
+        doc.add_block(para1);
+        doc.add_block(para2);
+
+        doc
+    }
+
+    #[test]
+    fn test_statistics() {
+        let doc = create_test_document();
+        assert_eq!(
+            doc.statistics(),
+            DocumentStatistics {
+                paragraphs: 2,
+                words: 8,
+                characters: 46,
+                characters_no_spaces: 40,
+            }
+        );
+        assert_eq!(
+            Document::new("Empty").statistics(),
+            DocumentStatistics::default()
+        );
+    }
+
+    #[test]
+    fn test_line_column() {
+        let mut doc = Document::from_plain_text("Lines", "One\nTwo three\nFour");
+        doc.insert_text(1, 3, "\n").unwrap();
+        assert_eq!(doc.line_column(0, 0), Some((1, 1)));
+        assert_eq!(doc.line_column(0, 3), Some((1, 4)));
+        assert_eq!(doc.line_column(1, 2), Some((2, 3)));
+        assert_eq!(doc.line_column(1, 5), Some((3, 2)));
+        assert_eq!(doc.line_column(2, 0), Some((4, 1)));
+        assert_eq!(doc.line_column(2, 5), None);
+        assert_eq!(doc.line_column(3, 0), None);
+    }
+
+    #[test]
+    fn test_char_and_word_len() {
+        let mut doc = create_test_document();
+        assert_eq!(doc.char_len(), 46);
+        assert_eq!(doc.word_len(), 8);
+        assert_eq!(doc.word_len(), doc.statistics().words);
+
+        // A word split over two chunks still counts once
+        doc.paragraph_mut(1).unwrap().add(StyledText::new(
+            " more".to_string(),
+            Style::new().switch_bold(),
+        ));
+        doc.paragraph_mut(1)
+            .unwrap()
+            .add(StyledText::new("text".to_string(), Style::new()));
+        assert_eq!(doc.word_len(), 9);
+        assert_eq!(doc.char_len(), 55);
+        assert_eq!(Document::new("Empty").word_len(), 0);
+    }
+
+    #[test]
+    fn test_style_summary() {
+        let mut doc = create_test_document();
+        let summary = doc.style_summary();
+        assert_eq!(summary.min_size, Some(11));
+        assert_eq!(summary.max_size, Some(11));
+        assert_eq!(summary.fonts, HashSet::from(["Arial".to_string()]));
+        assert_eq!(summary.colors, HashSet::from(["#000000".to_string()]));
+
+        doc.paragraph_mut(1).unwrap().chunks_mut()[0].set_style(
+            Style::new()
+                .change_size(24)
+                .change_font_color("#ff0000".to_string())
+                .unwrap(),
+        );
+        doc.paragraph_mut(0).unwrap().chunks_mut()[0].set_style(Style::new().change_size(8));
+        let summary = doc.style_summary();
+        assert_eq!((summary.min_size, summary.max_size), (Some(8), Some(24)));
+        assert_eq!(summary.colors.len(), 2);
+
+        assert_eq!(
+            Document::new("Empty").style_summary(),
+            StyleSummary::default()
+        );
+    }
+
+    #[test]
+    fn test_set_paragraph() {
+        let mut doc = create_test_document();
+        let mut replacement = StyledParagraph::new();
+        replacement.add(StyledText::new("Rebuilt.".to_string(), Style::new()));
+
+        let old = doc.set_paragraph(1, replacement.clone()).unwrap();
+        assert_eq!(old.text(), "Paragraph 2.");
+        assert_eq!(*doc.paragraph(1).unwrap(), replacement);
+        assert_eq!(doc.paragraph_count(), 2);
+
+        assert!(matches!(
+            doc.set_paragraph(2, replacement),
+            Err(DocumentError::IndexOutOfBounds(2))
+        ));
+    }
+
+    #[test]
+    fn test_to_markdown() {
+        let mut doc = create_test_document();
+        let mut item = StyledParagraph::new();
+        item.add(StyledText::new("Item".to_string(), Style::new()));
+        item.style = ParagraphStyle::new().set_list(Some(ListItem::new(ListKind::Bullet, 1)));
+        doc.add_block(item.clone());
+        doc.add_block(item);
+        assert_eq!(
+            doc.to_markdown(),
+            "Paragraph 1, Sentence 1. **Bold bit.**\n\nParagraph 2.\n\n    - Item\n    - Item\n"
+        );
+    }
+
+    #[test]
+    fn test_docx_roundtrip() {
+        let doc = create_test_document();
+        let loaded = Document::from_docx_bytes("Loaded", &doc.to_docx_bytes().unwrap()).unwrap();
+        assert_eq!(loaded.get_text(false), doc.get_text(false));
+        let bold = &loaded.paragraph(0).unwrap().chunks()[1];
+        assert_eq!(bold.text, "Bold bit.");
+        assert!(bold.style().bold());
+    }
+
+    #[test]
+    fn test_docx_underline_kinds() {
+        let run = |kind: &str| Paragraph::new().add_run(Run::new().add_text("u").underline(kind));
+        let mut bytes = Cursor::new(Vec::new());
+        Docx::new()
+            .add_paragraph(run("wave"))
+            .add_paragraph(run("thickDash"))
+            .add_paragraph(run("none"))
+            .add_paragraph(run("DOUBLE"))
+            .build()
+            .pack(&mut bytes)
+            .unwrap();
+        let loaded = Document::from_docx_bytes("Underlines", bytes.get_ref()).unwrap();
+        let underline = |para| {
+            loaded.paragraph(para).unwrap().chunks()[0]
+                .style()
+                .underline()
+                .cloned()
+        };
+        assert_eq!(underline(0), Some(UnderlineStyle::Wave));
+        // Kinds the model doesn't know keep an underline
+        assert_eq!(underline(1), Some(UnderlineStyle::Single));
+        assert_eq!(underline(2), None);
+        assert_eq!(underline(3), Some(UnderlineStyle::Double));
+    }
+
+    #[test]
+    fn test_load_non_docx() {
+        let result = Document::from_docx_bytes("Notes", b"just some plain text\n");
+        assert!(matches!(result, Err(DocumentError::DocxRead(_))));
+
+        let path = std::env::temp_dir().join("edda_not_a_docx.docx");
+        fs::write(&path, "plain text").unwrap();
+        let result = Document::load_from_docx(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(DocumentError::DocxRead(_))));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_bytes_roundtrip() {
+        use crate::stylemgr::image::tests::PNG;
+
+        let mut doc = create_test_document();
+        doc.add_comment(Comment::new("Ada", "Check", 1, 0..4))
+            .unwrap();
+        doc.add_block(Image::new(PNG.to_vec()).unwrap());
+        doc.track_changes(Some("Ada".to_string()));
+        doc.insert_text(1, 0, "New ").unwrap();
+        assert_eq!(doc.revisions().len(), 1);
+
+        let restored = Document::from_bytes(&doc.to_bytes().unwrap()).unwrap();
+        assert_eq!(restored, doc);
+        assert!(matches!(
+            Document::from_bytes(b"{\"content\": 3}"),
+            Err(DocumentError::Json(_))
+        ));
+
+        // Comments are checked against their paragraph like in add_comment
+        let json = String::from_utf8(doc.to_bytes().unwrap()).unwrap();
+        let out_of_range = json.replace(
+            r#""range":{"start":4,"end":8}"#,
+            r#""range":{"start":4,"end":400}"#,
+        );
+        assert_ne!(out_of_range, json);
+        assert!(Document::from_bytes(out_of_range.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_docx_default_style() {
+        let mut doc = Document::from_plain_text("Defaults", "Plain text\nMore plain text");
+        let xml = doc.to_docx(DocxExportOptions::default()).build();
+        let document = String::from_utf8(xml.document).unwrap();
+        assert!(!document.contains("<w:sz "));
+        assert!(!document.contains("<w:rFonts "));
+        let styles = String::from_utf8(xml.styles).unwrap();
+        assert!(styles.contains(r#"<w:sz w:val="22" />"#));
+        assert!(styles.contains(r#"<w:rFonts w:ascii="Arial" />"#));
+
+        // Chunks that differ from the default keep their own properties
+        doc.set_default_style(Style::new().change_size(14));
+        let document =
+            String::from_utf8(doc.to_docx(DocxExportOptions::default()).build().document).unwrap();
+        assert_eq!(document.matches(r#"<w:sz w:val="22" />"#).count(), 2);
+    }
+
+    #[test]
+    fn test_empty_paragraph_roundtrip() {
+        let mut doc = Document::from_plain_text("Blank", "First");
+        doc.add_block(StyledParagraph::new());
+        let mut second = StyledParagraph::new();
+        second.add(StyledText::new("Second".to_string(), Style::new()));
+        doc.add_block(second);
+
+        let xml =
+            String::from_utf8(doc.to_docx(DocxExportOptions::default()).build().document).unwrap();
+        assert_eq!(xml.matches("<w:r>").count(), 3);
+        let options = DocxExportOptions {
+            preserve_empty_paragraphs: false,
+            ..Default::default()
+        };
+        let xml = String::from_utf8(doc.to_docx(options).build().document).unwrap();
+        assert_eq!(xml.matches("<w:r>").count(), 2);
+
+        let loaded = Document::from_docx_bytes("Blank", &doc.to_docx_bytes().unwrap()).unwrap();
+        assert_eq!(loaded.paragraph_count(), 3);
+        assert!(loaded.paragraph(1).unwrap().is_empty());
+        assert_eq!(loaded.paragraph(2).unwrap().text(), "Second");
+    }
+
+    #[test]
+    fn test_insert_page_break() {
+        let mut doc = create_test_document();
+        doc.add_comment(Comment::new("Ada", "Check", 1, 0..4))
+            .unwrap();
+        doc.insert_page_break(0).unwrap();
+
+        assert_eq!(doc.paragraph_count(), 3);
+        let page_break = doc.paragraph(1).unwrap();
+        assert!(page_break.is_empty());
+        assert!(page_break.style.page_break_before());
+        assert_eq!(doc.paragraph(2).unwrap().text(), "Paragraph 2.");
+        assert_eq!(doc.comments()[0].paragraph, 2);
+        let docx = doc.to_docx(DocxExportOptions::default()).json();
+        assert!(docx.contains(r#""pageBreakBefore": true"#));
+
+        assert!(matches!(
+            doc.insert_page_break(3),
+            Err(DocumentError::IndexOutOfBounds(3))
+        ));
+        assert_eq!(doc.paragraph_count(), 3);
+    }
+
+    #[test]
+    fn test_paragraph_shading_docx() {
+        let mut doc = create_test_document();
+        doc.paragraph_mut(1).unwrap().style = ParagraphStyle::new()
+            .set_shading(Some("#fff2cc80".to_string()))
+            .unwrap();
+        let xml = packaged_part(&doc, "word/document.xml");
+        assert_eq!(
+            xml.matches(r#"<w:shd w:val="clear" w:color="auto" w:fill="fff2cc" />"#)
+                .count(),
+            1
+        );
+        assert!(!xml.contains(SHADING_MARKER));
+        assert!(
+            doc.to_html()
+                .contains("<p style=\"background-color:rgba(255,242,204,0.50)\">")
+        );
+    }
+
+    #[test]
+    fn test_double_strike_docx() {
+        use crate::stylemgr::style::StrikeStyle;
+
+        let mut doc = create_test_document();
+        doc.paragraph_mut(1).unwrap().chunks_mut()[0]
+            .set_style(Style::new().set_strike(Some(StrikeStyle::Double)));
+        let xml = packaged_part(&doc, "word/document.xml");
+        assert_eq!(xml.matches("<w:dstrike />").count(), 1);
+        assert!(!xml.contains(RUN_MARKER));
+    }
+
+    #[test]
+    fn test_small_caps_docx() {
+        use crate::stylemgr::style::{CapsStyle, StrikeStyle};
+
+        let mut doc = create_test_document();
+        let small_caps = Style::new().set_caps(Some(CapsStyle::SmallCaps));
+        doc.paragraph_mut(1).unwrap().chunks_mut()[0].set_style(small_caps.clone());
+        let mut sp = StyledParagraph::new();
+        sp.add(StyledText::new(
+            "Both".to_string(),
+            small_caps.set_strike(Some(StrikeStyle::Double)),
+        ));
+        doc.add_block(sp);
+
+        let xml = packaged_part(&doc, "word/document.xml");
+        assert_eq!(xml.matches("<w:smallCaps />").count(), 2);
+        assert!(xml.contains("<w:smallCaps /><w:dstrike />"));
+        assert!(!xml.contains("<w:caps />"));
+        assert!(!xml.contains(RUN_MARKER));
+    }
+
+    #[test]
+    fn test_split_by() {
+        let paragraph = |text: &str, style: Style| {
+            let mut sp = StyledParagraph::new();
+            sp.add(StyledText::new(text.to_string(), style));
+            sp
+        };
+        let heading = Style::new().switch_bold().change_size(20);
+        let mut doc = Document::new("Novel");
+        doc.add_block(paragraph("Foreword", Style::new()));
+        doc.add_block(paragraph("Chapter 1", heading.clone()));
+        doc.add_block(paragraph("It begins.", Style::new()));
+        doc.add_block(paragraph("Chapter 2", heading.clone()));
+        doc.add_block(paragraph("It goes on.", Style::new()));
+        doc.add_block(paragraph("And ends.", Style::new()));
+        doc.add_comment(Comment::new("Ada", "Tense?", 4, 3..7))
+            .unwrap();
+
+        let is_heading = |sp: &StyledParagraph| sp.chunks().iter().all(|st| st.style() == &heading);
+        let sections = doc.split_by(is_heading);
+        let texts = |d: &Document| d.blocks().iter().map(Block::text).collect::<Vec<_>>();
+        assert_eq!(sections.len(), 3);
+        assert_eq!(sections[0].get_metadata().title(), "Novel");
+        assert_eq!(texts(&sections[0]), ["Foreword"]);
+        assert_eq!(sections[1].get_metadata().title(), "Chapter 1");
+        assert_eq!(texts(&sections[1]), ["Chapter 1", "It begins."]);
+        assert_eq!(sections[2].get_metadata().title(), "Chapter 2");
+        assert_eq!(
+            texts(&sections[2]),
+            ["Chapter 2", "It goes on.", "And ends."]
+        );
+        assert!(sections[1].comments().is_empty());
+        assert_eq!(sections[2].comments()[0].paragraph, 1);
+
+        // Starting right at a heading gives no leading section
+        doc.content.remove(0);
+        assert_eq!(doc.split_by(is_heading).len(), 2);
+        assert!(Document::new("Empty").split_by(is_heading).is_empty());
+    }
+
+    #[test]
+    fn test_to_indented_text() {
+        let mut doc = create_test_document();
+        doc.paragraph_mut(1).unwrap().style = ParagraphStyle::new().set_indentation(Indentation {
+            left: 1440,
+            right: 0,
+            first_line: 0,
+        });
+        assert_eq!(
+            doc.to_indented_text(4),
+            "Paragraph 1, Sentence 1. Bold bit.\n        Paragraph 2.\n"
+        );
+        assert!(doc.to_indented_text(0).ends_with("\n\t\tParagraph 2.\n"));
+    }
+
+    #[test]
+    fn test_estimated_page_count() {
+        assert_eq!(
+            Document::new("Empty").estimated_page_count(DEFAULT_CHARS_PER_PAGE),
+            0
+        );
+        let doc = create_test_document();
+        assert_eq!(doc.estimated_page_count(DEFAULT_CHARS_PER_PAGE), 1);
+
+        let long = Document::from_plain_text("Long", &"a".repeat(DEFAULT_CHARS_PER_PAGE * 3 + 1));
+        assert_eq!(long.estimated_page_count(DEFAULT_CHARS_PER_PAGE), 4);
+        assert_eq!(long.estimated_page_count(0), DEFAULT_CHARS_PER_PAGE * 3 + 1);
+    }
+
+    #[test]
+    fn test_missing_fonts() {
+        // Depends on the fonts installed on the system running the tests
+        let Some(installed) = ["DejaVu Sans", "Liberation Sans", "Arial", "Times New Roman"]
+            .into_iter()
+            .find_map(|font| Style::new().change_font(font.to_string()).ok())
+        else {
+            println!("Test skipped: no common font installed.");
+            return;
+        };
+        // Parsing a tag doesn't check the font, so it can name one that doesn't exist
+        let bogus: Style = "font(DefinitelyNotAFontName123)".parse().unwrap();
+
+        let mut doc = Document::new("Fonts");
+        let mut sp = StyledParagraph::new();
+        sp.add(StyledText::new("Real ".to_string(), installed.clone()));
+        sp.add(StyledText::new("bogus ".to_string(), bogus.clone()));
+        doc.add_block(sp);
+        let mut sp = StyledParagraph::new();
+        sp.add(StyledText::new("again".to_string(), bogus.switch_bold()));
+        sp.add(StyledText::new(" real".to_string(), installed));
+        doc.add_block(sp);
+
+        assert_eq!(doc.missing_fonts(), ["DefinitelyNotAFontName123"]);
+        assert!(Document::new("Empty").missing_fonts().is_empty());
+    }
+
+    #[test]
+    fn test_from_plain_text_line_endings() {
+        let paragraphs = |doc: &Document| doc.content.iter().map(Block::text).collect::<Vec<_>>();
+
+        let doc = Document::from_plain_text("CRLF", "First\r\nSecond\r\n\r\nFourth\r\n");
+        assert_eq!(paragraphs(&doc), ["First", "Second", "", "Fourth"]);
+
+        let doc = Document::from_plain_text("Mixed", "One\rTwo\nThree\r\n\rFive");
+        assert_eq!(paragraphs(&doc), ["One", "Two", "Three", "", "Five"]);
+        assert!(!doc.get_text(false).contains('\r'));
+
+        assert_eq!(Document::from_plain_text("Empty", "").paragraph_count(), 0);
+    }
+
+    #[test]
+    fn test_word_at() {
+        let mut doc = Document::from_plain_text("Words", "Déjà vu, isn't it");
+        doc.add_block(Table::new());
+        assert_eq!(doc.word_at(0, 1), Some((0, 4)));
+        assert_eq!(doc.word_at(0, 7), Some((7, 8)));
+        assert_eq!(doc.word_at(0, 9), Some((9, 14)));
+        assert_eq!(doc.word_at(0, 16), Some((15, 17)));
+        assert_eq!(doc.word_at(0, 4), None);
+        assert_eq!(doc.word_at(0, 17), None);
+        assert_eq!(doc.word_at(1, 0), None);
+        assert_eq!(doc.word_at(5, 0), None);
+    }
+
+    #[test]
+    fn test_chunks_with_offsets() {
+        let mut doc = create_test_document();
+        doc.add_block(Table::new());
+        doc.add_block(StyledParagraph::new());
+        let offsets: Vec<(usize, usize, &str)> = doc
+            .chunks_with_offsets()
+            .map(|(para, offset, st)| (para, offset, st.text.as_str()))
+            .collect();
+        assert_eq!(
+            offsets,
+            [
+                (0, 0, "Paragraph 1, Sentence 1. "),
+                (0, 25, "Bold bit."),
+                (1, 0, "Paragraph 2."),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_heading_navigation() {
+        let palette = StylePalette::default();
+        let mut doc = create_test_document();
+        for (name, text) in [
+            ("Heading 1", "Part"),
+            ("Body", "Text"),
+            ("Heading 2", "Section"),
+        ] {
+            let mut sp = StyledParagraph::new();
+            sp.add(StyledText::new(text.to_string(), Style::new()));
+            palette.apply_named(&mut sp, name).unwrap();
+            doc.add_block(sp);
+        }
+        // A bold paragraph that isn't sized like a heading doesn't count
+        let mut bold = StyledParagraph::new();
+        bold.add(StyledText::new(
+            "Loud".to_string(),
+            Style::new().switch_bold(),
+        ));
+        doc.add_block(bold);
+
+        let is_heading = |sp: &StyledParagraph| palette.is_heading(sp);
+        assert_eq!(doc.headings(is_heading), [2, 4]);
+        assert_eq!(doc.next_heading(0, is_heading), Some(2));
+        assert_eq!(doc.next_heading(2, is_heading), Some(4));
+        assert_eq!(doc.next_heading(4, is_heading), None);
+        assert_eq!(doc.next_heading(usize::MAX, is_heading), None);
+        assert_eq!(doc.prev_heading(5, is_heading), Some(4));
+        assert_eq!(doc.prev_heading(4, is_heading), Some(2));
+        assert_eq!(doc.prev_heading(2, is_heading), None);
+        assert_eq!(doc.prev_heading(99, is_heading), Some(4));
+        assert_eq!(Document::new("Empty").next_heading(0, is_heading), None);
+
+        // A palette of its own decides what a heading is
+        let custom = StylePalette::new().define("Heading", Style::new().switch_bold(), None);
+        assert_eq!(doc.headings(|sp| custom.is_heading(sp)), [5]);
+    }
+
+    #[test]
+    fn test_append_run() {
+        let mut doc = Document::new("Stream");
+        doc.append_run("Hello", Style::new());
+        assert_eq!(doc.paragraph_count(), 1);
+        assert_eq!(doc.blocks()[0].text(), "Hello");
+
+        let mut doc = create_test_document();
+        doc.append_run(" More.", Style::new().switch_italic());
+        assert_eq!(doc.paragraph_count(), 2);
+        let last = doc.blocks()[1].as_paragraph().unwrap();
+        assert_eq!(last.text(), "Paragraph 2. More.");
+        assert!(last.chunks()[1].style().italic());
+
+        doc.add_block(Table::new());
+        doc.append_run("After", Style::new());
+        assert_eq!(doc.blocks().len(), 4);
+        assert_eq!(doc.blocks()[3].text(), "After");
+    }
+
+    #[test]
+    fn test_style_at() {
+        let mut doc = create_test_document();
+        let bold = Style::new().switch_bold();
+        // "Paragraph 1, Sentence 1. " is 25 characters, followed by the bold "Bold bit."
+        assert_eq!(doc.style_at(0, 0), Some(Style::new()));
+        assert_eq!(doc.style_at(0, 24), Some(Style::new()));
+        assert_eq!(doc.style_at(0, 25), Some(bold.clone()));
+        assert_eq!(doc.style_at(0, 34), Some(bold));
+        assert_eq!(doc.style_at(0, 35), None);
+        assert_eq!(doc.style_at(5, 0), None);
+
+        doc.add_block(Table::new());
+        doc.add_block(StyledParagraph::new());
+        assert_eq!(doc.style_at(2, 0), None);
+        assert_eq!(doc.style_at(3, 0), None);
+    }
+
+    #[test]
+    fn test_selection_as_tagged() {
+        let mut doc = create_test_document();
+        let mut sp = StyledParagraph::new();
+        sp.add(StyledText::new("Ünï ".to_string(), Style::new()));
+        sp.add(StyledText::new(
+            "cödé".to_string(),
+            Style::new().switch_italic(),
+        ));
+        doc.add_block(sp);
+
+        // "1. Bold" spans the plain chunk and the bold one
+        let tagged = doc.selection_as_tagged(0, 22, 29).unwrap();
+        let bold = Style::new().switch_bold().to_tag();
+        assert_eq!(tagged, format!("1. [[{}]]Bold[[/{}]]", bold, bold));
+        let parsed = StyledParagraph::from_tagged_text(&tagged).unwrap();
+        let mut expected = StyledParagraph::new();
+        expected.add(StyledText::new("1. ".to_string(), Style::new()));
+        expected.add(StyledText::new(
+            "Bold".to_string(),
+            Style::new().switch_bold(),
+        ));
+        assert_eq!(parsed.chunks(), expected.chunks());
+
+        // Offsets are characters, not bytes
+        let tagged = doc.selection_as_tagged(2, 2, 6).unwrap();
+        let parsed = StyledParagraph::from_tagged_text(&tagged).unwrap();
+        assert_eq!(parsed.text(), "ï cö");
+        assert!(parsed.chunks()[1].style().italic());
+
+        assert_eq!(doc.selection_as_tagged(1, 3, 3).unwrap(), "");
+        assert!(matches!(
+            doc.selection_as_tagged(1, 5, 20),
+            Err(DocumentError::Paragraph(
+                ParagraphModifyError::InvalidRange(5, 20, 12)
+            ))
+        ));
+        assert!(matches!(
+            doc.selection_as_tagged(9, 0, 1),
+            Err(DocumentError::IndexOutOfBounds(9))
+        ));
+    }
+
+    #[test]
+    fn test_to_wrapped_text() {
+        let mut doc = Document::new("Wrap");
+        let mut sp = StyledParagraph::new();
+        sp.add(StyledText::new(
+            "The quick brown fox jumps over the lazy dog".to_string(),
+            Style::new(),
+        ));
+        doc.add_block(sp);
+        doc.add_block(StyledParagraph::new());
+        let mut sp = StyledParagraph::new();
+        sp.add(StyledText::new(
+            "Ünïcödé wörds and extraordinarily".to_string(),
+            Style::new(),
+        ));
+        doc.add_block(sp);
+
+        let wrapped = doc.to_wrapped_text(10);
+        assert_eq!(
+            wrapped,
+            "The quick\nbrown fox\njumps over\nthe lazy\ndog\n\nÜnïcödé\nwörds and\nextraordin\narily\n"
+        );
+        assert!(wrapped.lines().all(|l| l.chars().count() <= 10));
+        // Apart from the hard-broken word, every word survives intact
+        let words: Vec<&str> = wrapped.split_whitespace().collect();
+        assert_eq!(
+            words[..11],
+            [
+                "The",
+                "quick",
+                "brown",
+                "fox",
+                "jumps",
+                "over",
+                "the",
+                "lazy",
+                "dog",
+                "Ünïcödé",
+                "wörds"
+            ]
+        );
+        assert_eq!(Document::new("Empty").to_wrapped_text(10), "");
+    }
+
+    #[test]
+    fn test_to_ansi() {
+        let doc = create_test_document();
+        assert_eq!(
+            doc.to_ansi(),
+            "Paragraph 1, Sentence 1. \x1b[1mBold bit.\x1b[0m\nParagraph 2.\n"
+        );
+        assert_eq!(Document::new("Empty").to_ansi(), "");
+    }
+
+    #[cfg(feature = "latex")]
+    #[test]
+    fn test_to_latex() {
+        let mut doc = Document::from_plain_text("LaTeX", "100% done\nNext");
+        let mut cell = StyledParagraph::new();
+        cell.add(StyledText::new("A&B".to_string(), Style::new()));
+        cell.add(StyledText::line_break(Style::new()));
+        cell.add(StyledText::new("C".to_string(), Style::new()));
+        doc.add_block(Table::new().add_row(vec![vec![cell], vec![]]));
+        let column = r"p{\dimexpr\linewidth/2-2\tabcolsep\relax}|";
+        assert_eq!(
+            doc.to_latex(),
+            format!(
+                "100\\% done\n\nNext\n\n\\begin{{tabular}}{{|{0}{0}}}\n\\hline\nA\\&B\\newline\nC &  \\\\ \\hline\n\\end{{tabular}}\n",
+                column
+            )
+        );
+        // The only row ends are the table's own
+        assert_eq!(doc.to_latex().matches("\\\\").count(), 1);
+        assert_eq!(Document::new("Empty").to_latex(), "");
+    }
+
+    #[test]
+    fn test_markdown_frontmatter() {
+        let mut doc = create_test_document();
+        doc.get_metadata_mut().add_author("Ann");
+        doc.get_metadata_mut().add_author("Bob \"B\"");
+        let md = doc.to_markdown_with_frontmatter();
+        assert!(md.starts_with(
+            "---\ntitle: \"Test Title\"\nauthors:\n  - \"Ann\"\n  - \"Bob \\\"B\\\"\"\n---\n\n"
+        ));
+        assert!(md.ends_with(&doc.to_markdown()));
+        assert!(!md.contains("keywords"));
+
+        let mut bare = create_test_document();
+        bare.metadata = Metadata::default();
+        assert_eq!(bare.to_markdown_with_frontmatter(), bare.to_markdown());
+    }
+
+    #[test]
+    fn test_search_across_paragraphs() {
+        let doc = create_test_document();
+        let found = doc.search("Paragraph", true);
+        assert_eq!(
+            found,
+            vec![
+                DocumentMatch {
+                    paragraph: 0,
+                    cell: None,
+                    start_char: 0,
+                    end_char: 9
+                },
+                DocumentMatch {
+                    paragraph: 1,
+                    cell: None,
+                    start_char: 0,
+                    end_char: 9
+                },
+            ]
+        );
+
+        // Matches are not limited to a single chunk: "1. bold" spans the plain and bold chunks
+        assert_eq!(doc.search("BIT", true), vec![]);
+        assert_eq!(doc.search("BIT", false).len(), 1);
+        assert_eq!(
+            doc.search("1. bold", false),
+            vec![DocumentMatch {
+                paragraph: 0,
+                cell: None,
+                start_char: 22,
+                end_char: 29
+            }]
+        );
+        assert_eq!(doc.search("p", false).len(), 4);
+        assert!(doc.search("", false).is_empty());
+    }
+
+    #[cfg(feature = "html")]
+    #[test]
+    fn test_from_html() {
+        let html = "<p>Plain <strong>bold</strong> and <span style=\"color:#ff0000;font-size:14pt\">red</span></p>\n\
+                    <p>Second<br>line &amp; <blink>more</blink></p>";
+        let doc = Document::from_html("Imported", html).unwrap();
+        assert_eq!(doc.get_metadata().title(), "Imported");
+        assert_eq!(doc.paragraph_count(), 2);
+
+        let first = doc.paragraph(0).unwrap().chunks();
+        assert_eq!(first.len(), 4);
+        assert_eq!(
+            first[0],
+            StyledText::new("Plain ".to_string(), Style::new())
+        );
+        assert_eq!(
+            first[1],
+            StyledText::new("bold".to_string(), Style::new().switch_bold())
+        );
+        assert_eq!(first[3].text, "red");
+        assert_eq!(first[3].style().font_color(), "#ff0000");
+        assert_eq!(first[3].style().size(), 14);
+        assert_eq!(doc.paragraph(1).unwrap().text(), "Second\nline & more");
+
+        assert!(matches!(
+            Document::from_html("Broken", "<p>text</p><span"),
+            Err(DocumentError::Html(_))
+        ));
+    }
+
+    #[cfg(feature = "html")]
+    #[test]
+    fn test_from_html_roundtrip() {
+        let mut doc = create_test_document();
+        let mut para = StyledParagraph::new();
+        para.add(StyledText::new(
+            "Highlighted <tag>".to_string(),
+            Style::new()
+                .switch_italic()
+                .change_font_highlight(Some("#ffff0080".to_string()))
+                .unwrap(),
+        ));
+        doc.add_block(para);
+        let imported = Document::from_html("Test Title", &doc.to_html()).unwrap();
+        assert_eq!(imported.content, doc.content);
+    }
 
-pub struct Document {
-    content: Vec<StyledParagraph>,
-    metadata: Metadata,
-}
+    #[test]
+    fn test_special_characters() {
+        let mut doc =
+            Document::from_plain_text("Typography", "10\u{a0}km — \"about\" 'six' miles…");
+        assert!(doc.to_html().contains("<p>10&nbsp;km — &quot;about&quot;"));
 
-#[allow(dead_code)]
-#[derive(Default, Debug)]
-pub struct Metadata {
-    title: String,
-    authors: Option<Vec<String>>,
-    description: Option<String>,
-    category: Option<String>,
-    version: Option<String>,
-    status: Option<String>,
-    language: Option<String>,
-    keywords: Option<Vec<String>>,
-}
+        let bytes = doc.to_docx_bytes().unwrap();
+        let loaded = Document::from_docx_bytes("Typography", &bytes).unwrap();
+        assert_eq!(loaded.get_text(false), doc.get_text(false));
 
-impl Document {
-    /// Create a blank document
-    pub fn new(title: &str) -> Self {
-        Self {
-            content: Vec::new(),
-            metadata: Metadata {
-                title: title.into(),
-                ..Default::default()
-            },
-        }
+        assert_eq!(doc.apply_smart_quotes(), 4);
+        assert_eq!(doc.get_text(false), "10\u{a0}km — “about” ‘six’ miles…");
     }
 
-    pub fn get_metadata(&self) -> &Metadata {
-        &self.metadata
+    #[cfg(feature = "html")]
+    #[test]
+    fn test_html_special_characters() {
+        let doc = Document::from_plain_text("Typography", "10\u{a0}km — “quoted”");
+        let imported = Document::from_html("Typography", &doc.to_html()).unwrap();
+        assert_eq!(imported.get_text(false), "10\u{a0}km — “quoted”");
+
+        let html = "<p>A&nbsp;&mdash;&ndash;&hellip;&lsquo;&rsquo;&ldquo;&rdquo;</p>";
+        let imported = Document::from_html("Entities", html).unwrap();
+        assert_eq!(imported.get_text(false), "A\u{a0}—–…‘’“”");
     }
-    /// Get full document as string
-    pub fn get_text(&self, tagged: bool) -> String {
-        let mut buffer = String::with_capacity(self.content.len() * 100);
 
-        for sp in &self.content {
-            for x in &sp.raw {
-                if tagged {
-                    let _ = write!(buffer, "{}", x.clone().apply_style_tagging());
-                } else {
-                    buffer.push_str(&x.text);
-                }
+    #[test]
+    fn test_sanitize() {
+        let mut doc = create_test_document();
+        doc.paragraph_mut(0).unwrap().chunks_mut()[1].set_style(Style::new().change_size(250));
+        doc.paragraph_mut(1).unwrap().chunks_mut()[0].set_style(Style::new().change_size(2));
+        let limits = SanitizeLimits {
+            max_chunk_chars: Some(10),
+            ..Default::default()
+        };
+
+        assert_eq!(doc.sanitize(limits), 3);
+        let chunks: Vec<(&str, u8)> = doc
+            .view()
+            .chunks()
+            .map(|(_, st)| (st.text.as_str(), st.style().size()))
+            .collect();
+        assert_eq!(
+            chunks,
+            [("Paragraph ", 11), ("Bold bit.", 96), ("Paragraph ", 6)]
+        );
+        assert_eq!(doc.sanitize(limits), 0);
+    }
+
+    #[test]
+    fn test_sanitize_fonts_and_comments() {
+        let mut doc = Document::new("Imported");
+        let hostile = Style::new().set_font_unchecked("x\"><script>");
+        doc.add_block(StyledParagraph::from_chunks(vec![
+            StyledText::new("0123456789abc".to_string(), hostile),
+            StyledText::new("XYZ".to_string(), Style::new().switch_bold()),
+        ]));
+        doc.add_comment(Comment::new("Ann", "On XYZ", 0, 13..16))
+            .unwrap();
+        doc.add_comment(Comment::new("Ann", "On abc", 0, 10..13))
+            .unwrap();
+        doc.add_comment(Comment::new("Ann", "Across", 0, 8..14))
+            .unwrap();
+        let limits = SanitizeLimits {
+            max_chunk_chars: Some(10),
+            ..Default::default()
+        };
+
+        assert_eq!(doc.sanitize(limits), 1);
+        assert_eq!(doc.style_at(0, 0).unwrap().font(), Style::new().font());
+        assert_eq!(doc.paragraph(0).unwrap().text(), "0123456789XYZ");
+        let comments: Vec<(&str, Range<usize>)> = doc
+            .comments()
+            .iter()
+            .map(|c| (c.text.as_str(), c.range.clone()))
+            .collect();
+        assert_eq!(comments, [("On XYZ", 10..13), ("Across", 8..11)]);
+    }
+
+    #[test]
+    fn test_map_styles() {
+        let mut doc = create_test_document();
+        let sizes: Vec<u8> = doc
+            .view()
+            .chunks()
+            .map(|(_, st)| st.style().size())
+            .collect();
+
+        doc.map_styles(|style| *style = style.clone().change_size(style.size() + 2));
+        doc.map_styles(|style| {
+            if style.font_color() == "#000000" {
+                *style = style
+                    .clone()
+                    .change_font_color("#333333".to_string())
+                    .unwrap();
             }
+        });
+
+        let chunks: Vec<&StyledText> = doc.view().chunks().map(|(_, st)| st).collect();
+        assert_eq!(chunks.len(), sizes.len());
+        for (chunk, size) in chunks.iter().zip(sizes) {
+            assert_eq!(chunk.style().size(), size + 2);
+            assert_eq!(chunk.style().font_color(), "#333333");
         }
-        buffer
     }
 
-    pub fn save_as_docx<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
-        let mut document = Docx::new();
+    #[test]
+    fn test_strip_formatting() {
+        let mut doc = create_test_document();
+        let text = doc.get_text(false);
+        doc.strip_formatting();
+        for sp in doc.view().paragraphs() {
+            assert_eq!(sp.chunk_count(), 1);
+            assert_eq!(sp.chunks()[0].style(), &Style::new());
+        }
+        assert_eq!(
+            doc.paragraph(0).unwrap().text(),
+            "Paragraph 1, Sentence 1. Bold bit."
+        );
+        assert_eq!(doc.get_text(false), text);
+    }
+
+    #[test]
+    fn test_document_view() {
+        let doc = create_test_document();
+        let view = doc.view();
+        assert_eq!(view.metadata().title(), "Test Title");
+        assert_eq!(view.paragraph_count(), 2);
+        assert_eq!(view.paragraph(1).unwrap().text(), "Paragraph 2.");
+        assert!(view.paragraph(2).is_none());
+
+        let text: String = view.chunks().map(|(_, st)| st.text.as_str()).collect();
+        assert_eq!(text, doc.get_text(false));
+        let owners: Vec<usize> = view.chunks().map(|(idx, _)| idx).collect();
+        assert_eq!(owners, vec![0, 0, 1]);
+        assert_eq!(
+            view.paragraphs()
+                .map(StyledParagraph::text)
+                .collect::<String>(),
+            text
+        );
+    }
 
-        for styled_paragraph in &self.content {
-            let mut docx_paragraph = Paragraph::new();
+    #[test]
+    fn test_table_block() {
+        let cell = |text: &str| {
+            let mut sp = StyledParagraph::new();
+            sp.add(StyledText::new(text.to_string(), Style::new()));
+            vec![sp]
+        };
+        let table = Table::new()
+            .add_row(vec![cell("A1"), cell("B1")])
+            .add_row(vec![cell("A2"), cell("B2")]);
 
-            for styled_text in &styled_paragraph.raw {
-                let run = styled_text.apply_to_raw();
-                docx_paragraph = docx_paragraph.add_run(run);
-            }
+        let mut doc = create_test_document();
+        doc.add_block(table);
+        assert_eq!(doc.block_count(), 3);
+        assert_eq!(doc.paragraph_count(), 6);
+        assert_eq!(doc.statistics().paragraphs, doc.paragraph_count());
+        assert_eq!(doc.word_len(), 12);
+        assert!(doc.view().paragraph(2).is_none());
+        assert!(matches!(
+            doc.insert_text(2, 0, "x"),
+            Err(DocumentError::NotAParagraph(2))
+        ));
+        assert_eq!(
+            doc.search("b2", false),
+            [DocumentMatch {
+                paragraph: 2,
+                cell: Some(CellPosition {
+                    row: 1,
+                    column: 1,
+                    paragraph: 0
+                }),
+                start_char: 0,
+                end_char: 2
+            }]
+        );
 
-            document = document.add_paragraph(docx_paragraph);
+        let xml =
+            String::from_utf8(doc.to_docx(DocxExportOptions::default()).build().document).unwrap();
+        assert_eq!(xml.matches("<w:tbl>").count(), 1);
+        assert_eq!(xml.matches("<w:tc>").count(), 4);
+        for text in ["A1", "B1", "A2", "B2"] {
+            assert!(xml.contains(text));
         }
+        assert!(doc.to_docx_bytes().is_ok());
 
-        let mut file = File::create(path)?;
-        document.build().pack(&mut file)?;
+        assert!(doc.to_html().contains("<table>\n<tr><td><p>A1</p></td>"));
+        assert!(
+            doc.to_markdown()
+                .ends_with("| A1 | B1 |\n| --- | --- |\n| A2 | B2 |\n")
+        );
+    }
 
-        Ok(())
+    #[test]
+    fn test_image_block() {
+        use crate::stylemgr::image::tests::PNG;
+
+        let image = Image::new(PNG.to_vec())
+            .unwrap()
+            .set_alt_text(Some("Red & black".to_string()));
+        let mut doc = create_test_document();
+        doc.add_block(image);
+        assert_eq!(doc.block_count(), 3);
+        assert_eq!(doc.paragraph_count(), 2);
+        assert_eq!(doc.statistics().paragraphs, 2);
+
+        let bytes = doc.to_docx_bytes().unwrap();
+        let archive = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+        let media = archive
+            .file_names()
+            .filter(|name| name.starts_with("word/media/") && name.ends_with(".png"))
+            .count();
+        assert_eq!(media, 1);
+
+        let xml = packaged_part(&doc, "word/document.xml");
+        assert!(xml.contains(r#"<wp:docPr id="1" name="Picture 1" descr="Red &amp; black" />"#));
+
+        // Fails loudly rather than exporting undescribed images if docx-rs changes its output
+        let mut built = doc.to_docx(DocxExportOptions::default()).build();
+        built.document = String::from_utf8_lossy(&built.document)
+            .replace(r#"name="Figure""#, r#"name="Image""#)
+            .into_bytes();
+        assert!(matches!(
+            doc.describe_images(&mut built),
+            Err(DocumentError::DocxPatch(_))
+        ));
+
+        assert!(doc.to_html().contains("<img src=\"data:image/png;base64,"));
+        assert!(
+            doc.to_markdown()
+                .contains("![Red & black](data:image/png;base64,")
+        );
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::stylemgr::structural::StyledParagraph;
-    use std::fs;
+    #[test]
+    fn test_reject_empty_document() {
+        use crate::stylemgr::image::tests::PNG;
 
-    // Helper to create a document with some content for testing
-    fn create_test_document() -> Document {
-        let mut doc = Document::new("Test Title");
+        let mut doc = Document::new("Empty");
+        doc.add_block(StyledParagraph::new());
+        assert!(doc.is_empty());
+        assert!(doc.to_docx_bytes().is_ok());
 
-        let style1 = Style::new();
-        let style2 = Style::new().switch_bold();
+        let options = DocxExportOptions {
+            reject_empty: true,
+            ..Default::default()
+        };
+        assert!(matches!(
+            doc.to_docx_bytes_with_options(options),
+            Err(DocumentError::EmptyDocument)
+        ));
 
-        let mut para1 = StyledParagraph::new();
-        para1.add(StyledText::new(
-            "Paragraph 1, Sentence 1. ".to_string(),
-            style1.clone(),
+        doc.add_block(Image::new(PNG.to_vec()).unwrap());
+        assert!(doc.require_non_empty().is_ok());
+        assert!(doc.to_docx_bytes_with_options(options).is_ok());
+        assert!(create_test_document().require_non_empty().is_ok());
+    }
+
+    #[test]
+    fn test_trim_empty_paragraphs() {
+        let blank = |text: &str| {
+            let mut sp = StyledParagraph::new();
+            sp.add(StyledText::new(text.to_string(), Style::new()));
+            sp
+        };
+        let mut doc = create_test_document();
+        doc.content.insert(1, blank(" \t").into());
+        doc.content.insert(0, StyledParagraph::new().into());
+        doc.add_block(blank(""));
+        doc.add_comment(Comment::new("Ada", "Check", 3, 0..4))
+            .unwrap();
+
+        assert_eq!(doc.trim_empty_paragraphs(true), 2);
+        assert_eq!(doc.paragraph_count(), 3);
+        assert_eq!(
+            doc.paragraph(0).unwrap().text(),
+            "Paragraph 1, Sentence 1. Bold bit."
+        );
+        assert_eq!(doc.paragraph(1).unwrap().text(), "Paragraph 2.");
+        assert!(doc.paragraph(2).unwrap().is_empty());
+        // The comment moved along with its paragraph
+        assert_eq!(doc.comments()[0].paragraph, 1);
+
+        assert_eq!(doc.trim_empty_paragraphs(false), 1);
+        assert_eq!(doc.paragraph_count(), 2);
+        assert_eq!(doc.trim_empty_paragraphs(false), 0);
+    }
+
+    #[test]
+    fn test_save_as_docx_embedding_fonts() {
+        // Depends on the fonts installed on the system running the tests
+        let Some(style) = ["DejaVu Sans", "Liberation Sans", "Arial", "Times New Roman"]
+            .into_iter()
+            .find_map(|font| Style::new().change_font(font.to_string()).ok())
+        else {
+            println!("Test skipped: no known font installed.");
+            return;
+        };
+        let mut doc = create_test_document();
+        doc.paragraph_mut(1).unwrap().chunks_mut()[0].set_style(style);
+
+        let path = std::env::temp_dir().join("edda_test_embedded_fonts.docx");
+        doc.save_as_docx_with_options(
+            &path,
+            DocxExportOptions {
+                embed_fonts: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let bytes = fs::read(&path).unwrap();
+        let _ = fs::remove_file(&path);
+
+        let mut archive = zip::ZipArchive::new(Cursor::new(bytes.clone())).unwrap();
+        let names: Vec<String> = archive.file_names().map(String::from).collect();
+        if !names.iter().any(|n| n.starts_with("word/fonts/")) {
+            println!("Test skipped: font substituted by the system.");
+            return;
+        }
+        let mut rels = String::new();
+        io::Read::read_to_string(
+            &mut archive.by_name("word/_rels/fontTable.xml.rels").unwrap(),
+            &mut rels,
+        )
+        .unwrap();
+        assert!(rels.contains("fonts/font1.odttf"));
+        assert!(docx_rs::read_docx(&bytes).is_ok());
+
+        let plain = doc.to_docx_bytes().unwrap();
+        let archive = zip::ZipArchive::new(Cursor::new(plain)).unwrap();
+        assert!(!archive.file_names().any(|n| n.starts_with("word/fonts/")));
+    }
+
+    #[test]
+    fn test_comments() {
+        let mut doc = create_test_document();
+        assert!(doc.comments().is_empty());
+
+        doc.add_comment(Comment::new("Ann", "Too bold?", 0, 25..34))
+            .unwrap();
+        doc.add_comment(Comment::new("Bob", "Whole paragraph", 1, 0..12))
+            .unwrap();
+        assert!(matches!(
+            doc.add_comment(Comment::new("Ann", "Out", 1, 5..40)),
+            Err(DocumentError::Paragraph(
+                ParagraphModifyError::InvalidRange(5, 40, 12)
+            ))
+        ));
+        assert!(matches!(
+            doc.add_comment(Comment::new("Ann", "Nowhere", 7, 0..0)),
+            Err(DocumentError::IndexOutOfBounds(7))
         ));
-        para1.add(StyledText::new("Bold bit.".to_string(), style2.clone()));
 
-        let mut para2 = StyledParagraph::new();
-        para2.add(StyledText::new("Paragraph 2.".to_string(), style1.clone()));
+        let first: Vec<&Comment> = doc.comments_for(0).collect();
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0].author, "Ann");
+        assert_eq!(first[0].range, 25..34);
+        assert_eq!(doc.comments_for(2).count(), 0);
 
-        doc.content.push(para1);
-        doc.content.push(para2);
+        let xml = doc.to_docx(DocxExportOptions::default()).build();
+        let comments = String::from_utf8(xml.comments).unwrap();
+        assert!(comments.contains("Too bold?"));
+        assert!(comments.contains(r#"w:author="Bob""#));
+        let body = String::from_utf8(xml.document).unwrap();
+        assert_eq!(body.matches("<w:commentRangeStart").count(), 2);
+        // The export itself doesn't touch the model
+        assert_eq!(doc.paragraph(0).unwrap().chunk_count(), 2);
 
-        doc
+        doc.clear_content();
+        assert!(doc.comments().is_empty());
+    }
+
+    #[test]
+    fn test_comments_follow_edits() {
+        let mut doc = create_test_document();
+        doc.add_comment(Comment::new("Ann", "Too bold?", 0, 25..34))
+            .unwrap();
+        doc.add_comment(Comment::new("Ann", "Which one?", 0, 10..11))
+            .unwrap();
+        doc.add_comment(Comment::new("Bob", "Number", 1, 10..11))
+            .unwrap();
+        let ranges = |doc: &Document| -> Vec<Range<usize>> {
+            doc.comments().iter().map(|c| c.range.clone()).collect()
+        };
+
+        doc.insert_text(0, 0, "New. ").unwrap();
+        assert_eq!(ranges(&doc), [30..39, 15..16, 10..11]);
+        doc.insert_text(0, 32, "ld, bo").unwrap();
+        assert_eq!(ranges(&doc), [30..45, 15..16, 10..11]);
+        doc.append_run(" Done.", Style::new());
+        assert_eq!(ranges(&doc), [30..45, 15..16, 10..11]);
+
+        // Cutting into a range shrinks it, removing all of its text drops the comment
+        assert_eq!(doc.delete_range(0, 14, 31).unwrap(), " 1, Sentence 1. B");
+        assert_eq!(ranges(&doc), [14..28, 10..11]);
+        assert_eq!(doc.comments()[0].text, "Too bold?");
+
+        // Tracked deletions keep the text, so the ranges stay
+        doc.track_changes(Some("Ann".to_string()));
+        doc.delete_range(0, 0, 5).unwrap();
+        assert_eq!(ranges(&doc), [14..28, 10..11]);
+        doc.reject_all().unwrap();
+        doc.track_changes(None);
+
+        doc.set_paragraph(1, StyledParagraph::new()).unwrap();
+        assert_eq!(doc.comments().len(), 1);
+        assert_eq!(doc.comments()[0].range, 14..28);
+    }
+
+    #[test]
+    fn test_tracked_changes() {
+        let mut doc = create_test_document();
+        let original = doc.get_text(false);
+        doc.track_changes(Some("Ann".to_string()));
+        assert!(doc.is_tracking_changes());
+
+        doc.insert_text(1, 9, " two").unwrap();
+        doc.insert_text(1, 13, "!").unwrap();
+        assert_eq!(doc.paragraph(1).unwrap().text(), "Paragraph two! 2.");
+        // Typing on at the end of an insertion extends it
+        assert_eq!(doc.revisions().len(), 1);
+        assert_eq!(doc.revisions()[0].range, 9..14);
+        assert_eq!(doc.revisions()[0].kind, RevisionKind::Insertion);
+
+        // Deleting tracked text only marks it
+        assert_eq!(doc.delete_range(0, 0, 10).unwrap(), "Paragraph ");
+        assert_eq!(
+            doc.paragraph(0).unwrap().text(),
+            "Paragraph 1, Sentence 1. Bold bit."
+        );
+        assert_eq!(doc.revisions()[1].kind, RevisionKind::Deletion);
+
+        let xml =
+            String::from_utf8(doc.to_docx(DocxExportOptions::default()).build().document).unwrap();
+        assert!(xml.contains(r#"<w:ins w:id="#));
+        assert!(xml.contains("<w:delText"));
+        assert!(xml.contains(r#"w:author="Ann""#));
+
+        let mut accepted = doc.clone();
+        accepted.accept_all().unwrap();
+        assert_eq!(
+            accepted.get_text(false),
+            "1, Sentence 1. Bold bit.Paragraph two! 2."
+        );
+        assert!(accepted.revisions().is_empty());
+
+        doc.reject_all().unwrap();
+        assert_eq!(doc.get_text(false), original);
+        assert!(doc.revisions().is_empty());
+    }
+
+    #[test]
+    fn test_tracked_delete_of_own_insertion() {
+        let mut doc = create_test_document();
+        doc.track_changes(Some("Ann".to_string()));
+        doc.insert_text(1, 0, "New ").unwrap();
+        // Removing your own pending insertion is a real deletion
+        assert_eq!(doc.delete_range(1, 0, 2).unwrap(), "Ne");
+        assert_eq!(doc.paragraph(1).unwrap().text(), "w Paragraph 2.");
+        assert_eq!(doc.revisions().len(), 1);
+        assert_eq!(doc.revisions()[0].range, 0..2);
+
+        doc.track_changes(None);
+        doc.insert_text(1, 0, "Untracked ").unwrap();
+        assert_eq!(doc.revisions()[0].range, 10..12);
+        doc.reject_all().unwrap();
+        assert_eq!(doc.paragraph(1).unwrap().text(), "Untracked Paragraph 2.");
     }
 
     #[test]
@@ -128,6 +3109,76 @@ mod tests {
         assert_eq!(metadata_ref.title, title);
     }
 
+    #[test]
+    fn test_clear_content_keeps_metadata() {
+        let mut doc = create_test_document();
+        doc.metadata.add_keyword("draft");
+        doc.clear_content();
+        assert_eq!(doc.paragraph_count(), 0);
+        assert_eq!(doc.get_metadata().title(), "Test Title");
+        assert!(doc.metadata.keywords.is_some());
+    }
+
+    #[test]
+    fn test_clear_all_resets_metadata() {
+        let mut doc = create_test_document();
+        doc.metadata.add_keyword("draft");
+        doc.clear_all();
+        assert_eq!(doc.paragraph_count(), 0);
+        assert_eq!(doc.get_metadata().title(), "Test Title");
+        assert!(doc.metadata.keywords.is_none());
+    }
+
+    #[test]
+    fn test_metadata_keywords() {
+        let mut metadata = Metadata::default();
+        assert!(!metadata.remove_keyword("rust"));
+
+        metadata.add_keyword("rust");
+        metadata.add_keyword("docx".to_string());
+        assert_eq!(metadata.keywords, Some(vec!["rust".into(), "docx".into()]));
+
+        assert!(metadata.remove_keyword("rust"));
+        assert!(!metadata.remove_keyword("rust"));
+        assert_eq!(metadata.keywords, Some(vec!["docx".into()]));
+    }
+
+    #[test]
+    fn test_metadata_authors() {
+        let mut metadata = Metadata::default();
+        metadata.add_author("Ada");
+        metadata.add_author("Grace");
+        assert!(metadata.remove_author("Ada"));
+        assert_eq!(metadata.authors, Some(vec!["Grace".into()]));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_metadata_json() {
+        let title_only = Metadata::new("Notes");
+        assert_eq!(title_only.to_json(), r#"{"title":"Notes"}"#);
+        assert_eq!(
+            Metadata::from_json(&title_only.to_json()).unwrap(),
+            title_only
+        );
+
+        let mut full = Metadata::new("Report");
+        full.add_author("Ada");
+        full.add_keyword("rust");
+        full.description = Some("Quarterly report".into());
+        full.category = Some("Finance".into());
+        full.version = Some("2".into());
+        full.status = Some("Draft".into());
+        full.set_language(Some("en-GB".into()));
+        full.set_alt_text(Some("Charts and tables".into()));
+        assert_eq!(Metadata::from_json(&full.to_json()).unwrap(), full);
+
+        assert!(matches!(
+            Metadata::from_json("{\"title\": 3}"),
+            Err(DocumentError::Json(_))
+        ));
+    }
+
     #[test]
     fn test_get_text_untagged() {
         let doc = create_test_document();
@@ -139,17 +3190,36 @@ mod tests {
     fn test_get_text_tagged() {
         let doc = create_test_document();
 
-        let style1_tag = format!("{}", Style::new());
-        let style2_tag = format!("{}", Style::new().switch_bold());
+        // Default-styled text is left untagged
+        let style2_tag = Style::new().switch_bold().to_tag();
 
         let expected_text = format!(
-            "[[{0}]]Paragraph 1, Sentence 1. [[/{0}]][[{1}]]Bold bit.[[/{1}]][[{0}]]Paragraph 2.[[/{0}]]",
-            style1_tag, style2_tag
+            "Paragraph 1, Sentence 1. [[{0}]]Bold bit.[[/{0}]]Paragraph 2.",
+            style2_tag
         );
 
         assert_eq!(doc.get_text(true), expected_text);
     }
 
+    #[test]
+    fn test_get_text_line_break() {
+        let mut doc = Document::new("Breaks");
+        let mut para = StyledParagraph::new();
+        para.add(StyledText::new("Line one".to_string(), Style::new()));
+        para.add(StyledText::line_break(Style::new()));
+        para.add(StyledText::new("Line two".to_string(), Style::new()));
+        doc.add_block(para);
+
+        assert_eq!(doc.get_text(false), "Line one\nLine two");
+        assert_eq!(
+            doc.to_docx(DocxExportOptions::default())
+                .document
+                .children
+                .len(),
+            1
+        );
+    }
+
     #[test]
     fn test_get_text_empty() {
         let doc = Document::new("Empty Doc");
@@ -157,6 +3227,356 @@ mod tests {
         assert_eq!(doc.get_text(true), "");
     }
 
+    #[test]
+    fn test_insert_text_mid_chunk() {
+        let mut doc = create_test_document();
+        doc.insert_text(1, 9, " two,").unwrap();
+
+        let para = doc.paragraph(1).unwrap();
+        let texts: Vec<&str> = para.chunks().iter().map(|st| st.text.as_str()).collect();
+        assert_eq!(texts, vec!["Paragraph", " two,", " 2."]);
+        assert_eq!(para.chunks()[1].style(), para.chunks()[0].style());
+    }
+
+    #[test]
+    fn test_insert_text_chunk_boundary() {
+        let mut doc = create_test_document();
+        // Right after "Paragraph 1, Sentence 1. ", before the bold chunk
+        doc.insert_text(0, 25, "Plain. ").unwrap();
+
+        let para = doc.paragraph(0).unwrap();
+        assert_eq!(para.chunk_count(), 3);
+        assert_eq!(para.chunks()[1].text, "Plain. ");
+        assert!(!para.chunks()[1].style().bold());
+        assert_eq!(para.text(), "Paragraph 1, Sentence 1. Plain. Bold bit.");
+    }
+
+    #[test]
+    fn test_insert_text_out_of_range() {
+        let mut doc = create_test_document();
+        assert!(matches!(
+            doc.insert_text(1, 13, "x"),
+            Err(DocumentError::Paragraph(
+                ParagraphModifyError::InvalidRange(13, 13, 12)
+            ))
+        ));
+        assert!(matches!(
+            doc.insert_text(2, 0, "x"),
+            Err(DocumentError::IndexOutOfBounds(2))
+        ));
+    }
+
+    #[test]
+    fn test_delete_range_across_chunks() {
+        let mut doc = create_test_document();
+        let deleted = doc.delete_range(0, 22, 30).unwrap();
+        assert_eq!(deleted, "1. Bold ");
+
+        let para = doc.paragraph(0).unwrap();
+        let texts: Vec<&str> = para.chunks().iter().map(|st| st.text.as_str()).collect();
+        assert_eq!(texts, vec!["Paragraph 1, Sentence ", "bit."]);
+        assert!(!para.chunks()[0].style().bold());
+        assert!(para.chunks()[1].style().bold());
+    }
+
+    #[test]
+    fn test_delete_range_merges_same_style() {
+        let mut doc = create_test_document();
+        doc.paragraph_mut(0).unwrap().chunks_mut()[1].set_style(Style::new().switch_italic());
+        doc.paragraph_mut(0)
+            .unwrap()
+            .add(StyledText::new(" Tail".to_string(), Style::new()));
+
+        // Deleting the whole middle chunk leaves two default chunks next to each other
+        assert_eq!(doc.delete_range(0, 25, 34).unwrap(), "Bold bit.");
+        assert_eq!(doc.paragraph(0).unwrap().chunk_count(), 1);
+        assert_eq!(
+            doc.paragraph(0).unwrap().text(),
+            "Paragraph 1, Sentence 1.  Tail"
+        );
+    }
+
+    #[test]
+    fn test_apply_command_toggles_each_chunk() {
+        let mut doc = create_test_document();
+
+        // "1. " is plain and "Bold" is bold: toggling flips both
+        doc.apply_command(0, 22, 29, ApplicableStyles::Bold)
+            .unwrap();
+        let chunks: Vec<(&str, bool)> = doc
+            .paragraph(0)
+            .unwrap()
+            .chunks()
+            .iter()
+            .map(|st| (st.text.as_str(), st.style().bold()))
+            .collect();
+        assert_eq!(
+            chunks,
+            [
+                ("Paragraph 1, Sentence ", false),
+                ("1. ", true),
+                ("Bold", false),
+                (" bit.", true),
+            ]
+        );
+
+        doc.apply_command(0, 0, 34, ApplicableStyles::Size(14))
+            .unwrap();
+        assert!(
+            doc.paragraph(0)
+                .unwrap()
+                .chunks()
+                .iter()
+                .all(|st| st.style().size() == 14)
+        );
+
+        // A failing command leaves the paragraph as it was
+        let before = doc.paragraph(0).unwrap().clone();
+        assert!(matches!(
+            doc.apply_command(0, 0, 5, ApplicableStyles::Color("red".to_string())),
+            Err(DocumentError::Style(_))
+        ));
+        assert!(
+            doc.apply_command(0, 5, 40, ApplicableStyles::Italic)
+                .is_err()
+        );
+        assert!(
+            doc.apply_command(2, 0, 1, ApplicableStyles::Italic)
+                .is_err()
+        );
+        assert_eq!(*doc.paragraph(0).unwrap(), before);
+    }
+
+    #[test]
+    fn test_delete_range_out_of_range() {
+        let mut doc = create_test_document();
+        assert!(doc.delete_range(1, 5, 50).is_err());
+        assert!(doc.delete_range(3, 0, 1).is_err());
+        assert_eq!(doc.paragraph(1).unwrap().text(), "Paragraph 2.");
+    }
+
+    #[test]
+    fn test_style_selection() {
+        let mut doc = create_test_document();
+        let italic = Style::new().switch_italic();
+        doc.style_selection(0, 10, 21, italic.clone()).unwrap();
+
+        let para = doc.paragraph(0).unwrap();
+        let texts: Vec<&str> = para.chunks().iter().map(|st| st.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            vec!["Paragraph ", "1, Sentence", " 1. ", "Bold bit."]
+        );
+        assert_eq!(para.chunks()[1].style(), &italic);
+        assert_eq!(doc.get_text(false), create_test_document().get_text(false));
+    }
+
+    #[test]
+    fn test_style_selection_out_of_range() {
+        let mut doc = create_test_document();
+        assert!(matches!(
+            doc.style_selection(5, 0, 1, Style::new()),
+            Err(DocumentError::IndexOutOfBounds(5))
+        ));
+        assert!(matches!(
+            doc.style_selection(1, 0, 100, Style::new()),
+            Err(DocumentError::Paragraph(
+                ParagraphModifyError::InvalidRange(..)
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_diff_identical() {
+        let doc = create_test_document();
+        assert!(doc.diff(&doc.clone()).is_empty());
+    }
+
+    #[test]
+    fn test_diff_insert_and_edit() {
+        let old = create_test_document();
+        let mut new = old.clone();
+
+        let mut inserted = StyledParagraph::new();
+        inserted.add(StyledText::new("Inserted.".to_string(), Style::new()));
+        new.content.insert(1, inserted.into());
+        new.paragraph_mut(2).unwrap().chunks_mut()[0].text = "Paragraph 2, edited.".to_string();
+
+        assert_eq!(
+            old.diff(&new),
+            vec![
+                ParagraphDiff::Added(1),
+                ParagraphDiff::Changed {
+                    index: 2,
+                    from_text: "Paragraph 2.".to_string(),
+                    to_text: "Paragraph 2, edited.".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff_removed() {
+        let old = create_test_document();
+        let mut new = old.clone();
+        new.content.remove(0);
+        assert_eq!(old.diff(&new), vec![ParagraphDiff::Removed(0)]);
+        assert_eq!(new.diff(&old), vec![ParagraphDiff::Added(0)]);
+    }
+
+    #[test]
+    fn test_to_html() {
+        let doc = create_test_document();
+        let html = doc.to_html();
+        assert_eq!(html.matches("<p>").count(), 2);
+        assert!(html.contains("<span style=\"font-weight:bold\">Bold bit.</span>"));
+        assert!(html.contains("<p>Paragraph 2.</p>"));
+        assert!(html.contains("<title>Test Title</title>"));
+        assert!(html.starts_with("<!DOCTYPE html>\n<html>\n"));
+    }
+
+    #[test]
+    fn test_save_as_html() {
+        let mut doc = create_test_document();
+        doc.get_metadata_mut().set_language(Some("en".to_string()));
+        let path = std::env::temp_dir().join("edda_test_save.html");
+        doc.save_as_html(&path).unwrap();
+
+        let html = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(html.starts_with("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n"));
+        assert!(html.contains("<meta charset=\"utf-8\">"));
+        assert!(html.contains("<title>Test Title</title>"));
+        assert!(html.contains("<p>Paragraph 2.</p>"));
+        assert!(html.ends_with("</body>\n</html>\n"));
+
+        let missing_dir = std::env::temp_dir()
+            .join("edda_no_such_dir")
+            .join("out.html");
+        assert!(matches!(
+            doc.save_as_html(missing_dir),
+            Err(DocumentError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_accessibility_metadata() {
+        let mut doc = create_test_document();
+        doc.get_metadata_mut()
+            .set_language(Some("es-ES".to_string()));
+        doc.get_metadata_mut()
+            .set_alt_text(Some("Two short \"test\" paragraphs".to_string()));
+
+        let html = doc.to_html();
+        assert!(html.contains("<html lang=\"es-ES\">"));
+        assert!(html.contains(
+            "<meta name=\"description\" content=\"Two short &quot;test&quot; paragraphs\">"
+        ));
+
+        let styles = |doc: &Document| packaged_part(doc, "word/styles.xml");
+        let defaults = styles(&doc);
+        let defaults = &defaults[defaults.find("<w:rPrDefault>").unwrap()..];
+        assert!(defaults.starts_with("<w:rPrDefault><w:rPr>"));
+        assert!(defaults.contains(r#"<w:lang w:val="es-ES" /></w:rPr></w:rPrDefault>"#));
+        assert!(!styles(&create_test_document()).contains("<w:lang"));
+    }
+
+    #[test]
+    fn test_to_docx_paragraph_count() {
+        let doc = create_test_document();
+        let docx = doc.to_docx(DocxExportOptions::default());
+        assert_eq!(docx.document.children.len(), 2);
+    }
+
+    #[test]
+    fn test_to_docx_bullet_list() {
+        let mut doc = create_test_document();
+        for n in 0..3 {
+            let mut para = StyledParagraph::new();
+            para.add(StyledText::new(format!("Item {}", n), Style::new()));
+            para.style = ParagraphStyle::new().set_list(Some(ListItem::new(ListKind::Bullet, 0)));
+            doc.add_block(para);
+        }
+
+        let docx = doc.to_docx(DocxExportOptions::default());
+        assert_eq!(docx.numberings.abstract_nums.len(), 1);
+        // The shared instance, then the one of this list
+        assert_eq!(docx.numberings.numberings.len(), 2);
+
+        let ids: Vec<Option<usize>> = docx
+            .document
+            .children
+            .iter()
+            .map(|child| match child {
+                DocumentChild::Paragraph(p) => p
+                    .property
+                    .numbering_property
+                    .as_ref()
+                    .and_then(|np| np.id.as_ref().map(|id| id.id)),
+                _ => None,
+            })
+            .collect();
+        let list = Some(docx.numberings.numberings[1].id);
+        assert_eq!(ids, vec![None, None, list, list, list]);
+        assert!(doc.to_docx_bytes().is_ok());
+    }
+
+    #[test]
+    fn test_to_docx_lists_restart() {
+        let mut doc = Document::new("Lists");
+        let item = |text: &str, kind| {
+            let mut para = StyledParagraph::new();
+            para.add(StyledText::new(text.to_string(), Style::new()));
+            para.style = ParagraphStyle::new().set_list(Some(ListItem::new(kind, 0)));
+            para
+        };
+        doc.add_block(item("One", ListKind::Number));
+        doc.add_block(item("Two", ListKind::Number));
+        doc.add_block(StyledParagraph::from_chunks(vec![StyledText::new(
+            "Between".to_string(),
+            Style::new(),
+        )]));
+        doc.add_block(item("One again", ListKind::Number));
+        doc.add_block(item("Dot", ListKind::Bullet));
+
+        let docx = doc.to_docx(DocxExportOptions::default());
+        let ids: Vec<Option<usize>> = docx
+            .document
+            .children
+            .iter()
+            .map(|child| match child {
+                DocumentChild::Paragraph(p) => p
+                    .property
+                    .numbering_property
+                    .as_ref()
+                    .and_then(|np| np.id.as_ref().map(|id| id.id)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(ids, vec![Some(3), Some(3), None, Some(4), Some(5)]);
+
+        let numbering = String::from_utf8(docx.build().numberings).unwrap();
+        for id in 3..=5 {
+            let instance = format!(r#"<w:num w:numId="{}">"#, id);
+            let start = numbering.find(&instance).unwrap();
+            let end = start + numbering[start..].find("</w:num>").unwrap();
+            assert!(numbering[start..end].contains(r#"<w:startOverride w:val="1" />"#));
+        }
+    }
+
+    #[test]
+    fn test_to_docx_no_lists_no_numbering() {
+        let docx = create_test_document().to_docx(DocxExportOptions::default());
+        assert!(docx.numberings.abstract_nums.is_empty());
+    }
+
+    #[test]
+    fn test_to_docx_bytes_is_zip() {
+        let doc = create_test_document();
+        let bytes = doc.to_docx_bytes().unwrap();
+        assert!(bytes.starts_with(b"PK\x03\x04"));
+    }
+
     #[test]
     // Basic test to ensure save_as_docx runs and returns Ok.
     // Does not validate the .docx content.
@@ -0,0 +1,207 @@
+//! Change notifications, so a view can re-render when the model changes instead of polling
+use std::cell::Cell;
+use std::fmt;
+
+use super::Document;
+
+/// What changed in a document. Indices are block indices at the time of the event; an edit inside
+/// a table cell reports the table's block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeEvent {
+    ParagraphAdded(usize),
+    /// Removals are reported back to front, so every index is still valid for a view that applies
+    /// them in order
+    ParagraphRemoved(usize),
+    TextChanged(usize),
+    StyleChanged(usize),
+    CommentAdded(usize),
+    MetadataChanged,
+    /// Change tracking was turned on, off or handed to another author
+    TrackingChanged,
+}
+
+type Callback = Box<dyn Fn(&ChangeEvent) + Send>;
+
+/// Callback set with `Document::set_on_change`. It belongs to the document instance: clones start
+/// without one, and it is ignored by comparisons and serialization
+#[derive(Default)]
+pub(super) struct ChangeObserver(Option<Callback>);
+
+impl Clone for ChangeObserver {
+    fn clone(&self) -> Self {
+        ChangeObserver(None)
+    }
+}
+
+impl PartialEq for ChangeObserver {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl fmt::Debug for ChangeObserver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(if self.0.is_some() {
+            "ChangeObserver(Some(..))"
+        } else {
+            "ChangeObserver(None)"
+        })
+    }
+}
+
+/// Flag behind `Document::is_modified`. `notify` sets it, so it sees every change an observer
+/// would. Like the observer it is ignored by comparisons and serialization
+#[derive(Debug, Clone, Default)]
+pub(super) struct Modified(Cell<bool>);
+
+impl PartialEq for Modified {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Document {
+    /// Whether anything changed since the document was created, loaded or saved with one of the
+    /// `save_as_*` methods
+    pub fn is_modified(&self) -> bool {
+        self.modified.0.get()
+    }
+
+    /// Clear `is_modified`, for documents written out some other way than `save_as_*`
+    pub fn mark_saved(&self) {
+        self.modified.0.set(false);
+    }
+
+    /// Call `f` after every change made through the document's mutating methods, replacing any
+    /// previous observer. `get_metadata_mut` reports `MetadataChanged` up front, since what is
+    /// done with the reference can't be seen. The callback is `Send` so documents can still move to
+    /// another thread
+    pub fn set_on_change(&mut self, f: Box<dyn Fn(&ChangeEvent) + Send>) {
+        self.on_change = ChangeObserver(Some(f));
+    }
+
+    pub fn clear_on_change(&mut self) {
+        self.on_change = ChangeObserver(None);
+    }
+
+    pub(super) fn notify(&self, event: ChangeEvent) {
+        self.modified.0.set(true);
+        if let Some(f) = &self.on_change.0 {
+            f(&event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::filemgr::document::Comment;
+    use crate::stylemgr::structural::StyledParagraph;
+    use crate::stylemgr::style::Style;
+
+    fn observed(doc: &mut Document) -> Arc<Mutex<Vec<ChangeEvent>>> {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&events);
+        doc.set_on_change(Box::new(move |event| sink.lock().unwrap().push(*event)));
+        events
+    }
+
+    #[test]
+    fn test_change_events() {
+        let mut doc = Document::from_plain_text("Events", "One\nTwo");
+        let events = observed(&mut doc);
+
+        doc.add_block(StyledParagraph::new());
+        doc.insert_text(0, 3, "!").unwrap();
+        doc.style_selection(1, 0, 3, Style::new().switch_bold())
+            .unwrap();
+        doc.get_metadata_mut().set_language(Some("en".to_string()));
+        assert_eq!(doc.trim_empty_paragraphs(false), 1);
+        doc.add_comment(Comment::new("Me", "Why?", 1, 0..3))
+            .unwrap();
+        doc.track_changes(Some("Me".to_string()));
+        doc.set_default_style(Style::new().change_size(14));
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                ChangeEvent::ParagraphAdded(2),
+                ChangeEvent::TextChanged(0),
+                ChangeEvent::StyleChanged(1),
+                ChangeEvent::MetadataChanged,
+                ChangeEvent::ParagraphRemoved(2),
+                ChangeEvent::CommentAdded(1),
+                ChangeEvent::TrackingChanged,
+                ChangeEvent::StyleChanged(0),
+                ChangeEvent::StyleChanged(1),
+            ]
+        );
+
+        // Failed edits report nothing
+        events.lock().unwrap().clear();
+        assert!(doc.insert_text(9, 0, "x").is_err());
+        assert!(doc.add_comment(Comment::new("Me", "?", 0, 0..9)).is_err());
+        assert!(events.lock().unwrap().is_empty());
+
+        doc.clear_content();
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                ChangeEvent::ParagraphRemoved(1),
+                ChangeEvent::ParagraphRemoved(0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_observer_not_cloned() {
+        let mut doc = Document::new("Events");
+        let events = observed(&mut doc);
+
+        let mut copy = doc.clone();
+        assert_eq!(copy, doc);
+        copy.add_block(StyledParagraph::new());
+        assert!(events.lock().unwrap().is_empty());
+
+        doc.clear_on_change();
+        doc.add_block(StyledParagraph::new());
+        assert!(events.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_modified_flag() {
+        let mut doc = Document::from_plain_text("Events", "One\nTwo");
+        assert!(!doc.is_modified());
+        assert!(!doc.clone().is_modified());
+        doc.insert_text(0, 3, "!").unwrap();
+        assert!(doc.is_modified());
+        doc.mark_saved();
+        assert!(!doc.is_modified());
+
+        // Failed edits change nothing
+        assert!(doc.insert_text(9, 0, "x").is_err());
+        assert!(!doc.is_modified());
+        doc.get_metadata_mut().set_language(Some("en".to_string()));
+        assert!(doc.is_modified());
+
+        let path = std::env::temp_dir().join("edda_modified_flag.html");
+        doc.save_as_html(&path).unwrap();
+        assert!(!doc.is_modified());
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_document_moves_to_another_thread() {
+        let mut doc = Document::from_plain_text("Events", "One");
+        let events = observed(&mut doc);
+        let doc = std::thread::spawn(move || {
+            doc.insert_text(0, 3, "!").unwrap();
+            doc
+        })
+        .join()
+        .unwrap();
+        assert_eq!(doc.get_text(false), "One!");
+        assert_eq!(*events.lock().unwrap(), [ChangeEvent::TextChanged(0)]);
+    }
+}
@@ -0,0 +1,250 @@
+//! Tracked changes. While tracking is on, insertions are recorded as revisions and deletions only
+//! mark the text, until they are accepted or rejected
+use std::ops::Range;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use docx_rs::{Delete, DeleteText, Insert, Paragraph, Run, RunChild};
+
+use super::{ChangeEvent, Document, DocumentError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RevisionKind {
+    Insertion,
+    Deletion,
+}
+
+/// Tracked change over a character range of a paragraph. Deleted text stays in the paragraph
+/// until the revision is accepted
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Revision {
+    pub kind: RevisionKind,
+    pub author: String,
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    pub paragraph: usize,
+    pub range: Range<usize>,
+}
+
+impl Document {
+    /// Start tracking changes made by `author`, or stop with None. Existing revisions are kept
+    pub fn track_changes(&mut self, author: Option<String>) {
+        self.tracking = author;
+        self.notify(ChangeEvent::TrackingChanged);
+    }
+
+    pub fn is_tracking_changes(&self) -> bool {
+        self.tracking.is_some()
+    }
+
+    pub fn revisions(&self) -> &[Revision] {
+        &self.revisions
+    }
+
+    /// Apply every pending revision: inserted text stays and deleted text is removed
+    pub fn accept_all(&mut self) -> Result<(), DocumentError> {
+        self.resolve(RevisionKind::Deletion)
+    }
+
+    /// Undo every pending revision: inserted text is removed and deleted text stays
+    pub fn reject_all(&mut self) -> Result<(), DocumentError> {
+        self.resolve(RevisionKind::Insertion)
+    }
+
+    /// Remove the text covered by revisions of `kind` and forget every revision
+    fn resolve(&mut self, kind: RevisionKind) -> Result<(), DocumentError> {
+        let mut ranges: Vec<(usize, Range<usize>)> = self
+            .revisions
+            .iter()
+            .filter(|r| r.kind == kind)
+            .map(|r| (r.paragraph, r.range.clone()))
+            .collect();
+        // Merge overlaps, then remove back to front so earlier offsets stay valid
+        ranges.sort_by_key(|(para, range)| (*para, range.start));
+        let mut merged: Vec<(usize, Range<usize>)> = Vec::with_capacity(ranges.len());
+        for (para, range) in ranges {
+            match merged.last_mut() {
+                Some((p, last)) if *p == para && range.start <= last.end => {
+                    last.end = last.end.max(range.end)
+                }
+                _ => merged.push((para, range)),
+            }
+        }
+        let mut changed: Vec<usize> = self.revisions.iter().map(|r| r.paragraph).collect();
+        for (para, range) in merged.into_iter().rev() {
+            self.paragraph_mut(para)?
+                .delete_range(range.start, range.end)?;
+            self.comments_after_delete(para, range);
+        }
+        self.revisions.clear();
+        changed.sort_unstable();
+        changed.dedup();
+        for para in changed {
+            self.notify(ChangeEvent::TextChanged(para));
+        }
+        Ok(())
+    }
+
+    /// Keep revision ranges in place after `len` characters were inserted at `offset`, and record
+    /// the insertion when tracking. Typing right after an insertion by the same author extends it
+    pub(super) fn revisions_after_insert(&mut self, para: usize, offset: usize, len: usize) {
+        if len == 0 {
+            return;
+        }
+        let author = self.tracking.clone();
+        let mut extended = false;
+        for r in self.revisions.iter_mut().filter(|r| r.paragraph == para) {
+            let continues = !extended
+                && r.kind == RevisionKind::Insertion
+                && r.range.end == offset
+                && author.as_deref() == Some(r.author.as_str());
+            if continues {
+                r.range.end += len;
+                extended = true;
+            } else if r.range.start >= offset {
+                r.range.start += len;
+                r.range.end += len;
+            } else if r.range.end > offset {
+                r.range.end += len;
+            }
+        }
+        if let Some(author) = author
+            && !extended
+        {
+            self.revisions.push(Revision {
+                kind: RevisionKind::Insertion,
+                author,
+                timestamp: now(),
+                paragraph: para,
+                range: offset..offset + len,
+            });
+        }
+    }
+
+    /// Keep revision ranges in place after `range` was removed from a paragraph
+    pub(super) fn revisions_after_delete(&mut self, para: usize, range: Range<usize>) {
+        let map = |x| offset_after_delete(x, &range);
+        for r in self.revisions.iter_mut().filter(|r| r.paragraph == para) {
+            r.range = map(r.range.start)..map(r.range.end);
+        }
+        self.revisions
+            .retain(|r| r.paragraph != para || !r.range.is_empty());
+    }
+
+    /// When tracking, record a deletion instead of removing text. Returns false if the text has
+    /// to be removed right away: not tracking, or the text is the author's own pending insertion
+    pub(super) fn track_deletion(&mut self, para: usize, range: Range<usize>) -> bool {
+        let Some(author) = self.tracking.clone() else {
+            return false;
+        };
+        let own_insertion = self.revisions.iter().any(|r| {
+            r.paragraph == para
+                && r.kind == RevisionKind::Insertion
+                && r.author == author
+                && r.range.start <= range.start
+                && range.end <= r.range.end
+        });
+        if own_insertion {
+            return false;
+        }
+        if !range.is_empty() {
+            self.revisions.push(Revision {
+                kind: RevisionKind::Deletion,
+                author,
+                timestamp: now(),
+                paragraph: para,
+                range,
+            });
+        }
+        true
+    }
+
+    /// Revision covering the whole of `range` in paragraph `para`, deletions first
+    pub(super) fn revision_at(&self, para: usize, range: &Range<usize>) -> Option<&Revision> {
+        let covering = |kind: RevisionKind| {
+            self.revisions.iter().find(|r| {
+                r.paragraph == para
+                    && r.kind == kind
+                    && r.range.start <= range.start
+                    && range.end <= r.range.end
+            })
+        };
+        covering(RevisionKind::Deletion).or_else(|| covering(RevisionKind::Insertion))
+    }
+}
+
+/// Where character offset `x` ends up once `removed` is cut out of its paragraph. Offsets inside
+/// the removed text collapse onto its start
+pub(super) fn offset_after_delete(x: usize, removed: &Range<usize>) -> usize {
+    if x <= removed.start {
+        x
+    } else if x >= removed.end {
+        x - removed.len()
+    } else {
+        removed.start
+    }
+}
+
+impl Revision {
+    /// Add `run` to `paragraph` inside the matching w:ins / w:del element
+    pub(super) fn add_run_to(&self, paragraph: Paragraph, mut run: Run) -> Paragraph {
+        let date = iso8601(self.timestamp);
+        match self.kind {
+            RevisionKind::Insertion => {
+                paragraph.add_insert(Insert::new(run).author(&self.author).date(date))
+            }
+            RevisionKind::Deletion => {
+                for child in &mut run.children {
+                    if let RunChild::Text(text) = child {
+                        *child = RunChild::DeleteText(DeleteText::new(&text.text));
+                    }
+                }
+                paragraph.add_delete(Delete::new().add_run(run).author(&self.author).date(date))
+            }
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// UTC timestamp in the format docx expects (`2024-01-31T12:00:00Z`)
+fn iso8601(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3_600,
+        rem % 3_600 / 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_iso8601() {
+        assert_eq!(iso8601(0), "1970-01-01T00:00:00Z");
+        assert_eq!(iso8601(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(iso8601(1_700_000_000), "2023-11-14T22:13:20Z");
+    }
+}
@@ -0,0 +1,72 @@
+//! Minimal docx reader used by `Document::from_docx_bytes`. Only top-level paragraphs are read,
+//! keeping their text plus bold, italic, underline and single strikethrough. Tables, images and
+//! other run properties are dropped
+use docx_rs::{
+    Bold, DocumentChild, Italic, ParagraphChild, RunChild, RunProperty, Strike, Underline,
+};
+
+use crate::stylemgr::{
+    structural::StyledParagraph,
+    style::{StrikeStyle, Style, UnderlineStyle},
+    text::StyledText,
+};
+
+use super::document::DocumentError;
+
+pub(crate) fn parse(bytes: &[u8]) -> Result<Vec<StyledParagraph>, DocumentError> {
+    let docx = docx_rs::read_docx(bytes).map_err(|e| DocumentError::DocxRead(e.to_string()))?;
+
+    let mut paragraphs = Vec::new();
+    for child in docx.document.children {
+        let DocumentChild::Paragraph(paragraph) = child else {
+            continue;
+        };
+        let mut sp = StyledParagraph::new();
+        for child in paragraph.children {
+            let ParagraphChild::Run(run) = child else {
+                continue;
+            };
+            let text: String = run
+                .children
+                .iter()
+                .filter_map(|c| match c {
+                    RunChild::Text(t) => Some(t.text.as_str()),
+                    RunChild::Tab(_) => Some("\t"),
+                    _ => None,
+                })
+                .collect();
+            if !text.is_empty() {
+                sp.add(StyledText::new(text, style_of(&run.run_property)));
+            }
+        }
+        sp.normalize();
+        paragraphs.push(sp);
+    }
+    Ok(paragraphs)
+}
+
+/// The `w:val` of an underline. docx-rs keeps it private and doesn't expose its XML writer, so
+/// it is read from the `Debug` output, `Underline { val: "..." }`. Should that format change, the
+/// value comes back empty and reads as no underline, which `test_docx_underline_kinds` catches
+fn underline_value(underline: &Underline) -> String {
+    let debug = format!("{:?}", underline);
+    debug.split('"').nth(1).unwrap_or_default().to_string()
+}
+
+/// docx-rs keeps the property values private, so they are compared against the enabled ones
+fn style_of(property: &RunProperty) -> Style {
+    let mut style = Style::new();
+    if property.bold.as_ref() == Some(&Bold::new()) {
+        style = style.switch_bold();
+    }
+    if property.italic.as_ref() == Some(&Italic::new()) {
+        style = style.switch_italic();
+    }
+    if let Some(underline) = &property.underline {
+        style = style.set_underline(UnderlineStyle::parse_optional(&underline_value(underline)));
+    }
+    if property.strike.as_ref() == Some(&Strike::new()) {
+        style = style.set_strike(Some(StrikeStyle::Single));
+    }
+    style
+}
@@ -0,0 +1,238 @@
+//! Font embedding for DOCX export. docx-rs writes a fixed font table, so the embedded fonts are
+//! patched into the built parts and appended to the package afterwards
+use std::collections::BTreeSet;
+use std::io::{Cursor, Write};
+
+use docx_rs::XMLDocx;
+use font_kit::{family_name::FamilyName, properties::Properties, source::SystemSource};
+use zip::{ZipWriter, write::FileOptions};
+
+use super::document::DocumentError;
+use super::xml_patch::{self, edit_part, escape_xml};
+use crate::logs::Level;
+
+const FONT_RELATIONSHIP: &str =
+    "http://schemas.openxmlformats.org/officeDocument/2006/relationships/font";
+const OBFUSCATED_FONT: &str = "application/vnd.openxmlformats-officedocument.obfuscatedFont";
+
+/// A font ready to go into the package
+struct EmbeddedFont {
+    name: String,
+    key: String,
+    data: Vec<u8>,
+}
+
+/// Load every font in `names` from the system. Fonts that can't be found (or that the system
+/// would substitute with a different family) are skipped with a warning
+fn collect<'a>(names: impl IntoIterator<Item = &'a str>) -> Vec<EmbeddedFont> {
+    let names: BTreeSet<&str> = names.into_iter().collect();
+    let mut fonts = Vec::new();
+    for name in names {
+        match load_font(name) {
+            Some(data) => {
+                let key = font_key(name);
+                let data = obfuscate(data, &key);
+                fonts.push(EmbeddedFont {
+                    name: name.to_string(),
+                    key,
+                    data,
+                });
+            }
+            None => crate::log!(Level::Warning, "Font '{}' could not be embedded", name),
+        }
+    }
+    fonts
+}
+
+fn load_font(name: &str) -> Option<Vec<u8>> {
+    let handle = SystemSource::new()
+        .select_best_match(&[FamilyName::Title(name.to_string())], &Properties::new())
+        .ok()?;
+    let font = handle.load().ok()?;
+    if !font.family_name().eq_ignore_ascii_case(name) {
+        return None;
+    }
+    font.copy_font_data().map(|data| data.to_vec())
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// 64-bit FNV-1a over `bytes`, starting from `hash`
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(hash, |h, &b| (h ^ u64::from(b)).wrapping_mul(FNV_PRIME))
+}
+
+/// GUID used as the obfuscation key. Derived from the font name with a fixed hash, so exports are
+/// reproducible across builds
+fn font_key(name: &str) -> String {
+    let high = fnv1a(FNV_OFFSET, name.as_bytes());
+    let low = fnv1a(high, name.as_bytes());
+    let hex = format!("{:016X}{:016X}", high, low);
+    format!(
+        "{{{}-{}-{}-{}-{}}}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+/// Obfuscate a font as ECMA-376 requires: the first 32 bytes are XORed with the GUID bytes,
+/// taken in reverse order of the key string
+fn obfuscate(mut data: Vec<u8>, key: &str) -> Vec<u8> {
+    let digits: String = key.chars().filter(char::is_ascii_hexdigit).collect();
+    let guid: Vec<u8> = (0..16)
+        .rev()
+        .map(|i| u8::from_str_radix(&digits[i * 2..i * 2 + 2], 16).unwrap_or(0))
+        .collect();
+    for (i, byte) in data.iter_mut().take(32).enumerate() {
+        *byte ^= guid[i % 16];
+    }
+    data
+}
+
+fn font_path(idx: usize) -> String {
+    format!("word/fonts/font{}.odttf", idx + 1)
+}
+
+/// Register `fonts` in the font table, settings and content types of a built document
+fn patch_parts(xml: &mut XMLDocx, fonts: &[EmbeddedFont]) -> Result<(), DocumentError> {
+    edit_part(&mut xml.font_table, |table| {
+        for (idx, font) in fonts.iter().enumerate() {
+            let embed = format!(
+                r#"<w:embedRegular r:id="rIdFont{}" w:fontKey="{}" />"#,
+                idx + 1,
+                font.key
+            );
+            // docx-rs lists a few common fonts already; the embed elements go last inside their
+            // w:font, after charset, family and pitch
+            let existing = format!(r#"<w:font w:name="{}">"#, escape_xml(&font.name));
+            match table.find(&existing) {
+                Some(start) => {
+                    let end = xml_patch::find(table, "</w:font>", start)?;
+                    table.insert_str(end, &embed);
+                }
+                None => {
+                    let entry = format!("{}{}</w:font>", existing, embed);
+                    xml_patch::insert_before(table, "</w:fonts>", &entry)?;
+                }
+            }
+        }
+        Ok(())
+    })?;
+
+    // embedTrueTypeFonts has to follow zoom in the settings schema
+    edit_part(&mut xml.settings, |settings| {
+        let zoom = xml_patch::find(settings, "<w:zoom", 0)?;
+        let end = xml_patch::find(settings, "/>", zoom)? + 2;
+        settings.insert_str(end, "<w:embedTrueTypeFonts />");
+        Ok(())
+    })?;
+
+    let default = format!(
+        r#"<Default Extension="odttf" ContentType="{}" />"#,
+        OBFUSCATED_FONT
+    );
+    edit_part(&mut xml.content_type, |types| {
+        xml_patch::insert_before(types, "<Override", &default)
+    })
+}
+
+/// Relationships from the font table to the font parts
+fn font_table_rels(fonts: &[EmbeddedFont]) -> String {
+    let mut rels = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">"#,
+    );
+    for idx in 0..fonts.len() {
+        rels.push_str(&format!(
+            r#"<Relationship Id="rIdFont{}" Type="{}" Target="fonts/font{}.odttf" />"#,
+            idx + 1,
+            FONT_RELATIONSHIP,
+            idx + 1
+        ));
+    }
+    rels.push_str("</Relationships>");
+    rels
+}
+
+/// Pack `xml`, embedding the fonts called `names`
+pub(crate) fn pack_with_fonts<'a>(
+    mut xml: XMLDocx,
+    names: impl IntoIterator<Item = &'a str>,
+) -> Result<Vec<u8>, DocumentError> {
+    let packaging = |e: zip::result::ZipError| DocumentError::DocxPackaging(e.to_string());
+    let fonts = collect(names);
+    if !fonts.is_empty() {
+        patch_parts(&mut xml, &fonts)?;
+    }
+
+    let mut buffer = Cursor::new(Vec::new());
+    xml.pack(&mut buffer).map_err(packaging)?;
+    if fonts.is_empty() {
+        return Ok(buffer.into_inner());
+    }
+
+    let mut zip = ZipWriter::new_append(buffer).map_err(packaging)?;
+    let options = FileOptions::default();
+    zip.start_file("word/_rels/fontTable.xml.rels", options)
+        .map_err(packaging)?;
+    zip.write_all(font_table_rels(&fonts).as_bytes())?;
+    for (idx, font) in fonts.iter().enumerate() {
+        zip.start_file(font_path(idx), options).map_err(packaging)?;
+        zip.write_all(&font.data)?;
+    }
+    Ok(zip.finish().map_err(packaging)?.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_obfuscate_roundtrip() {
+        let key = font_key("Some Font");
+        // Pinned, so a change to the hash shows up as a change to every export
+        assert_eq!(key, "{3F5F5D7F-76C7-9EEA-673D-7A4FEC8D9017}");
+        let data: Vec<u8> = (0..64).collect();
+        let obfuscated = obfuscate(data.clone(), &key);
+        assert_ne!(obfuscated[..32], data[..32]);
+        assert_eq!(obfuscated[32..], data[32..]);
+        assert_eq!(obfuscate(obfuscated, &key), data);
+    }
+
+    fn font(name: &str) -> EmbeddedFont {
+        EmbeddedFont {
+            name: name.to_string(),
+            key: font_key(name),
+            data: Vec::new(),
+        }
+    }
+
+    /// Fails when docx-rs changes the parts the embedding is anchored on
+    #[test]
+    fn test_patch_parts_anchors() {
+        let mut xml = docx_rs::Docx::new().build();
+        let fonts = [font("Arial"), font("A&B \"Sans\"")];
+        patch_parts(&mut xml, &fonts).unwrap();
+
+        let table = String::from_utf8_lossy(&xml.font_table);
+        let arial = table.find(r#"<w:font w:name="Arial">"#).unwrap();
+        let embed = table.find(r#"<w:embedRegular r:id="rIdFont1""#).unwrap();
+        assert!(embed > arial && embed < arial + table[arial..].find("</w:font>").unwrap());
+        assert!(table.contains(
+            r#"<w:font w:name="A&amp;B &quot;Sans&quot;"><w:embedRegular r:id="rIdFont2""#
+        ));
+        assert!(String::from_utf8_lossy(&xml.settings).contains("<w:embedTrueTypeFonts />"));
+        assert!(String::from_utf8_lossy(&xml.content_type).contains(r#"Extension="odttf""#));
+
+        xml.settings = b"<w:settings />".to_vec();
+        assert!(matches!(
+            patch_parts(&mut xml, &fonts),
+            Err(DocumentError::DocxPatch(_))
+        ));
+    }
+}
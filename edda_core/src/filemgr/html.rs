@@ -0,0 +1,390 @@
+//! Minimal HTML reader used by `Document::from_html`. It understands the markup `to_html` emits
+//! plus the common inline tags found in clipboard HTML. Unknown tags are dropped, keeping their text
+use crate::stylemgr::{
+    structural::StyledParagraph,
+    style::{Style, UnderlineStyle},
+    text::StyledText,
+};
+
+use super::document::DocumentError;
+
+/// Elements whose content is never document text
+const SKIPPED: [&str; 4] = ["head", "title", "script", "style"];
+
+pub(crate) fn parse(html: &str) -> Result<Vec<StyledParagraph>, DocumentError> {
+    let mut paragraphs = Vec::new();
+    let mut current = StyledParagraph::new();
+    let mut in_paragraph = false;
+    // Open inline elements and the style in effect inside each of them
+    let mut stack: Vec<(String, Style)> = Vec::new();
+    let mut pos = 0;
+
+    while pos < html.len() {
+        let rest = &html[pos..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            pos += 4 + comment.find("-->").map_or(comment.len(), |end| end + 3);
+            continue;
+        }
+        if !rest.starts_with('<') {
+            let end = rest.find('<').unwrap_or(rest.len());
+            let text = collapse_whitespace(&decode_entities(&rest[..end]));
+            pos += end;
+            if !in_paragraph && text.trim().is_empty() {
+                continue;
+            }
+            in_paragraph = true;
+            let text = if current.is_empty() {
+                text.trim_start()
+            } else {
+                &text
+            };
+            if !text.is_empty() {
+                let style = stack.last().map_or_else(Style::new, |(_, s)| s.clone());
+                current.add(StyledText::new(text.to_string(), style));
+            }
+            continue;
+        }
+
+        let end = rest
+            .find('>')
+            .ok_or_else(|| DocumentError::Html(format!("Unterminated tag at byte {}", pos)))?;
+        let tag = &rest[1..end];
+        pos += end + 1;
+        let closing = tag.starts_with('/');
+        let tag = tag.trim_start_matches('/').trim_end_matches('/');
+        let (name, attributes) = tag
+            .split_once(|c: char| c.is_ascii_whitespace())
+            .unwrap_or((tag, ""));
+        let name = name.to_ascii_lowercase();
+
+        if !closing && SKIPPED.contains(&name.as_str()) {
+            let close = format!("</{}", name);
+            pos += html[pos..]
+                .to_ascii_lowercase()
+                .find(&close)
+                .unwrap_or(html.len() - pos);
+            continue;
+        }
+
+        match (name.as_str(), closing) {
+            ("p", false) => {
+                if in_paragraph {
+                    paragraphs.push(finish(&mut current));
+                }
+                in_paragraph = true;
+            }
+            ("p", true) if in_paragraph => {
+                paragraphs.push(finish(&mut current));
+                in_paragraph = false;
+            }
+            ("p", true) => {}
+            ("br", _) => {
+                let style = stack.last().map_or_else(Style::new, |(_, s)| s.clone());
+                current.add(StyledText::line_break(style));
+                in_paragraph = true;
+            }
+            (_, true) => {
+                if let Some(idx) = stack.iter().rposition(|(open, _)| *open == name) {
+                    stack.truncate(idx);
+                }
+            }
+            (_, false) => {
+                let parent = stack.last().map_or_else(Style::new, |(_, s)| s.clone());
+                if let Some(style) = inline_style(&name, attributes, parent) {
+                    stack.push((name, style));
+                }
+            }
+        }
+    }
+
+    if in_paragraph {
+        paragraphs.push(finish(&mut current));
+    }
+    Ok(paragraphs)
+}
+
+/// Close the paragraph being built and return it, leaving a fresh one in its place
+fn finish(current: &mut StyledParagraph) -> StyledParagraph {
+    let mut paragraph = std::mem::take(current);
+    if let Some(last) = paragraph.chunks_mut().last_mut() {
+        last.text.truncate(last.text.trim_end_matches(' ').len());
+    }
+    paragraph.normalize();
+    paragraph
+}
+
+/// Style inside an inline element, or None when the element carries no formatting
+fn inline_style(name: &str, attributes: &str, mut style: Style) -> Option<Style> {
+    match name {
+        "b" | "strong" if !style.bold() => style = style.switch_bold(),
+        "i" | "em" if !style.italic() => style = style.switch_italic(),
+        "b" | "strong" | "i" | "em" => {}
+        "u" => style = style.set_underline(Some(UnderlineStyle::Single)),
+        "span" => {
+            if let Some(css) = attribute(attributes, "style") {
+                style = apply_css(style, &css);
+            }
+        }
+        _ => return None,
+    }
+    Some(style)
+}
+
+/// Value of an attribute in the raw attribute list of a tag
+fn attribute(attributes: &str, key: &str) -> Option<String> {
+    let lower = attributes.to_ascii_lowercase();
+    let mut from = 0;
+    while let Some(found) = lower[from..].find(key) {
+        let start = from + found;
+        from = start + key.len();
+        let boundary = start == 0 || lower.as_bytes()[start - 1].is_ascii_whitespace();
+        let rest = attributes[from..].trim_start();
+        if !boundary || !rest.starts_with('=') {
+            continue;
+        }
+        let rest = rest[1..].trim_start();
+        let value = match rest.chars().next() {
+            Some(quote @ ('"' | '\'')) => rest[1..].split(quote).next().unwrap_or(""),
+            _ => rest
+                .split(|c: char| c.is_ascii_whitespace())
+                .next()
+                .unwrap_or(""),
+        };
+        return Some(decode_entities(value));
+    }
+    None
+}
+
+/// Apply the CSS declarations Edda understands, ignoring the rest
+fn apply_css(mut style: Style, css: &str) -> Style {
+    let mut underline = false;
+    let mut decoration_style = None;
+    for declaration in css.split(';') {
+        let Some((property, value)) = declaration.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match property.trim().to_ascii_lowercase().as_str() {
+            "font-weight" if (value == "bold" || value == "700") && !style.bold() => {
+                style = style.switch_bold()
+            }
+            "font-style" if value == "italic" && !style.italic() => style = style.switch_italic(),
+            "text-decoration" | "text-decoration-line" if value.contains("underline") => {
+                underline = true;
+                // The shorthand may carry the style too
+                if let Some(kind) = value.split_whitespace().find_map(css_underline) {
+                    decoration_style = Some(kind);
+                }
+            }
+            "text-decoration-style" => decoration_style = css_underline(value),
+            "font-size" => {
+                if let Some(size) = css_size(value) {
+                    style = style.change_size(size);
+                }
+            }
+            "font-family" => {
+                let family = value.split(',').next().unwrap_or("");
+                let family = family.trim().trim_matches(|c| c == '\'' || c == '"');
+                // Fonts that aren't installed are left as they were
+                if let Ok(changed) = style.clone().change_font(family.to_string()) {
+                    style = changed;
+                }
+            }
+            "color" => {
+                if let Some(color) = css_hex(value)
+                    && let Ok(changed) = style.clone().change_font_color(color)
+                {
+                    style = changed;
+                }
+            }
+            "background" | "background-color" => {
+                if let Some(color) = css_hex(value)
+                    && let Ok(changed) = style.clone().change_font_highlight(Some(color))
+                {
+                    style = changed;
+                }
+            }
+            _ => {}
+        }
+    }
+    // A decoration style alone only changes an underline the element already has
+    if underline || style.underline().is_some() {
+        let kind = decoration_style.or(style.underline().cloned());
+        style = style.set_underline(Some(kind.unwrap_or(UnderlineStyle::Single)));
+    }
+    style
+}
+
+/// Underline for a CSS `text-decoration-style` keyword, read through its docx name
+fn css_underline(keyword: &str) -> Option<UnderlineStyle> {
+    let name = match keyword.to_ascii_lowercase().as_str() {
+        "solid" => "single",
+        "double" => "double",
+        "dotted" => "dotted",
+        "dashed" => "dash",
+        "wavy" => "wave",
+        _ => return None,
+    };
+    UnderlineStyle::parse_optional(name)
+}
+
+/// Font size in points from a CSS length (`pt` or `px`)
+fn css_size(value: &str) -> Option<u8> {
+    let points = if let Some(pt) = value.strip_suffix("pt") {
+        pt.trim().parse::<f64>().ok()?
+    } else {
+        value.strip_suffix("px")?.trim().parse::<f64>().ok()? * 0.75
+    };
+    Some(points.round().clamp(1.0, u8::MAX as f64) as u8)
+}
+
+/// CSS color as Edda hex. Supports `#rgb`, `#rrggbb`, `#rrggbbaa` and `rgb()`/`rgba()`
+fn css_hex(value: &str) -> Option<String> {
+    if let Some(hex) = value.strip_prefix('#') {
+        return match hex.len() {
+            3 => Some(hex.chars().fold("#".to_string(), |mut acc, c| {
+                acc.push(c);
+                acc.push(c);
+                acc
+            })),
+            6 | 8 => Some(value.to_string()),
+            _ => None,
+        };
+    }
+    let args = value
+        .strip_prefix("rgba(")
+        .or_else(|| value.strip_prefix("rgb("))?
+        .strip_suffix(')')?;
+    let parts: Vec<&str> = args.split(',').map(str::trim).collect();
+    let channel = |s: &str| s.parse::<u8>().ok();
+    let mut hex = format!(
+        "#{:02x}{:02x}{:02x}",
+        channel(parts.first()?)?,
+        channel(parts.get(1)?)?,
+        channel(parts.get(2)?)?
+    );
+    if let Some(alpha) = parts.get(3) {
+        let alpha = alpha.parse::<f64>().ok()?.clamp(0.0, 1.0);
+        hex.push_str(&format!("{:02x}", (alpha * 255.0).round() as u8));
+    }
+    Some(hex)
+}
+
+/// Collapse whitespace runs into a single space like a browser would
+fn collapse_whitespace(text: &str) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    let mut in_space = false;
+    for c in text.chars() {
+        if c.is_ascii_whitespace() {
+            if !in_space {
+                collapsed.push(' ');
+            }
+            in_space = true;
+        } else {
+            collapsed.push(c);
+            in_space = false;
+        }
+    }
+    collapsed
+}
+
+/// Replace the named entities `escape_html` produces, plus `&nbsp;` and numeric references.
+/// Anything unrecognised is kept verbatim
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        decoded.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let entity = rest[1..].find(';').map(|end| &rest[1..end + 1]);
+        let c = entity.and_then(|e| match e {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            "ndash" => Some('–'),
+            "mdash" => Some('—'),
+            "hellip" => Some('…'),
+            "lsquo" => Some('‘'),
+            "rsquo" => Some('’'),
+            "ldquo" => Some('“'),
+            "rdquo" => Some('”'),
+            _ => {
+                let code = e.strip_prefix('#')?;
+                let value = match code.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                    None => code.parse().ok()?,
+                };
+                char::from_u32(value)
+            }
+        });
+        match (c, entity) {
+            (Some(c), Some(e)) => {
+                decoded.push(c);
+                rest = &rest[e.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_css_hex() {
+        assert_eq!(css_hex("#abc").as_deref(), Some("#aabbcc"));
+        assert_eq!(css_hex("rgb(255, 0, 16)").as_deref(), Some("#ff0010"));
+        assert_eq!(
+            css_hex("rgba(255,255,0,0.50)").as_deref(),
+            Some("#ffff0080")
+        );
+        assert_eq!(css_hex("red"), None);
+    }
+
+    #[test]
+    fn test_decode_entities() {
+        assert_eq!(
+            decode_entities("a &amp; b &lt;3 &#65;&#x42;"),
+            "a & b <3 AB"
+        );
+        assert_eq!(decode_entities("AT&T &bogus; &"), "AT&T &bogus; &");
+    }
+
+    #[test]
+    fn test_css_underline_kinds() {
+        let underline = |style: Style, css: &str| apply_css(style, css).underline().cloned();
+        assert_eq!(
+            underline(Style::new(), "text-decoration: underline"),
+            Some(UnderlineStyle::Single)
+        );
+        assert_eq!(
+            underline(Style::new(), "text-decoration: underline wavy red"),
+            Some(UnderlineStyle::Wave)
+        );
+        assert_eq!(
+            underline(
+                Style::new(),
+                "text-decoration-style: dashed; text-decoration-line: underline"
+            ),
+            Some(UnderlineStyle::Dash)
+        );
+        assert_eq!(
+            underline(Style::new(), "text-decoration-style: dotted"),
+            None
+        );
+        let u = Style::new().set_underline(Some(UnderlineStyle::Single));
+        assert_eq!(
+            underline(u, "text-decoration-style: double"),
+            Some(UnderlineStyle::Double)
+        );
+    }
+}
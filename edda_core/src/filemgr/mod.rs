@@ -1 +1,6 @@
 pub mod document;
+mod docx_reader;
+mod embed;
+#[cfg(feature = "html")]
+mod html;
+mod xml_patch;
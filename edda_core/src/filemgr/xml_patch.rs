@@ -0,0 +1,73 @@
+//! Edits to the XML built by docx-rs, for the parts of the format it can't write. Every edit is
+//! anchored on a fixed string of docx-rs's output and fails with `DocumentError::DocxPatch` when
+//! the anchor is missing, so a change in that output can't silently drop what was patched in
+use super::document::DocumentError;
+
+/// Escape text for use in XML content or a quoted attribute value
+pub(crate) fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Byte offset of the first `anchor` in `xml` at or after `from`
+pub(crate) fn find(xml: &str, anchor: &str, from: usize) -> Result<usize, DocumentError> {
+    xml.get(from..)
+        .and_then(|rest| rest.find(anchor))
+        .map(|at| from + at)
+        .ok_or_else(|| DocumentError::DocxPatch(anchor.to_string()))
+}
+
+/// Insert `insert` right before the first `anchor`
+pub(crate) fn insert_before(
+    xml: &mut String,
+    anchor: &str,
+    insert: &str,
+) -> Result<(), DocumentError> {
+    let at = find(xml, anchor, 0)?;
+    xml.insert_str(at, insert);
+    Ok(())
+}
+
+/// Run `edit` over a built part as text
+pub(crate) fn edit_part<F>(part: &mut Vec<u8>, edit: F) -> Result<(), DocumentError>
+where
+    F: FnOnce(&mut String) -> Result<(), DocumentError>,
+{
+    let mut xml = String::from_utf8_lossy(part).into_owned();
+    edit(&mut xml)?;
+    *part = xml.into_bytes();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_anchor_is_an_error() {
+        let mut xml = String::from("<a><b /></a>");
+        insert_before(&mut xml, "</a>", "<c />").unwrap();
+        assert_eq!(xml, "<a><b /><c /></a>");
+
+        let result = insert_before(&mut xml, "<d />", "<e />");
+        assert!(matches!(result, Err(DocumentError::DocxPatch(anchor)) if anchor == "<d />"));
+        assert_eq!(xml, "<a><b /><c /></a>");
+        assert_eq!(find(&xml, "<b />", 0).unwrap(), 3);
+        assert!(find(&xml, "<b />", 4).is_err());
+    }
+
+    #[test]
+    fn test_escape_xml() {
+        assert_eq!(escape_xml(r#"a<b>&"c'"#), "a&lt;b&gt;&amp;&quot;c&apos;");
+    }
+}
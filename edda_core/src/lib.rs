@@ -1,2 +1,3 @@
 pub mod filemgr;
+pub mod logs;
 pub mod stylemgr;
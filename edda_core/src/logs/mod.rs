@@ -0,0 +1,416 @@
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Default size a log file can reach before being rotated (5 MB)
+pub const DEFAULT_MAX_SIZE: u64 = 5 * 1024 * 1024;
+/// Default amount of rotated files kept around
+pub const DEFAULT_MAX_FILES: usize = 3;
+
+static LOGGER: LazyLock<Mutex<Logger>> = LazyLock::new(|| Mutex::new(Logger::from_env()));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Info,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Level::Info => "INFO",
+                Level::Warning => "WARN",
+                Level::Error => "ERROR",
+            }
+        )
+    }
+}
+
+/// Append-only log file with size based rotation. Once the file reaches `max_size` it is
+/// renamed to `<path>.1` (shifting older ones to `.2`, `.3`...) and a fresh file is started.
+/// Only `max_files` rotated files are kept.
+///
+/// Nothing is buffered: every line is written to the file before `write` returns, so a crash
+/// loses nothing that was logged. `flush` additionally asks the OS to put it on disk.
+#[derive(Debug, Clone)]
+pub struct Logger {
+    path: PathBuf,
+    max_size: u64,
+    max_files: usize,
+    /// Write structured entries as JSON objects instead of `key=value` pairs
+    json: bool,
+}
+
+impl Logger {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            max_size: DEFAULT_MAX_SIZE,
+            max_files: DEFAULT_MAX_FILES,
+            json: false,
+        }
+    }
+
+    /// Logger configured through `EDDA_LOG_PATH`, `EDDA_LOG_MAX_SIZE` (bytes),
+    /// `EDDA_LOG_MAX_FILES` and `EDDA_LOG_JSON` (any value but "0"), falling back to the defaults
+    pub fn from_env() -> Self {
+        let path = std::env::var_os("EDDA_LOG_PATH")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| std::env::temp_dir().join("edda.log"));
+        let mut logger = Logger::new(path);
+        if let Some(size) = env_number("EDDA_LOG_MAX_SIZE") {
+            logger = logger.max_size(size);
+        }
+        if let Some(files) = env_number("EDDA_LOG_MAX_FILES") {
+            logger = logger.max_files(files as usize);
+        }
+        if let Some(json) = std::env::var_os("EDDA_LOG_JSON") {
+            logger = logger.json(json != "0");
+        }
+        logger
+    }
+
+    pub fn max_size(mut self, bytes: u64) -> Self {
+        self.max_size = bytes;
+        self
+    }
+
+    pub fn max_files(mut self, files: usize) -> Self {
+        self.max_files = files;
+        self
+    }
+
+    pub fn json(mut self, enabled: bool) -> Self {
+        self.json = enabled;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn write(&self, level: Level, message: &str) -> io::Result<()> {
+        self.append_line(&format!("[{}] [{}] {}", timestamp(), level, message))
+    }
+
+    /// Write a line with machine-readable fields: the message followed by `key=value` pairs
+    /// (values quoted when they contain whitespace, control characters, quotes or `=`), or a single
+    /// JSON object when the logger is in JSON mode. The message and values are escaped like JSON
+    /// strings in both modes, so an entry stays on one line
+    pub fn write_structured(
+        &self,
+        level: Level,
+        message: &str,
+        fields: &[(&str, &str)],
+    ) -> io::Result<()> {
+        if !self.json {
+            let mut line = escape_json(message);
+            for (key, value) in fields {
+                line.push_str(&format!(" {}={}", key, quote_value(value)));
+            }
+            return self.write(level, &line);
+        }
+
+        let mut line = format!(
+            "{{\"timestamp\":{},\"level\":\"{}\",\"message\":\"{}\"",
+            timestamp(),
+            level,
+            escape_json(message)
+        );
+        for (key, value) in fields {
+            line.push_str(&format!(
+                ",\"{}\":\"{}\"",
+                escape_json(key),
+                escape_json(value)
+            ));
+        }
+        line.push('}');
+        self.append_line(&line)
+    }
+
+    fn append_line(&self, line: &str) -> io::Result<()> {
+        self.rotate_if_needed()?;
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", line)
+    }
+
+    /// Make sure the logged lines reach the disk, e.g. right before exiting. Lines are already in
+    /// the file after `write`; this syncs it so they also survive a power loss or OS crash
+    pub fn flush(&self) -> io::Result<()> {
+        match OpenOptions::new().append(true).open(&self.path) {
+            Ok(file) => file.sync_data(),
+            // Nothing logged yet
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn rotated_path(&self, n: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    fn rotate_if_needed(&self) -> io::Result<()> {
+        let size = match fs::metadata(&self.path) {
+            Ok(metadata) => metadata.len(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        if size < self.max_size {
+            return Ok(());
+        }
+
+        if self.max_files == 0 {
+            return fs::remove_file(&self.path);
+        }
+        let oldest = self.rotated_path(self.max_files);
+        if oldest.exists() {
+            fs::remove_file(oldest)?;
+        }
+        for n in (1..self.max_files).rev() {
+            let from = self.rotated_path(n);
+            if from.exists() {
+                fs::rename(from, self.rotated_path(n + 1))?;
+            }
+        }
+        fs::rename(&self.path, self.rotated_path(1))
+    }
+}
+
+fn env_number(key: &str) -> Option<u64> {
+    std::env::var(key).ok()?.parse().ok()
+}
+
+/// Seconds since the Unix epoch
+fn timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn quote_value(value: &str) -> String {
+    let needs_quotes = |c: char| c.is_whitespace() || c.is_control() || c == '"' || c == '=';
+    if value.is_empty() || value.contains(needs_quotes) {
+        format!("\"{}\"", escape_json(value))
+    } else {
+        value.to_string()
+    }
+}
+
+fn escape_json(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Write a line to the global log
+pub fn write(level: Level, message: &str) -> io::Result<()> {
+    lock().write(level, message)
+}
+
+/// Write a line with key-value fields to the global log, see `Logger::write_structured`
+pub fn write_structured(level: Level, message: &str, fields: &[(&str, &str)]) -> io::Result<()> {
+    lock().write_structured(level, message, fields)
+}
+
+/// Switch the global log between `key=value` and JSON output for structured entries
+pub fn set_json(enabled: bool) {
+    let mut logger = lock();
+    *logger = logger.clone().json(enabled);
+}
+
+/// Sync the global log to disk, see `Logger::flush`
+pub fn flush() -> io::Result<()> {
+    lock().flush()
+}
+
+/// Change the size at which the global log is rotated
+pub fn set_max_size(bytes: u64) {
+    let mut logger = lock();
+    *logger = logger.clone().max_size(bytes);
+}
+
+/// Change how many rotated files the global log keeps
+pub fn set_max_files(files: usize) {
+    let mut logger = lock();
+    *logger = logger.clone().max_files(files);
+}
+
+fn lock() -> std::sync::MutexGuard<'static, Logger> {
+    // A panic while holding the lock can't leave the logger in a broken state, so keep going
+    LOGGER.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Log a formatted message to the global log, ignoring write failures
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $($arg:tt)*) => {{
+        let _ = $crate::logs::write($level, &format!($($arg)*));
+    }};
+}
+
+/// Log a message with `key => value` fields to the global log, ignoring write failures.
+/// Values can be anything implementing `Display`
+#[macro_export]
+macro_rules! log_kv {
+    ($level:expr, $message:expr $(, $key:expr => $value:expr)* $(,)?) => {{
+        let _ = $crate::logs::write_structured(
+            $level,
+            &$message,
+            &[$(($key, $value.to_string().as_str())),*],
+        );
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_logger(name: &str) -> Logger {
+        let path = std::env::temp_dir().join(format!("edda_test_{}.log", name));
+        let logger = Logger::new(&path);
+        let _ = fs::remove_file(&path);
+        for n in 1..=5 {
+            let _ = fs::remove_file(logger.rotated_path(n));
+        }
+        logger
+    }
+
+    #[test]
+    fn test_write_appends() {
+        let logger = test_logger("append");
+        logger.write(Level::Info, "first").unwrap();
+        logger.write(Level::Error, "second").unwrap();
+
+        let content = fs::read_to_string(logger.path()).unwrap();
+        assert_eq!(content.lines().count(), 2);
+        assert!(content.contains("[INFO] first"));
+        assert!(content.contains("[ERROR] second"));
+        fs::remove_file(logger.path()).unwrap();
+    }
+
+    #[test]
+    fn test_write_structured() {
+        let logger = test_logger("structured");
+        logger
+            .write_structured(
+                Level::Info,
+                "Saved",
+                &[
+                    ("path", "/tmp/a b.docx"),
+                    ("bytes", "2048"),
+                    ("reason", "disk\r\nfull \"now\""),
+                ],
+            )
+            .unwrap();
+        let content = fs::read_to_string(logger.path()).unwrap();
+        assert!(content.contains("[INFO] Saved"));
+        assert!(content.contains(r#" path="/tmp/a b.docx""#));
+        assert!(content.contains(" bytes=2048 "));
+        // A value can't start a new entry
+        assert!(
+            content
+                .trim_end()
+                .ends_with(r#" reason="disk\r\nfull \"now\"""#)
+        );
+        assert_eq!(content.lines().count(), 1);
+        fs::remove_file(logger.path()).unwrap();
+
+        // Neither can the message
+        logger
+            .write_structured(Level::Warning, "Retrying\nsave", &[("try", "2")])
+            .unwrap();
+        let content = fs::read_to_string(logger.path()).unwrap();
+        assert!(content.contains(r#"Retrying\nsave try=2"#));
+        assert_eq!(content.lines().count(), 1);
+        fs::remove_file(logger.path()).unwrap();
+
+        let logger = logger.json(true);
+        logger
+            .write_structured(Level::Error, "Failed \"save\"", &[("reason", "disk\nfull")])
+            .unwrap();
+        let content = fs::read_to_string(logger.path()).unwrap();
+        assert!(content.starts_with(r#"{"timestamp":"#));
+        assert!(content.contains(r#""level":"ERROR","message":"Failed \"save\"""#));
+        assert!(content.contains(r#""reason":"disk\nfull"}"#));
+        assert_eq!(content.lines().count(), 1);
+        fs::remove_file(logger.path()).unwrap();
+    }
+
+    #[test]
+    fn test_flush() {
+        let logger = test_logger("flush");
+        // Flushing before anything was logged is fine
+        logger.flush().unwrap();
+
+        logger.write(Level::Info, "persisted").unwrap();
+        logger.flush().unwrap();
+        let content = fs::read_to_string(logger.path()).unwrap();
+        assert!(content.ends_with("[INFO] persisted\n"));
+        fs::remove_file(logger.path()).unwrap();
+    }
+
+    #[test]
+    fn test_rotation_past_limit() {
+        let logger = test_logger("rotate").max_size(10).max_files(2);
+        logger
+            .write(Level::Info, "this line is over ten bytes")
+            .unwrap();
+        assert!(!logger.rotated_path(1).exists());
+
+        logger.write(Level::Info, "second").unwrap();
+        assert!(logger.rotated_path(1).exists());
+        let current = fs::read_to_string(logger.path()).unwrap();
+        assert!(current.contains("second"));
+        assert!(!current.contains("over ten bytes"));
+
+        for n in 1..=2 {
+            let _ = fs::remove_file(logger.rotated_path(n));
+        }
+        fs::remove_file(logger.path()).unwrap();
+    }
+
+    #[test]
+    fn test_rotation_retention() {
+        let logger = test_logger("retention").max_size(1).max_files(2);
+        for n in 0..5 {
+            logger
+                .write(Level::Warning, &format!("line {}", n))
+                .unwrap();
+        }
+
+        assert!(logger.rotated_path(1).exists());
+        assert!(logger.rotated_path(2).exists());
+        assert!(!logger.rotated_path(3).exists());
+        let newest_rotated = fs::read_to_string(logger.rotated_path(1)).unwrap();
+        assert!(newest_rotated.contains("line 3"));
+
+        for n in 1..=2 {
+            fs::remove_file(logger.rotated_path(n)).unwrap();
+        }
+        fs::remove_file(logger.path()).unwrap();
+    }
+}
@@ -0,0 +1,240 @@
+//! Embedded images. The bytes are checked on construction, so an `Image` always decodes
+use docx_rs::{Paragraph, Pic, Run};
+use thiserror::Error;
+
+use super::text::escape_html;
+
+/// EMUs (the docx drawing unit) per pixel at 96 DPI
+const EMU_PER_PX: u32 = 9_525;
+
+#[derive(Debug, Error)]
+pub enum ImageError {
+    #[error("Unsupported image format, expected PNG, JPEG or GIF")]
+    UnsupportedFormat,
+    #[error("Failed to decode image: {0}")]
+    Decode(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    Gif,
+}
+
+impl ImageFormat {
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "image/png",
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::Gif => "image/gif",
+        }
+    }
+}
+
+/// Image block. `width` and `height` are the display size in pixels, the intrinsic size unless
+/// changed with `set_size`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "SerializedImage"))]
+pub struct Image {
+    data: Vec<u8>,
+    format: ImageFormat,
+    width: u32,
+    height: u32,
+    alt_text: Option<String>,
+}
+
+impl Image {
+    /// Wrap encoded image bytes, checking that they are a PNG, JPEG or GIF that can be decoded
+    pub fn new(data: Vec<u8>) -> Result<Self, ImageError> {
+        let format = match ::image::guess_format(&data) {
+            Ok(::image::ImageFormat::Png) => ImageFormat::Png,
+            Ok(::image::ImageFormat::Jpeg) => ImageFormat::Jpeg,
+            Ok(::image::ImageFormat::Gif) => ImageFormat::Gif,
+            _ => return Err(ImageError::UnsupportedFormat),
+        };
+        let decoded =
+            ::image::load_from_memory(&data).map_err(|e| ImageError::Decode(e.to_string()))?;
+        Ok(Image {
+            data,
+            format,
+            width: decoded.width(),
+            height: decoded.height(),
+            alt_text: None,
+        })
+    }
+
+    pub fn set_size(mut self, width: u32, height: u32) -> Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    pub fn set_alt_text(mut self, alt_text: Option<String>) -> Self {
+        self.alt_text = alt_text;
+        self
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn format(&self) -> ImageFormat {
+        self.format
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn alt_text(&self) -> Option<&str> {
+        self.alt_text.as_deref()
+    }
+
+    /// `data:` URL with the image bytes, for HTML and Markdown export
+    pub fn data_url(&self) -> String {
+        format!(
+            "data:{};base64,{}",
+            self.format.mime_type(),
+            base64(&self.data)
+        )
+    }
+
+    pub fn to_html(&self) -> String {
+        format!(
+            "<img src=\"{}\" alt=\"{}\" width=\"{}\" height=\"{}\">",
+            self.data_url(),
+            escape_html(self.alt_text().unwrap_or("")),
+            self.width,
+            self.height
+        )
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let alt = self.alt_text().unwrap_or("").replace(['[', ']'], "");
+        format!("![{}]({})", alt, self.data_url())
+    }
+
+    /// Docx paragraph holding the image inline. docx-rs stores every image as PNG and has no
+    /// alt text attribute, so `Document` patches the description in after building
+    pub fn apply_to_raw(&self) -> Paragraph {
+        let pic = Pic::new(&self.data).size(
+            self.width.saturating_mul(EMU_PER_PX),
+            self.height.saturating_mul(EMU_PER_PX),
+        );
+        Paragraph::new().add_run(Run::new().add_image(pic))
+    }
+}
+
+/// Fields of a serialized `Image`. Deserializing goes through `Image::new` so the bytes are checked
+/// like any other image; the format is worked out again from them
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct SerializedImage {
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+    alt_text: Option<String>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<SerializedImage> for Image {
+    type Error = ImageError;
+
+    fn try_from(image: SerializedImage) -> Result<Self, Self::Error> {
+        Ok(Image::new(image.data)?
+            .set_size(image.width, image.height)
+            .set_alt_text(image.alt_text))
+    }
+}
+
+/// Standard base64 with padding
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for group in data.chunks(3) {
+        let bytes = [
+            group[0],
+            *group.get(1).unwrap_or(&0),
+            *group.get(2).unwrap_or(&0),
+        ];
+        let n = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= group.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// 2x1 RGB PNG: one red and one black pixel
+    pub(crate) const PNG: [u8; 70] = [
+        0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44,
+        0x52, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x01, 0x08, 0x02, 0x00, 0x00, 0x00, 0x7b,
+        0x40, 0xe8, 0xdd, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9c, 0x63, 0xf8,
+        0xcf, 0x00, 0x04, 0xff, 0x01, 0x07, 0x00, 0x01, 0xff, 0xe2, 0x23, 0x9e, 0x59, 0x00, 0x00,
+        0x00, 0x00, 0x49, 0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+    ];
+
+    #[test]
+    fn test_image_validation() {
+        let image = Image::new(PNG.to_vec()).unwrap();
+        assert_eq!(image.format(), ImageFormat::Png);
+        assert_eq!((image.width(), image.height()), (2, 1));
+
+        assert!(matches!(
+            Image::new(b"not an image".to_vec()),
+            Err(ImageError::UnsupportedFormat)
+        ));
+        // Right signature, truncated body
+        assert!(matches!(
+            Image::new(PNG[..20].to_vec()),
+            Err(ImageError::Decode(_))
+        ));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_validates_data() {
+        let image = Image::new(PNG.to_vec())
+            .unwrap()
+            .set_size(20, 10)
+            .set_alt_text(Some("Dot".to_string()));
+        let json = serde_json::to_string(&image).unwrap();
+        assert_eq!(serde_json::from_str::<Image>(&json).unwrap(), image);
+
+        let corrupt = json.replacen("[137,80,78,71", "[0,0,0,0", 1);
+        assert!(serde_json::from_str::<Image>(&corrupt).is_err());
+    }
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn test_image_html() {
+        let image = Image::new(PNG.to_vec())
+            .unwrap()
+            .set_alt_text(Some("A \"red\" dot".to_string()));
+        let html = image.to_html();
+        assert!(html.starts_with("<img src=\"data:image/png;base64,iVBORw0KGgo"));
+        assert!(html.ends_with("alt=\"A &quot;red&quot; dot\" width=\"2\" height=\"1\">"));
+    }
+}
@@ -1,3 +1,6 @@
+pub mod image;
+pub mod palette;
 pub mod structural;
 pub mod style;
+pub mod table;
 pub mod text;
@@ -0,0 +1,213 @@
+//! Named styles ("Heading 1", "Body", ...) that can be applied to whole paragraphs
+use super::{
+    structural::{Indentation, ParagraphModifyError, ParagraphStyle, StyledParagraph},
+    style::{Style, StyleOverride},
+};
+
+/// A named style. `paragraph` is only applied when set, so a preset can change the text style
+/// without touching lists or indentation
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StylePreset {
+    pub name: String,
+    pub style: Style,
+    pub paragraph: Option<ParagraphStyle>,
+}
+
+/// Ordered set of presets, in the order they should be offered to the user
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StylePalette {
+    presets: Vec<StylePreset>,
+}
+
+impl Default for StylePalette {
+    /// Palette with the built-in presets: Heading 1, Heading 2, Body and Quote
+    fn default() -> Self {
+        let quote_color = StyleOverride {
+            font_color: Some("#555555".to_string()),
+            ..StyleOverride::default()
+        };
+        let quote_indent = Indentation {
+            left: 720,
+            right: 720,
+            first_line: 0,
+        };
+        StylePalette::new()
+            .define(
+                "Heading 1",
+                Style::new().switch_bold().change_size(20),
+                None,
+            )
+            .define(
+                "Heading 2",
+                Style::new().switch_bold().change_size(16),
+                None,
+            )
+            .define("Body", Style::new(), None)
+            .define(
+                "Quote",
+                Style::new().switch_italic().merge(&quote_color),
+                Some(ParagraphStyle::new().set_indentation(quote_indent)),
+            )
+    }
+}
+
+impl StylePalette {
+    /// Empty palette
+    pub fn new() -> Self {
+        StylePalette {
+            presets: Vec::new(),
+        }
+    }
+
+    /// Add a preset, replacing any existing one with the same name in place
+    pub fn define(
+        mut self,
+        name: impl Into<String>,
+        style: Style,
+        paragraph: Option<ParagraphStyle>,
+    ) -> Self {
+        self.set(StylePreset {
+            name: name.into(),
+            style,
+            paragraph,
+        });
+        self
+    }
+
+    /// Add or replace a preset. A replaced preset keeps its position
+    pub fn set(&mut self, preset: StylePreset) {
+        match self.presets.iter_mut().find(|p| p.name == preset.name) {
+            Some(existing) => *existing = preset,
+            None => self.presets.push(preset),
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<StylePreset> {
+        let idx = self.presets.iter().position(|p| p.name == name)?;
+        Some(self.presets.remove(idx))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&StylePreset> {
+        self.presets.iter().find(|p| p.name == name)
+    }
+
+    pub fn presets(&self) -> &[StylePreset] {
+        &self.presets
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.presets.iter().map(|p| p.name.as_str())
+    }
+
+    /// Whether `paragraph` is a heading: it isn't blank and all of its text is in the style of one
+    /// of the presets whose name starts with "Heading". A predicate for `Document::headings`
+    pub fn is_heading(&self, paragraph: &StyledParagraph) -> bool {
+        let headings = self
+            .presets
+            .iter()
+            .filter(|p| p.name.starts_with("Heading"));
+        !paragraph.text().trim().is_empty()
+            && paragraph
+                .chunks()
+                .iter()
+                .filter(|st| !st.text.is_empty())
+                .all(|st| headings.clone().any(|p| &p.style == st.style()))
+    }
+
+    /// Give every chunk of `paragraph` the style of the preset called `name`, and its paragraph
+    /// style if the preset has one. The text is left untouched
+    pub fn apply_named(
+        &self,
+        paragraph: &mut StyledParagraph,
+        name: &str,
+    ) -> Result<(), ParagraphModifyError> {
+        let preset = self
+            .get(name)
+            .ok_or_else(|| ParagraphModifyError::UnknownPreset(name.to_string()))?;
+        for chunk in paragraph.chunks_mut() {
+            chunk.set_style(preset.style.clone());
+        }
+        paragraph.normalize();
+        if let Some(style) = &preset.paragraph {
+            paragraph.style = style.clone();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stylemgr::{
+        structural::{ListItem, ListKind},
+        text::StyledText,
+    };
+
+    fn sample_paragraph() -> StyledParagraph {
+        let mut p = StyledParagraph::new();
+        p.add(StyledText::new("Chapter ".to_string(), Style::new()));
+        p.add(StyledText::new(
+            "one".to_string(),
+            Style::new().switch_italic(),
+        ));
+        p
+    }
+
+    #[test]
+    fn test_apply_named() {
+        let heading = Style::new().switch_bold().change_size(24);
+        let palette = StylePalette::new().define("Heading", heading.clone(), None);
+
+        let mut p = sample_paragraph();
+        p.style = ParagraphStyle::new().set_list(Some(ListItem::new(ListKind::Number, 0)));
+        palette.apply_named(&mut p, "Heading").unwrap();
+        assert_eq!(p.text(), "Chapter one");
+        assert!(p.chunks().iter().all(|c| c.style() == &heading));
+        // Without a paragraph style in the preset, list membership is kept
+        assert!(p.style.list().is_some());
+
+        assert!(matches!(
+            palette.apply_named(&mut p, "Missing"),
+            Err(ParagraphModifyError::UnknownPreset(name)) if name == "Missing"
+        ));
+    }
+
+    #[test]
+    fn test_default_presets() {
+        let palette = StylePalette::default();
+        assert_eq!(
+            palette.names().collect::<Vec<_>>(),
+            ["Heading 1", "Heading 2", "Body", "Quote"]
+        );
+
+        let mut p = sample_paragraph();
+        palette.apply_named(&mut p, "Quote").unwrap();
+        assert_eq!(p.chunk_count(), 1);
+        assert!(p.chunks()[0].style().italic());
+        assert_eq!(p.chunks()[0].style().font_color(), "#555555");
+        assert_eq!(p.style.indentation().left, 720);
+    }
+
+    #[test]
+    fn test_define_replaces_in_place() {
+        let mut palette =
+            StylePalette::default().define("Body", Style::new().change_size(12), None);
+        assert_eq!(palette.presets().len(), 4);
+        assert_eq!(palette.presets()[2].style.size(), 12);
+
+        assert!(palette.remove("Body").is_some());
+        assert!(palette.get("Body").is_none());
+        assert!(palette.remove("Body").is_none());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_roundtrip() {
+        let palette = StylePalette::default();
+        let json = serde_json::to_string(&palette).unwrap();
+        let restored: StylePalette = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, palette);
+    }
+}
@@ -1,7 +1,17 @@
+use std::collections::HashMap;
 use std::fmt::Write;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::str::FromStr;
+use std::sync::Arc;
 
+use docx_rs::{
+    AbstractNumbering, IndentLevel, Level, LevelJc, LevelOverride, LevelText, NumberFormat,
+    Numbering, NumberingId, Paragraph, SpecialIndentType, Start,
+};
+
+use super::text::{docx_hex, intern, unescape_tagged};
 use super::{
-    style::{Style, UnderlineStyle},
+    style::{Style, StyleError, UnderlineStyle, check_hex, css_color, hex_to_rgba, rgba_to_hex},
     text::StyledText,
 };
 use thiserror::Error;
@@ -10,8 +20,48 @@ use thiserror::Error;
 pub enum ParagraphModifyError {
     #[error("Chunk to modify not found in paragraph: '{0}'")]
     ChunkNotFound(String),
+    #[error("Character range {0}..{1} is out of the paragraph bounds ({2} characters)")]
+    InvalidRange(usize, usize, usize),
+    #[error("Invalid pattern: {0}")]
+    InvalidPattern(String),
+    #[error("No style named '{0}' in the palette")]
+    UnknownPreset(String),
+    #[error("Chunk index {0} is out of bounds ({1} chunks)")]
+    IndexOutOfBounds(usize, usize),
+    #[error(transparent)]
+    Style(#[from] StyleError),
+}
+
+/// Errors from parsing a formatting command such as `size:14`
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum CommandParseError {
+    #[error("Unknown formatting command: '{0}'")]
+    UnknownCommand(String),
+    #[error("Command '{0}' needs an argument")]
+    MissingArgument(String),
+    #[error("Invalid argument for '{0}': '{1}'")]
+    InvalidArgument(String, String),
+}
+
+/// Errors from parsing the tagged-text format. Positions are byte offsets into the input
+#[derive(Debug, Error)]
+pub enum TagParseError {
+    #[error("Tag opened at byte {0} is never closed")]
+    UnclosedTag(usize),
+    #[error("Closing tag at byte {0} has no matching opening tag")]
+    UnexpectedClose(usize),
+    #[error("Tag at byte {0} does not describe a valid style: '{1}'")]
+    MalformedStyle(usize, String),
+    #[error("Tag at byte {0} exceeds the maximum tag length of {max} bytes", max = MAX_TAG_LEN)]
+    TagTooLong(usize),
 }
 
+/// Upper bound for the content of a single `[[...]]` tag. Real tags are well under this, so
+/// anything longer is treated as corrupt input instead of being scanned and parsed
+pub const MAX_TAG_LEN: usize = 512;
+
+/// A single formatting command, as emitted by a toolbar button
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ApplicableStyles {
     Bold,
     Italic,
@@ -22,87 +72,1386 @@ pub enum ApplicableStyles {
     Highlight(Option<String>),
 }
 
+impl ApplicableStyles {
+    /// Style resulting from applying the command to `style`. Toggles flip the current value
+    pub fn apply_to(&self, style: Style) -> Result<Style, StyleError> {
+        Ok(match self {
+            ApplicableStyles::Bold => style.switch_bold(),
+            ApplicableStyles::Italic => style.switch_italic(),
+            ApplicableStyles::Underline(underline) => style.set_underline(underline.clone()),
+            ApplicableStyles::Size(n) => style.change_size(*n),
+            ApplicableStyles::Font(font) => style.change_font(font.clone())?,
+            ApplicableStyles::Color(color) => style.change_font_color(color.clone())?,
+            ApplicableStyles::Highlight(color) => style.change_font_highlight(color.clone())?,
+        })
+    }
+}
+
+impl FromStr for ApplicableStyles {
+    type Err = CommandParseError;
+
+    /// Parse a command in the `name` or `name:argument` form: `bold`, `italic`,
+    /// `underline[:style|none]`, `size:14`, `font:Arial`, `color:#FF0000` and
+    /// `highlight[:#FFFF00|none]`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, argument) = match s.trim().split_once(':') {
+            Some((name, argument)) => (name.trim(), Some(argument.trim())),
+            None => (s.trim(), None),
+        };
+        let invalid = |argument: &str| {
+            CommandParseError::InvalidArgument(name.to_string(), argument.to_string())
+        };
+        let required =
+            || argument.ok_or_else(|| CommandParseError::MissingArgument(name.to_string()));
+
+        match name {
+            "bold" | "italic" => {
+                if let Some(argument) = argument {
+                    return Err(invalid(argument));
+                }
+                Ok(if name == "bold" {
+                    ApplicableStyles::Bold
+                } else {
+                    ApplicableStyles::Italic
+                })
+            }
+            "underline" => Ok(ApplicableStyles::Underline(match argument {
+                None => Some(UnderlineStyle::Single),
+                Some(argument) => UnderlineStyle::parse_optional(argument),
+            })),
+            "size" => {
+                let argument = required()?;
+                match argument.parse() {
+                    Ok(size) if size > 0 => Ok(ApplicableStyles::Size(size)),
+                    _ => Err(invalid(argument)),
+                }
+            }
+            "font" => {
+                let argument = required()?;
+                if argument.is_empty() {
+                    return Err(invalid(argument));
+                }
+                Ok(ApplicableStyles::Font(argument.to_string()))
+            }
+            "color" => {
+                let argument = required()?;
+                check_hex(argument).map_err(|_| invalid(argument))?;
+                Ok(ApplicableStyles::Color(argument.to_string()))
+            }
+            "highlight" => match argument {
+                None | Some("none") => Ok(ApplicableStyles::Highlight(None)),
+                Some(argument) => {
+                    check_hex(argument).map_err(|_| invalid(argument))?;
+                    Ok(ApplicableStyles::Highlight(Some(argument.to_string())))
+                }
+            },
+            _ => Err(CommandParseError::UnknownCommand(name.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ListKind {
+    Bullet,
+    Number,
+}
+
+impl ListKind {
+    /// Id of the docx numbering definition backing this kind of list, and of the numbering
+    /// instance `StyledParagraph::apply_to_raw` refers to
+    pub fn numbering_id(self) -> usize {
+        match self {
+            ListKind::Bullet => 1,
+            ListKind::Number => 2,
+        }
+    }
+
+    /// Docx numbering definition for this kind of list, covering every indent level
+    pub fn numbering_definition(self) -> (AbstractNumbering, Numbering) {
+        let id = self.numbering_id();
+        let mut abstract_numbering = AbstractNumbering::new(id);
+
+        for level in 0..=ListItem::MAX_LEVEL {
+            let (format, text) = match self {
+                ListKind::Bullet => ("bullet", "•".to_string()),
+                ListKind::Number => ("decimal", format!("%{}.", level + 1)),
+            };
+            abstract_numbering = abstract_numbering.add_level(
+                Level::new(
+                    level,
+                    Start::new(1),
+                    NumberFormat::new(format),
+                    LevelText::new(text),
+                    LevelJc::new("left"),
+                )
+                .indent(
+                    Some(720 * (level as i32 + 1)),
+                    Some(SpecialIndentType::Hanging(360)),
+                    None,
+                    None,
+                ),
+            );
+        }
+
+        (abstract_numbering, self.numbering(id))
+    }
+
+    /// Numbering instance `id` of this kind of list, counting from 1 at every level. Instances
+    /// sharing a definition would otherwise continue each other's count, so every list that
+    /// should start over needs its own
+    pub fn numbering(self, id: usize) -> Numbering {
+        (0..=ListItem::MAX_LEVEL).fold(Numbering::new(id, self.numbering_id()), |n, level| {
+            n.add_override(LevelOverride::new(level).start(1))
+        })
+    }
+}
+
+/// Membership of a paragraph in a list
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(from = "SerializedListItem"))]
+pub struct ListItem {
+    pub kind: ListKind,
+    level: usize,
+}
+
+impl ListItem {
+    /// Docx supports nine levels of nesting (0 to 8)
+    pub const MAX_LEVEL: usize = 8;
+
+    pub fn new(kind: ListKind, level: usize) -> Self {
+        ListItem {
+            kind,
+            level: level.min(Self::MAX_LEVEL),
+        }
+    }
+
+    /// Nesting level, at most `MAX_LEVEL`
+    pub fn level(&self) -> usize {
+        self.level
+    }
+}
+
+/// Fields of a serialized `ListItem`. Deserializing goes through `ListItem::new` so the level is
+/// clamped
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct SerializedListItem {
+    kind: ListKind,
+    level: usize,
+}
+
+#[cfg(feature = "serde")]
+impl From<SerializedListItem> for ListItem {
+    fn from(item: SerializedListItem) -> Self {
+        ListItem::new(item.kind, item.level)
+    }
+}
+
+/// Paragraph indentation in twips (1/20 pt). A negative `first_line` is a hanging indent
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Indentation {
+    pub left: i32,
+    pub right: i32,
+    pub first_line: i32,
+}
+
+impl Indentation {
+    pub fn is_zero(&self) -> bool {
+        *self == Indentation::default()
+    }
+}
+
+/// Properties that apply to a paragraph as a whole instead of to its chunks
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "SerializedParagraphStyle"))]
+pub struct ParagraphStyle {
+    list: Option<ListItem>,
+    indentation: Indentation,
+    /// Background of the whole paragraph, as opposed to the highlight of its text
+    #[cfg_attr(feature = "serde", serde(default))]
+    shading: Option<String>,
+    /// Don't let a page break split the paragraph
+    #[cfg_attr(feature = "serde", serde(default))]
+    keep_together: bool,
+    /// Start the paragraph on a new page
+    #[cfg_attr(feature = "serde", serde(default))]
+    page_break_before: bool,
+}
+
+/// Fields of a serialized `ParagraphStyle`. Deserializing goes through `set_shading` so the
+/// color is checked
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct SerializedParagraphStyle {
+    list: Option<ListItem>,
+    indentation: Indentation,
+    #[serde(default)]
+    shading: Option<String>,
+    #[serde(default)]
+    keep_together: bool,
+    #[serde(default)]
+    page_break_before: bool,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<SerializedParagraphStyle> for ParagraphStyle {
+    type Error = StyleError;
+
+    fn try_from(style: SerializedParagraphStyle) -> Result<Self, Self::Error> {
+        ParagraphStyle::new()
+            .set_list(style.list)
+            .set_indentation(style.indentation)
+            .set_keep_together(style.keep_together)
+            .set_page_break_before(style.page_break_before)
+            .set_shading(style.shading)
+    }
+}
+
+/// Tag appended to the paragraph id (`w14:paraId`), between dots and followed by the fill color.
+/// docx-rs can't shade paragraphs, so `Document` strips the tag and adds a `<w:shd>` after
+/// building. The id is used so any paragraph style set on the raw paragraph is kept
+pub(crate) const SHADING_MARKER: &str = "EddaShading";
+
+impl ParagraphStyle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_list(mut self, list: Option<ListItem>) -> Self {
+        self.list = list;
+        self
+    }
+
+    pub fn set_indentation(mut self, indentation: Indentation) -> Self {
+        self.indentation = indentation;
+        self
+    }
+
+    /// Set or clear the paragraph background. The color must be a hex code
+    pub fn set_shading(mut self, shading: Option<String>) -> Result<Self, StyleError> {
+        if let Some(color) = &shading {
+            check_hex(color)?;
+        }
+        self.shading = shading;
+        Ok(self)
+    }
+
+    pub fn set_keep_together(mut self, keep_together: bool) -> Self {
+        self.keep_together = keep_together;
+        self
+    }
+
+    pub fn set_page_break_before(mut self, page_break_before: bool) -> Self {
+        self.page_break_before = page_break_before;
+        self
+    }
+
+    pub fn list(&self) -> Option<&ListItem> {
+        self.list.as_ref()
+    }
+
+    pub fn keep_together(&self) -> bool {
+        self.keep_together
+    }
+
+    pub fn page_break_before(&self) -> bool {
+        self.page_break_before
+    }
+
+    pub fn shading(&self) -> Option<&str> {
+        self.shading.as_deref()
+    }
+
+    /// Copy with a shading that isn't a valid hex code removed, like `Style::sanitized` does for
+    /// colors
+    pub fn sanitized(&self) -> ParagraphStyle {
+        let mut style = self.clone();
+        if style
+            .shading
+            .as_deref()
+            .is_some_and(|c| check_hex(c).is_err())
+        {
+            style.shading = None;
+        }
+        style
+    }
+
+    pub fn indentation(&self) -> Indentation {
+        self.indentation
+    }
+
+    /// Indentation of the first line in tab stops (half an inch, 720 twips), rounded to the
+    /// nearest one, plus one per list nesting level. Used to indent plain-text exports
+    pub fn indent_level(&self) -> usize {
+        // Widened, as any i32 indentation is accepted
+        let first_line =
+            (i64::from(self.indentation.left) + i64::from(self.indentation.first_line)).max(0);
+        let tabs = (first_line + 360) / 720;
+        tabs as usize + self.list.as_ref().map_or(0, ListItem::level)
+    }
+
+    pub fn apply_to_raw(&self, mut paragraph: Paragraph) -> Paragraph {
+        if let Some(item) = &self.list {
+            paragraph = paragraph.numbering(
+                NumberingId::new(item.kind.numbering_id()),
+                IndentLevel::new(item.level()),
+            );
+        }
+        if !self.indentation.is_zero() {
+            let Indentation {
+                left,
+                right,
+                first_line,
+            } = self.indentation;
+            let special = match first_line {
+                0 => None,
+                n if n > 0 => Some(SpecialIndentType::FirstLine(n)),
+                n => Some(SpecialIndentType::Hanging(-n)),
+            };
+            paragraph = paragraph.indent(Some(left), special, Some(right), None);
+        }
+        if self.keep_together {
+            paragraph = paragraph.keep_lines(true);
+        }
+        if self.page_break_before {
+            paragraph = paragraph.page_break_before(true);
+        }
+        if let Some(fill) = self.shading.as_deref().and_then(docx_hex) {
+            paragraph.id = format!("{}.{}.{}", paragraph.id, SHADING_MARKER, fill);
+        }
+        paragraph
+    }
+
+    /// Paragraph properties as CSS declarations for the `<p>` element. Empty for the defaults
+    pub fn to_css(&self) -> String {
+        let mut css = Vec::new();
+        let Indentation {
+            left,
+            right,
+            first_line,
+        } = self.indentation;
+        // Twips to points
+        for (property, twips) in [
+            ("margin-left", left),
+            ("margin-right", right),
+            ("text-indent", first_line),
+        ] {
+            if twips != 0 {
+                css.push(format!("{}:{}pt", property, twips as f64 / 20.0));
+            }
+        }
+        if let Some(color) = &self.shading {
+            css.push(format!("background-color:{}", css_color(color)));
+        }
+        if self.keep_together {
+            css.push("break-inside:avoid".to_string());
+        }
+        if self.page_break_before {
+            css.push("break-before:page".to_string());
+        }
+        css.join(";")
+    }
+}
+
 /// Collection of text chunks with its own styles
-#[derive(Debug)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct StyledParagraph {
-    pub raw: Vec<StyledText>,
+    raw: Vec<StyledText>,
+    pub style: ParagraphStyle,
 }
 
 impl StyledParagraph {
     pub fn new() -> Self {
-        StyledParagraph { raw: Vec::new() }
+        StyledParagraph {
+            raw: Vec::new(),
+            style: ParagraphStyle::new(),
+        }
+    }
+
+    /// Build a paragraph straight from its chunks, e.g. after `into_chunks`. Chunks are taken
+    /// as given; call `normalize` to drop empty ones and merge equal styles
+    pub fn from_chunks(chunks: Vec<StyledText>) -> Self {
+        StyledParagraph {
+            raw: chunks,
+            style: ParagraphStyle::new(),
+        }
+    }
+
+    pub fn into_chunks(self) -> Vec<StyledText> {
+        self.raw
+    }
+
+    pub fn chunks(&self) -> &[StyledText] {
+        &self.raw
+    }
+
+    /// Chunks for in-place edits. Chunks can't be added or removed through this
+    pub fn chunks_mut(&mut self) -> &mut [StyledText] {
+        &mut self.raw
+    }
+
+    /// Paragraph as an HTML `<p>` element
+    pub fn to_html(&self) -> String {
+        let css = self.style.to_css();
+        let mut buffer = if css.is_empty() {
+            "<p>".to_string()
+        } else {
+            format!("<p style=\"{}\">", css)
+        };
+        for x in &self.raw {
+            buffer.push_str(&x.to_html());
+        }
+        buffer.push_str("</p>");
+        buffer
+    }
+
+    /// Paragraph as a Markdown block. List items get their marker, indented by level
+    pub fn to_markdown(&self) -> String {
+        let mut buffer = match self.style.list() {
+            Some(item) => {
+                let marker = match item.kind {
+                    ListKind::Bullet => "- ",
+                    ListKind::Number => "1. ",
+                };
+                format!("{}{}", "    ".repeat(item.level()), marker)
+            }
+            None => String::new(),
+        };
+        for x in &self.raw {
+            buffer.push_str(&x.to_markdown());
+        }
+        buffer
+    }
+
+    /// Paragraph as LaTeX, see `StyledText::to_latex`
+    #[cfg(feature = "latex")]
+    pub fn to_latex(&self) -> String {
+        self.raw.iter().map(StyledText::to_latex).collect()
+    }
+
+    /// Paragraph with ANSI styling for a terminal, see `StyledText::to_ansi`
+    pub fn to_ansi(&self) -> String {
+        self.raw.iter().map(StyledText::to_ansi).collect()
+    }
+
+    pub fn apply_to_raw(&self) -> Paragraph {
+        self.apply_to_raw_with_defaults(None)
+    }
+
+    /// See `StyledText::apply_to_raw_with_defaults`
+    pub fn apply_to_raw_with_defaults(&self, defaults: Option<&Style>) -> Paragraph {
+        let mut paragraph = Paragraph::new();
+        for styled_text in &self.raw {
+            paragraph = paragraph.add_run(styled_text.apply_to_raw_with_defaults(defaults));
+        }
+        self.style.apply_to_raw(paragraph)
+    }
+
+    /// Plain text of the paragraph, without any styling
+    pub fn text(&self) -> String {
+        self.raw.iter().map(|st| st.text.as_str()).collect()
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.raw.len()
+    }
+
+    /// True when there are no chunks or none of them hold any text
+    pub fn is_empty(&self) -> bool {
+        self.raw.iter().all(|st| st.text.is_empty())
+    }
+
+    /// Length of the paragraph text in Unicode scalar values
+    pub fn char_len(&self) -> usize {
+        self.raw.iter().map(StyledText::char_count).sum()
+    }
+
+    /// Style covering the most characters, for the toolbar when a selection mixes styles. Ties go
+    /// to the style that appears first; None when the paragraph has no text
+    pub fn dominant_style(&self) -> Option<Style> {
+        let mut counts: HashMap<&Style, usize> = HashMap::new();
+        for st in &self.raw {
+            *counts.entry(st.style()).or_default() += st.char_count();
+        }
+        let mut best: Option<(&Style, usize)> = None;
+        for st in &self.raw {
+            let count = counts[st.style()];
+            if count > 0 && best.is_none_or(|(_, most)| count > most) {
+                best = Some((st.style(), count));
+            }
+        }
+        best.map(|(style, _)| style.clone())
+    }
+
+    /// Insert a chunk at `idx`. Panics if `idx` is past the chunk count; see `try_insert`
+    pub fn insert(&mut self, idx: usize, new: StyledText) {
+        self.raw.insert(idx, new);
+    }
+
+    /// Insert a chunk at `idx`, or fail without touching the paragraph if `idx` is past the
+    /// chunk count
+    pub fn try_insert(&mut self, idx: usize, new: StyledText) -> Result<(), ParagraphModifyError> {
+        if idx > self.raw.len() {
+            return Err(ParagraphModifyError::IndexOutOfBounds(idx, self.raw.len()));
+        }
+        self.raw.insert(idx, new);
+        Ok(())
+    }
+
+    pub fn add(&mut self, new: StyledText) {
+        self.raw.push(new);
+    }
+
+    pub fn get_chunk(&self, idx: usize) -> Option<&StyledText> {
+        self.raw.get(idx)
+    }
+
+    pub fn get_chunk_mut(&mut self, idx: usize) -> Option<&mut StyledText> {
+        self.raw.get_mut(idx)
+    }
+
+    /// Remove the chunk at `idx` and return it. None if out of range
+    pub fn remove_chunk(&mut self, idx: usize) -> Option<StyledText> {
+        (idx < self.raw.len()).then(|| self.raw.remove(idx))
+    }
+
+    /// Replace the chunk at `idx`, returning the previous one. None (and no change) if out of range
+    pub fn replace_chunk(&mut self, idx: usize, new: StyledText) -> Option<StyledText> {
+        self.raw
+            .get_mut(idx)
+            .map(|chunk| std::mem::replace(chunk, new))
+    }
+
+    //TODO: This is hideous
+    /// Apply `style` to the first occurrence of `chunk`. Nothing is split when the text already has
+    /// that style, and the result is normalized so equal neighbours merge back together
+    pub fn modify(&mut self, style: Style, chunk: &str) -> Result<(), ParagraphModifyError> {
+        let (idx, dif) = self
+            .raw
+            .iter()
+            .enumerate()
+            .find(|(_n, st)| st.text.contains(chunk))
+            .map(|(n, st)| (n, st.clone()))
+            .ok_or_else(|| ParagraphModifyError::ChunkNotFound(chunk.to_string()))?;
+        if dif.style() == &style {
+            return Ok(());
+        }
+
+        let start_offset = dif
+            .text
+            .find(chunk)
+            .ok_or_else(|| ParagraphModifyError::ChunkNotFound(chunk.to_string()))?;
+        let end_offset = start_offset + chunk.len();
+
+        let mut current_idx = idx;
+
+        self.raw.remove(idx);
+
+        let prepend_text = &dif.text[..start_offset];
+        if !prepend_text.is_empty() {
+            self.raw.insert(
+                current_idx,
+                StyledText::shared(prepend_text.into(), Arc::clone(dif.shared_style())),
+            );
+            current_idx += 1;
+        }
+
+        let new_st = StyledText::new(chunk.into(), style);
+        self.raw.insert(current_idx, new_st);
+        current_idx += 1;
+
+        let append_text = &dif.text[end_offset..];
+        if !append_text.is_empty() {
+            self.raw.insert(
+                current_idx,
+                StyledText::shared(append_text.into(), Arc::clone(dif.shared_style())),
+            );
+        }
+        self.normalize();
+
+        Ok(())
+    }
+
+    /// Split the first occurrence of `chunk` into one chunk per character, with font colors going
+    /// evenly from `start_hex` to `end_hex`. Everything else about the style is kept
+    pub fn apply_gradient(
+        &mut self,
+        chunk: &str,
+        start_hex: &str,
+        end_hex: &str,
+    ) -> Result<(), ParagraphModifyError> {
+        let from = hex_to_rgba(start_hex)?;
+        let to = hex_to_rgba(end_hex)?;
+        let idx = self
+            .raw
+            .iter()
+            .position(|st| !chunk.is_empty() && st.text.contains(chunk))
+            .ok_or_else(|| ParagraphModifyError::ChunkNotFound(chunk.to_string()))?;
+
+        let original = self.raw.remove(idx);
+        let start = original.text.find(chunk).unwrap_or_default();
+        let end = start + chunk.len();
+        let steps = (chunk.chars().count() - 1).max(1) as f32;
+
+        let mut pieces = Vec::new();
+        if start > 0 {
+            pieces.push(StyledText::shared(
+                original.text[..start].to_string(),
+                Arc::clone(original.shared_style()),
+            ));
+        }
+        for (i, c) in chunk.chars().enumerate() {
+            let t = i as f32 / steps;
+            let rgba = [0, 1, 2, 3].map(|n| from[n] + (to[n] - from[n]) * t);
+            let style = original
+                .style()
+                .clone()
+                .change_font_color(rgba_to_hex(rgba))?;
+            pieces.push(StyledText::new(c.to_string(), style));
+        }
+        if end < original.text.len() {
+            pieces.push(StyledText::shared(
+                original.text[end..].to_string(),
+                Arc::clone(original.shared_style()),
+            ));
+        }
+        self.raw.splice(idx..idx, pieces);
+
+        Ok(())
+    }
+
+    /// Insert `text` as a new chunk at `char_offset`. It inherits the style of the text right before
+    /// the insertion point (or right after it when inserting at the start)
+    pub fn insert_text(
+        &mut self,
+        char_offset: usize,
+        text: &str,
+    ) -> Result<(), ParagraphModifyError> {
+        let len = self.char_len();
+        if char_offset > len {
+            return Err(ParagraphModifyError::InvalidRange(
+                char_offset,
+                char_offset,
+                len,
+            ));
+        }
+
+        let idx = self.split_at_char(char_offset);
+        let style = idx
+            .checked_sub(1)
+            .or((!self.raw.is_empty()).then_some(0))
+            .map_or_else(
+                || intern(Style::new()),
+                |i| Arc::clone(self.raw[i].shared_style()),
+            );
+        self.raw.insert(idx, StyledText::shared(text.into(), style));
+        Ok(())
+    }
+
+    /// Remove the characters in `start_char..end_char` and return them. Emptied chunks are dropped
+    /// and the paragraph is normalized afterwards
+    pub fn delete_range(
+        &mut self,
+        start_char: usize,
+        end_char: usize,
+    ) -> Result<String, ParagraphModifyError> {
+        let len = self.char_len();
+        if start_char > end_char || end_char > len {
+            return Err(ParagraphModifyError::InvalidRange(
+                start_char, end_char, len,
+            ));
+        }
+
+        let first = self.split_at_char(start_char);
+        let last = self.split_at_char(end_char);
+        let deleted = self
+            .raw
+            .drain(first..last)
+            .map(|st| st.text)
+            .collect::<String>();
+        self.normalize();
+        Ok(deleted)
+    }
+
+    /// Drop empty chunks and merge adjacent chunks sharing the same style (and tags)
+    pub fn normalize(&mut self) {
+        let mut merged: Vec<StyledText> = Vec::with_capacity(self.raw.len());
+        for st in self.raw.drain(..) {
+            if st.text.is_empty() {
+                continue;
+            }
+            match merged.last_mut() {
+                Some(prev) if prev.same_format(&st) => prev.text.push_str(&st.text),
+                _ => merged.push(st),
+            }
+        }
+        self.raw = merged;
+    }
+
+    /// Apply `style` to the characters in `start_char..end_char`, even if the range spans several
+    /// chunks. Chunks are split at the range edges, so styles outside the range are untouched.
+    /// The result is normalized, so restyling text that already has `style` adds no chunks
+    pub fn modify_spanning(
+        &mut self,
+        style: Style,
+        start_char: usize,
+        end_char: usize,
+    ) -> Result<(), ParagraphModifyError> {
+        let len = self.char_len();
+        if start_char > end_char || end_char > len {
+            return Err(ParagraphModifyError::InvalidRange(
+                start_char, end_char, len,
+            ));
+        }
+
+        let first = self.split_at_char(start_char);
+        let last = self.split_at_char(end_char);
+        for st in &mut self.raw[first..last] {
+            st.set_style(style.clone());
+        }
+        self.normalize();
+        Ok(())
+    }
+
+    /// Turn straight quotes into typographic ones. A quote after whitespace, an opening bracket,
+    /// a dash or another opening quote opens, anything else closes (so apostrophes become `’`).
+    /// Every quote is a single character either way, so offsets stay valid. Returns the number
+    /// of quotes changed
+    pub fn apply_smart_quotes(&mut self) -> usize {
+        let mut changed = 0;
+        let mut previous: Option<char> = None;
+        for st in &mut self.raw {
+            if !st.text.contains(['"', '\'']) {
+                previous = st.text.chars().last().or(previous);
+                continue;
+            }
+            let mut text = String::with_capacity(st.text.len() + 4);
+            for c in st.text.chars() {
+                let opens = previous.is_none_or(|p| p.is_whitespace() || "([{‘“—–".contains(p));
+                let smart = match (c, opens) {
+                    ('"', true) => '“',
+                    ('"', false) => '”',
+                    ('\'', true) => '‘',
+                    ('\'', false) => '’',
+                    _ => c,
+                };
+                if smart != c {
+                    changed += 1;
+                }
+                text.push(smart);
+                previous = Some(smart);
+            }
+            st.text = text;
+        }
+        changed
+    }
+
+    /// Replace every occurrence of `find` with `replace`, including matches spanning several
+    /// chunks. The replacement takes the style of the first matched character; text around the
+    /// matches keeps its style. Returns the number of replacements
+    pub fn replace_all(&mut self, find: &str, replace: &str) -> usize {
+        if find.is_empty() {
+            return 0;
+        }
+        let text = self.text();
+        let find_len = find.chars().count();
+        let starts: Vec<usize> = text
+            .match_indices(find)
+            .map(|(byte, _)| text[..byte].chars().count())
+            .collect();
+
+        // Back to front, so the offsets of earlier matches stay valid
+        for &start in starts.iter().rev() {
+            let first = self.split_at_char(start);
+            let last = self.split_at_char(start + find_len);
+            let style = Arc::clone(self.raw[first].shared_style());
+            self.raw.drain(first..last);
+            self.raw
+                .insert(first, StyledText::shared(replace.to_string(), style));
+        }
+        self.normalize();
+        starts.len()
+    }
+
+    /// Cheap fingerprint of the paragraph (chunk texts and styles, plus the paragraph style) to
+    /// detect unchanged paragraphs. Stable within a build, not meant to be persisted
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.raw.hash(&mut hasher);
+        self.style.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Remove all character formatting, leaving the text as a single default-styled chunk.
+    /// Paragraph-level properties (lists, indentation) are kept
+    pub fn clear_style(&mut self) {
+        let text = self.text();
+        self.raw.clear();
+        if !text.is_empty() {
+            self.raw.push(StyledText::new(text, Style::new()));
+        }
+    }
+
+    /// Apply `style` to every match of the regular expression `pattern` over the paragraph text.
+    /// Returns the number of (non-empty) matches styled
+    #[cfg(feature = "regex")]
+    pub fn modify_regex(
+        &mut self,
+        style: Style,
+        pattern: &str,
+    ) -> Result<usize, ParagraphModifyError> {
+        let re = regex::Regex::new(pattern)
+            .map_err(|e| ParagraphModifyError::InvalidPattern(e.to_string()))?;
+        let text = self.text();
+        let to_char = |byte: usize| text[..byte].chars().count();
+        let spans: Vec<(usize, usize)> = re
+            .find_iter(&text)
+            .filter(|m| !m.is_empty())
+            .map(|m| (to_char(m.start()), to_char(m.end())))
+            .collect();
+
+        for &(start, end) in &spans {
+            self.modify_spanning(style.clone(), start, end)?;
+        }
+        Ok(spans.len())
+    }
+
+    /// Map a paragraph-wide character offset to (chunk index, byte offset within that chunk).
+    /// Offsets on a chunk boundary map to the start of the following chunk, except the very end
+    /// of the paragraph, which maps to the end of the last chunk. None if beyond the end
+    pub fn locate(&self, char_offset: usize) -> Option<(usize, usize)> {
+        let mut start = 0;
+        let mut last = None;
+        for (idx, st) in self.raw.iter().enumerate() {
+            let count = st.char_count();
+            if char_offset < start + count {
+                let byte = st
+                    .text
+                    .char_indices()
+                    .nth(char_offset - start)
+                    .map_or(st.text.len(), |(byte, _)| byte);
+                return Some((idx, byte));
+            }
+            start += count;
+            last = Some((idx, st.text.len()));
+        }
+        last.filter(|_| char_offset == start)
+    }
+
+    /// UTF-16 code unit offset of the character at `char_offset`, for APIs that count in UTF-16.
+    /// Characters outside the Basic Multilingual Plane (most emoji) take two units. None if
+    /// beyond the end of the paragraph
+    pub fn char_to_utf16_offset(&self, char_offset: usize) -> Option<usize> {
+        let mut chars = self.raw.iter().flat_map(|st| st.text.chars());
+        let mut units = 0;
+        for _ in 0..char_offset {
+            units += chars.next()?.len_utf16();
+        }
+        Some(units)
+    }
+
+    /// Character offset of the UTF-16 code unit offset `utf16_offset`. None if beyond the end of
+    /// the paragraph or in the middle of a surrogate pair
+    pub fn utf16_to_char_offset(&self, utf16_offset: usize) -> Option<usize> {
+        let mut units = 0;
+        let mut chars = 0;
+        for c in self.raw.iter().flat_map(|st| st.text.chars()) {
+            if units >= utf16_offset {
+                break;
+            }
+            units += c.len_utf16();
+            chars += 1;
+        }
+        (units == utf16_offset).then_some(chars)
+    }
+
+    /// Make sure a chunk starts at `char_offset`, splitting the chunk containing it if needed.
+    /// Returns the index of the chunk starting there (or the chunk count at the very end)
+    pub(crate) fn split_at_char(&mut self, char_offset: usize) -> usize {
+        match self.locate(char_offset) {
+            Some((idx, 0)) => idx,
+            Some((idx, byte)) if byte < self.raw[idx].text.len() => {
+                let text = self.raw[idx].text.split_off(byte);
+                // Same style and tags as the head
+                let mut tail = self.raw[idx].clone();
+                tail.text = text;
+                self.raw.insert(idx + 1, tail);
+                idx + 1
+            }
+            _ => self.raw.len(),
+        }
+    }
+
+    /// Paragraph in the tagged-text format. Can be parsed back with `from_tagged_text`
+    pub fn as_tagged(&self) -> String {
+        let mut buffer = String::new();
+        for x in &self.raw {
+            let _ = write!(buffer, "{}", x.apply_style_tagging());
+        }
+        buffer
+    }
+
+    /// (opening tag, text) of every chunk, the pieces `as_tagged` is made of. The text is as
+    /// stored, not escaped. See `StyledText::style_tag`
+    pub fn tagged_segments(&self) -> Vec<(String, &str)> {
+        self.raw
+            .iter()
+            .map(|st| (st.style_tag(), st.text.as_str()))
+            .collect()
+    }
+
+    /// Build a paragraph from the tagged-text format. Text outside of any tag takes the default style.
+    /// The parser is a single forward pass, so any input either parses or errors in linear time.
+    /// Tags do not nest: anything between an opening tag and its closing tag is literal text, with
+    /// `\\` and `\[` standing for a backslash and a bracket
+    pub fn from_tagged_text(tagged: &str) -> Result<Self, TagParseError> {
+        let mut paragraph = StyledParagraph::new();
+        let mut rest = tagged;
+
+        while !rest.is_empty() {
+            let position = tagged.len() - rest.len();
+            let Some(after_open) = rest.strip_prefix("[[") else {
+                let end = find_unescaped(rest, "[[").unwrap_or(rest.len());
+                paragraph.add(StyledText::new(unescape_tagged(&rest[..end]), Style::new()));
+                rest = &rest[end..];
+                continue;
+            };
+
+            // Only look for the end of the tag as far as a tag may go
+            let mut limit = (MAX_TAG_LEN + 2).min(after_open.len());
+            while !after_open.is_char_boundary(limit) {
+                limit -= 1;
+            }
+            let tag_end = match after_open[..limit].find("]]") {
+                Some(end) => end,
+                None if limit < after_open.len() => {
+                    return Err(TagParseError::TagTooLong(position));
+                }
+                None => return Err(TagParseError::UnclosedTag(position)),
+            };
+            let tag = &after_open[..tag_end];
+            if tag.starts_with('/') {
+                return Err(TagParseError::UnexpectedClose(position));
+            }
+            let style = tag
+                .parse::<Style>()
+                .map_err(|_| TagParseError::MalformedStyle(position, tag.to_string()))?;
+
+            let body = &after_open[tag_end + 2..];
+            let close = format!("[[/{}]]", tag);
+            let text_end =
+                find_unescaped(body, &close).ok_or(TagParseError::UnclosedTag(position))?;
+
+            paragraph.add(StyledText::new(unescape_tagged(&body[..text_end]), style));
+            rest = &body[text_end + close.len()..];
+        }
+
+        Ok(paragraph)
+    }
+}
+
+/// Byte offset of the first `pattern` in tagged text that isn't escaped with a backslash
+fn find_unescaped(text: &str, pattern: &str) -> Option<usize> {
+    let mut chars = text.char_indices();
+    while let Some((at, c)) = chars.next() {
+        if c == '\\' {
+            chars.next();
+        } else if text[at..].starts_with(pattern) {
+            return Some(at);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stylemgr::style::{CapsStyle, Style, UnderlineStyle};
+    use crate::stylemgr::text::StyledText;
+
+    #[test]
+    fn test_apply_to() {
+        let base = Style::new().switch_italic();
+        let apply = |command: ApplicableStyles| command.apply_to(base.clone());
+
+        assert_eq!(
+            apply(ApplicableStyles::Bold).unwrap(),
+            base.clone().switch_bold()
+        );
+        assert!(!apply(ApplicableStyles::Italic).unwrap().italic());
+        assert_eq!(
+            apply(ApplicableStyles::Underline(Some(UnderlineStyle::Wave)))
+                .unwrap()
+                .underline(),
+            Some(&UnderlineStyle::Wave)
+        );
+        assert_eq!(apply(ApplicableStyles::Size(30)).unwrap().size(), 30);
+        assert_eq!(
+            apply(ApplicableStyles::Color("#00ff00".to_string()))
+                .unwrap()
+                .font_color(),
+            "#00ff00"
+        );
+        assert_eq!(
+            apply(ApplicableStyles::Highlight(Some("#ffff00".to_string())))
+                .unwrap()
+                .highlight_color(),
+            Some("#ffff00")
+        );
+        assert!(matches!(
+            apply(ApplicableStyles::Color("green".to_string())),
+            Err(StyleError::InvalidHexColor(_))
+        ));
+        assert!(matches!(
+            apply(ApplicableStyles::Font(
+                "DefinitelyNotAFontName123".to_string()
+            )),
+            Err(StyleError::FontNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        let parse = |s: &str| s.parse::<ApplicableStyles>();
+        assert_eq!(parse("bold"), Ok(ApplicableStyles::Bold));
+        assert_eq!(parse(" italic "), Ok(ApplicableStyles::Italic));
+        assert_eq!(
+            parse("underline"),
+            Ok(ApplicableStyles::Underline(Some(UnderlineStyle::Single)))
+        );
+        assert_eq!(
+            parse("underline:wavyDouble"),
+            Ok(ApplicableStyles::Underline(Some(
+                UnderlineStyle::WavyDouble
+            )))
+        );
+        assert_eq!(
+            parse("underline:none"),
+            Ok(ApplicableStyles::Underline(None))
+        );
+        // Unknown kinds keep an underline, as they do on import
+        assert_eq!(
+            parse("underline:squiggly"),
+            Ok(ApplicableStyles::Underline(Some(UnderlineStyle::Single)))
+        );
+        assert_eq!(parse("size:14"), Ok(ApplicableStyles::Size(14)));
+        assert_eq!(
+            parse("font:Times New Roman"),
+            Ok(ApplicableStyles::Font("Times New Roman".to_string()))
+        );
+        assert_eq!(
+            parse("color:#FF0000"),
+            Ok(ApplicableStyles::Color("#FF0000".to_string()))
+        );
+        assert_eq!(
+            parse("highlight:#FFFF0080"),
+            Ok(ApplicableStyles::Highlight(Some("#FFFF0080".to_string())))
+        );
+        assert_eq!(parse("highlight"), Ok(ApplicableStyles::Highlight(None)));
+    }
+
+    #[test]
+    fn test_parse_malformed_commands() {
+        let parse = |s: &str| s.parse::<ApplicableStyles>();
+        let invalid = |name: &str, argument: &str| {
+            Err(CommandParseError::InvalidArgument(
+                name.to_string(),
+                argument.to_string(),
+            ))
+        };
+        assert_eq!(parse("size:abc"), invalid("size", "abc"));
+        assert_eq!(parse("size:0"), invalid("size", "0"));
+        assert_eq!(parse("size:300"), invalid("size", "300"));
+        assert_eq!(parse("color:red"), invalid("color", "red"));
+        assert_eq!(parse("bold:yes"), invalid("bold", "yes"));
+        assert_eq!(
+            parse("size"),
+            Err(CommandParseError::MissingArgument("size".to_string()))
+        );
+        assert_eq!(
+            parse("strike"),
+            Err(CommandParseError::UnknownCommand("strike".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_apply_gradient() {
+        let mut p = StyledParagraph::new();
+        p.add(StyledText::new(
+            "a abc z".to_string(),
+            Style::new().switch_bold(),
+        ));
+        p.apply_gradient("abc", "#000000", "#ffffff").unwrap();
+
+        let colors: Vec<&str> = p
+            .chunks()
+            .iter()
+            .map(|st| st.style().font_color())
+            .collect();
+        assert_eq!(
+            colors,
+            ["#000000", "#000000", "#808080", "#ffffff", "#000000"]
+        );
+        assert_eq!(p.text(), "a abc z");
+        assert!(p.chunks().iter().all(|st| st.style().bold()));
+
+        assert!(matches!(
+            p.apply_gradient("z", "black", "#ffffff"),
+            Err(ParagraphModifyError::Style(StyleError::InvalidHexColor(_)))
+        ));
+        assert!(matches!(
+            p.apply_gradient("missing", "#000000", "#ffffff"),
+            Err(ParagraphModifyError::ChunkNotFound(_))
+        ));
+        assert_eq!(p.chunk_count(), 5);
+    }
+
+    #[test]
+    fn test_smart_quotes() {
+        let mut p = StyledParagraph::new();
+        p.add(StyledText::new("\"It's ".to_string(), Style::new()));
+        p.add(StyledText::new(
+            "here,\" she said ('quietly').".to_string(),
+            Style::new().switch_italic(),
+        ));
+        assert_eq!(p.apply_smart_quotes(), 5);
+        assert_eq!(p.text(), "“It’s here,” she said (‘quietly’).");
+        assert_eq!(p.chunk_count(), 2);
+        assert_eq!(p.apply_smart_quotes(), 0);
+
+        // The quote opening a chunk looks at the end of the previous one
+        let mut p = StyledParagraph::new();
+        p.add(StyledText::new("Say ".to_string(), Style::new()));
+        p.add(StyledText::new(
+            "\"hi\"".to_string(),
+            Style::new().switch_bold(),
+        ));
+        p.apply_smart_quotes();
+        assert_eq!(p.text(), "Say “hi”");
+    }
+
+    #[test]
+    fn test_page_flags() {
+        let raw = ParagraphStyle::new().apply_to_raw(Paragraph::new());
+        assert_eq!(raw.property.keep_lines, None);
+        assert_eq!(raw.property.page_break_before, None);
+
+        let style = ParagraphStyle::new()
+            .set_keep_together(true)
+            .set_page_break_before(true);
+        assert!(style.keep_together() && style.page_break_before());
+        let raw = style.apply_to_raw(Paragraph::new());
+        assert_eq!(raw.property.keep_lines, Some(true));
+        assert_eq!(raw.property.page_break_before, Some(true));
+        assert_eq!(style.to_css(), "break-inside:avoid;break-before:page");
+    }
+
+    #[test]
+    fn test_paragraph_shading() {
+        assert!(matches!(
+            ParagraphStyle::new().set_shading(Some("yellow".to_string())),
+            Err(StyleError::InvalidHexColor(_))
+        ));
+
+        let style = ParagraphStyle::new()
+            .set_shading(Some("#FFF2CC".to_string()))
+            .unwrap();
+        assert_eq!(style.shading(), Some("#FFF2CC"));
+        assert_eq!(style.to_css(), "background-color:#FFF2CC");
+
+        let mut p = StyledParagraph::new();
+        p.add(StyledText::new("Note".to_string(), Style::new()));
+        p.style = style.clone();
+        assert!(
+            p.to_html()
+                .starts_with("<p style=\"background-color:#FFF2CC\">")
+        );
+        let raw = style.apply_to_raw(Paragraph::new().style("Quote"));
+        assert!(raw.id.ends_with(".EddaShading.FFF2CC"));
+        assert_eq!(raw.property.style.unwrap().val, "Quote");
+
+        // Shading that skipped the setter is left out rather than sliced
+        let mut broken = style.clone();
+        broken.shading = Some("#é".to_string());
+        let raw = broken.apply_to_raw(Paragraph::new());
+        assert!(!raw.id.contains(SHADING_MARKER));
+        assert_eq!(broken.sanitized().shading(), None);
+        assert_eq!(style.sanitized(), style);
+
+        let cleared = style.set_shading(None).unwrap();
+        assert_eq!(cleared, ParagraphStyle::new());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_paragraph_style_serde() {
+        let style = ParagraphStyle::new()
+            .set_keep_together(true)
+            .set_shading(Some("#FFF2CC".to_string()))
+            .unwrap();
+        let json = serde_json::to_string(&style).unwrap();
+        assert_eq!(
+            serde_json::from_str::<ParagraphStyle>(&json).unwrap(),
+            style
+        );
+
+        let broken = json.replace("#FFF2CC", "#é");
+        assert!(serde_json::from_str::<ParagraphStyle>(&broken).is_err());
     }
 
-    pub fn insert(&mut self, idx: usize, new: StyledText) {
-        self.raw.insert(idx, new);
+    #[test]
+    fn test_indent_level() {
+        let indented = |left, first_line| {
+            ParagraphStyle::new().set_indentation(Indentation {
+                left,
+                right: 0,
+                first_line,
+            })
+        };
+        assert_eq!(ParagraphStyle::new().indent_level(), 0);
+        assert_eq!(indented(720, 0).indent_level(), 1);
+        assert_eq!(indented(720, 720).indent_level(), 2);
+        // Rounded to the nearest tab stop; a hanging indent can't go below zero
+        assert_eq!(indented(1000, 0).indent_level(), 1);
+        assert_eq!(indented(0, -720).indent_level(), 0);
+        assert_eq!(
+            indented(i32::MAX, i32::MAX).indent_level(),
+            (2 * i32::MAX as usize + 360) / 720
+        );
+        assert_eq!(indented(i32::MIN, i32::MIN).indent_level(), 0);
+        let nested = indented(720, 0).set_list(Some(ListItem::new(ListKind::Bullet, 2)));
+        assert_eq!(nested.indent_level(), 3);
     }
 
-    pub fn add(&mut self, new: StyledText) {
-        self.raw.push(new);
+    #[test]
+    fn test_paragraph_new() {
+        let p = StyledParagraph::new();
+        assert!(p.raw.is_empty());
     }
 
-    //TODO: This is hideous
-    pub fn modify(&mut self, style: Style, chunk: &str) -> Result<(), ParagraphModifyError> {
-        let (idx, dif) = self
-            .raw
-            .iter()
-            .enumerate()
-            .find(|(_n, st)| st.text.contains(chunk))
-            .map(|(n, st)| (n, st.clone()))
-            .ok_or_else(|| ParagraphModifyError::ChunkNotFound(chunk.to_string()))?;
+    #[test]
+    fn test_paragraph_counts_empty() {
+        let mut p = StyledParagraph::new();
+        assert_eq!(p.chunk_count(), 0);
+        assert!(p.is_empty());
+        assert_eq!(p.char_len(), 0);
 
-        let start_offset = dif
-            .text
-            .find(chunk)
-            .ok_or_else(|| ParagraphModifyError::ChunkNotFound(chunk.to_string()))?;
-        let end_offset = start_offset + chunk.len();
+        p.add(StyledText::new(String::new(), Style::new()));
+        assert_eq!(p.chunk_count(), 1);
+        assert!(p.is_empty());
+    }
 
-        let mut current_idx = idx;
+    #[test]
+    fn test_paragraph_counts_single_chunk() {
+        let mut p = StyledParagraph::new();
+        p.add(StyledText::new("Hello".to_string(), Style::new()));
+        assert_eq!(p.chunk_count(), 1);
+        assert!(!p.is_empty());
+        assert_eq!(p.char_len(), 5);
+    }
 
-        self.raw.remove(idx);
+    #[test]
+    fn test_paragraph_char_len_multibyte() {
+        let mut p = StyledParagraph::new();
+        p.add(StyledText::new("Ñandú ".to_string(), Style::new()));
+        p.add(StyledText::new(
+            "🦀".to_string(),
+            Style::new().switch_bold(),
+        ));
+        assert_eq!(p.chunk_count(), 2);
+        assert_eq!(p.char_len(), 7);
+    }
 
-        let prepend_text = &dif.text[..start_offset];
-        if !prepend_text.is_empty() {
-            self.raw.insert(
-                current_idx,
-                StyledText::new(prepend_text.into(), dif.style.clone()),
-            );
-            current_idx += 1;
-        }
+    #[test]
+    fn test_paragraph_style_list() {
+        let style = ParagraphStyle::new().set_list(Some(ListItem::new(ListKind::Number, 12)));
+        assert_eq!(style.list(), Some(&ListItem::new(ListKind::Number, 8)));
 
-        let new_st = StyledText::new(chunk.into(), style);
-        self.raw.insert(current_idx, new_st);
-        current_idx += 1;
+        let mut p = StyledParagraph::new();
+        p.style = style;
+        let numbering = p.apply_to_raw().property.numbering_property.unwrap();
+        assert_eq!(numbering.id.unwrap().id, ListKind::Number.numbering_id());
+        assert_eq!(numbering.level.unwrap().val, 8);
+    }
 
-        let append_text = &dif.text[end_offset..];
-        if !append_text.is_empty() {
-            self.raw
-                .insert(current_idx, StyledText::new(append_text.into(), dif.style));
-        }
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_list_item_serde_clamps_level() {
+        let item: ListItem = serde_json::from_str(r#"{"kind":"Number","level":40}"#).unwrap();
+        assert_eq!(item.level(), ListItem::MAX_LEVEL);
+        let json = serde_json::to_string(&item).unwrap();
+        assert_eq!(serde_json::from_str::<ListItem>(&json).unwrap(), item);
+    }
 
-        Ok(())
+    #[test]
+    fn test_paragraph_style_default_not_a_list() {
+        let p = StyledParagraph::new();
+        assert!(p.style.list().is_none());
+        assert!(p.apply_to_raw().property.numbering_property.is_none());
     }
 
-    #[allow(dead_code)]
-    fn parse_as_raw_tagged_text(&self) -> String {
-        let mut buffer = String::new();
-        for x in &self.raw {
-            let _ = write!(buffer, "{}", x.apply_style_tagging());
-        }
-        buffer
+    #[test]
+    fn test_paragraph_style_indentation() {
+        let mut p = StyledParagraph::new();
+        p.add(StyledText::new("Indented".to_string(), Style::new()));
+        assert!(p.apply_to_raw().property.indent.is_none());
+        assert_eq!(p.to_html(), "<p>Indented</p>");
+
+        p.style = ParagraphStyle::new().set_indentation(Indentation {
+            first_line: 720,
+            ..Default::default()
+        });
+        let indent = p.apply_to_raw().property.indent.unwrap();
+        assert_eq!(
+            indent.special_indent,
+            Some(SpecialIndentType::FirstLine(720))
+        );
+        assert_eq!(indent.start, Some(0));
+        assert_eq!(p.to_html(), "<p style=\"text-indent:36pt\">Indented</p>");
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::stylemgr::style::{Style, UnderlineStyle};
-    use crate::stylemgr::text::StyledText;
+    #[test]
+    fn test_paragraph_style_hanging_indent() {
+        let style = ParagraphStyle::new().set_indentation(Indentation {
+            left: 1440,
+            right: 360,
+            first_line: -360,
+        });
+        let indent = style
+            .apply_to_raw(Paragraph::new())
+            .property
+            .indent
+            .unwrap();
+        assert_eq!(indent.start, Some(1440));
+        assert_eq!(indent.end, Some(360));
+        assert_eq!(indent.special_indent, Some(SpecialIndentType::Hanging(360)));
+        assert_eq!(
+            style.to_css(),
+            "margin-left:72pt;margin-right:18pt;text-indent:-18pt"
+        );
+    }
 
     #[test]
-    fn test_paragraph_new() {
-        let p = StyledParagraph::new();
-        assert!(p.raw.is_empty());
+    fn test_dominant_style() {
+        let bold = Style::new().switch_bold();
+        let mut p = StyledParagraph::new();
+        assert_eq!(p.dominant_style(), None);
+
+        p.add(StyledText::new("Mostly ".to_string(), bold.clone()));
+        p.add(StyledText::new("a".to_string(), Style::new()));
+        p.add(StyledText::new(" bold text".to_string(), bold.clone()));
+        assert_eq!(p.dominant_style(), Some(bold.clone()));
+
+        // Equal share: the first style wins
+        let mut tie = StyledParagraph::new();
+        tie.add(StyledText::new("ab".to_string(), Style::new()));
+        tie.add(StyledText::new("cd".to_string(), bold));
+        assert_eq!(tie.dominant_style(), Some(Style::new()));
     }
 
     #[test]
@@ -115,8 +1464,8 @@ mod tests {
         assert_eq!(p.raw.len(), 2);
         assert_eq!(p.raw[0].text, "Hello ");
         assert_eq!(p.raw[1].text, "World");
-        assert!(!p.raw[0].style.bold());
-        assert!(p.raw[1].style.bold());
+        assert!(!p.raw[0].style().bold());
+        assert!(p.raw[1].style().bold());
     }
 
     #[test]
@@ -133,7 +1482,24 @@ mod tests {
         assert_eq!(p.raw[0].text, "First");
         assert_eq!(p.raw[1].text, "Second");
         assert_eq!(p.raw[2].text, "Third");
-        assert!(p.raw[1].style.italic());
+        assert!(p.raw[1].style().italic());
+    }
+
+    #[test]
+    fn test_paragraph_try_insert() {
+        let mut p = StyledParagraph::new();
+        p.add(StyledText::new("First".to_string(), Style::new()));
+        let before = p.clone();
+
+        let extra = StyledText::new("Extra".to_string(), Style::new().switch_bold());
+        assert!(matches!(
+            p.try_insert(2, extra.clone()),
+            Err(ParagraphModifyError::IndexOutOfBounds(2, 1))
+        ));
+        assert_eq!(p, before);
+
+        p.try_insert(1, extra.clone()).unwrap();
+        assert_eq!(p.raw[1], extra);
     }
 
     #[test]
@@ -148,11 +1514,11 @@ mod tests {
         assert!(result.is_ok());
         assert_eq!(p.raw.len(), 3);
         assert_eq!(p.raw[0].text, "This ");
-        assert!(!p.raw[0].style.bold());
+        assert!(!p.raw[0].style().bold());
         assert_eq!(p.raw[1].text, "is a");
-        assert!(p.raw[1].style.bold());
+        assert!(p.raw[1].style().bold());
         assert_eq!(p.raw[2].text, " test.");
-        assert!(!p.raw[2].style.bold());
+        assert!(!p.raw[2].style().bold());
     }
 
     #[test]
@@ -171,11 +1537,11 @@ mod tests {
         assert!(result.is_ok());
         assert_eq!(p.raw.len(), 3); // Should replace st2, not split it
         assert_eq!(p.raw[0].text, "Part1 ");
-        assert!(!p.raw[0].style.italic());
+        assert!(!p.raw[0].style().italic());
         assert_eq!(p.raw[1].text, "ModifyMe");
-        assert!(p.raw[1].style.italic());
+        assert!(p.raw[1].style().italic());
         assert_eq!(p.raw[2].text, " Part3");
-        assert!(!p.raw[2].style.italic());
+        assert!(!p.raw[2].style().italic());
     }
 
     #[test]
@@ -190,9 +1556,9 @@ mod tests {
         assert!(result.is_ok());
         assert_eq!(p.raw.len(), 2);
         assert_eq!(p.raw[0].text, "Prefix");
-        assert!(p.raw[0].style.bold());
+        assert!(p.raw[0].style().bold());
         assert_eq!(p.raw[1].text, " suffix");
-        assert!(!p.raw[1].style.bold());
+        assert!(!p.raw[1].style().bold());
     }
 
     #[test]
@@ -207,9 +1573,9 @@ mod tests {
         assert!(result.is_ok());
         assert_eq!(p.raw.len(), 2);
         assert_eq!(p.raw[0].text, "Prefix ");
-        assert!(!p.raw[0].style.bold());
+        assert!(!p.raw[0].style().bold());
         assert_eq!(p.raw[1].text, "suffix");
-        assert!(p.raw[1].style.bold());
+        assert!(p.raw[1].style().bold());
     }
 
     #[test]
@@ -231,25 +1597,541 @@ mod tests {
     }
 
     #[test]
-    fn test_parse_as_raw_tagged_text() {
+    fn test_modify_spanning_across_chunks() {
+        let mut p = StyledParagraph::new();
+        p.add(StyledText::new("Hello ".to_string(), Style::new()));
+        p.add(StyledText::new(
+            "wörld".to_string(),
+            Style::new().switch_italic(),
+        ));
+
+        let bold = Style::new().switch_bold();
+        p.modify_spanning(bold.clone(), 3, 8).unwrap();
+
+        let texts: Vec<&str> = p.raw.iter().map(|st| st.text.as_str()).collect();
+        // The two bold pieces merge
+        assert_eq!(texts, vec!["Hel", "lo wö", "rld"]);
+        assert_eq!(p.raw[0].style(), &Style::new());
+        assert_eq!(p.raw[1].style(), &bold);
+        assert!(p.raw[2].style().italic());
+        assert_eq!(p.text(), "Hello wörld");
+    }
+
+    #[test]
+    fn test_restyling_does_not_fragment() {
+        let mut p = StyledParagraph::new();
+        p.add(StyledText::new("Some bold text".to_string(), Style::new()));
+        let bold = Style::new().switch_bold();
+
+        p.modify(bold.clone(), "bold").unwrap();
+        assert_eq!(p.chunk_count(), 3);
+        p.modify(bold.clone(), "bold").unwrap();
+        assert_eq!(p.chunk_count(), 3);
+        p.modify(bold.clone(), "ol").unwrap();
+        assert_eq!(p.chunk_count(), 3);
+
+        p.modify_spanning(bold.clone(), 3, 9).unwrap();
+        let texts: Vec<&str> = p.raw.iter().map(|st| st.text.as_str()).collect();
+        assert_eq!(texts, vec!["Som", "e bold", " text"]);
+        p.modify_spanning(bold, 3, 9).unwrap();
+        assert_eq!(p.chunk_count(), 3);
+    }
+
+    #[test]
+    fn test_modify_spanning_invalid_range() {
+        let mut p = StyledParagraph::new();
+        p.add(StyledText::new("Short".to_string(), Style::new()));
+
+        let result = p.modify_spanning(Style::new().switch_bold(), 2, 6);
+        assert!(matches!(
+            result,
+            Err(ParagraphModifyError::InvalidRange(2, 6, 5))
+        ));
+        assert!(p.modify_spanning(Style::new(), 4, 2).is_err());
+        assert_eq!(p.chunk_count(), 1);
+    }
+
+    #[test]
+    fn test_insert_text_start_and_empty() {
+        let mut p = StyledParagraph::new();
+        p.insert_text(0, "First").unwrap();
+        assert_eq!(p.raw[0].style(), &Style::new());
+
+        p.raw[0].set_style(Style::new().switch_bold());
+        p.insert_text(0, ">> ").unwrap();
+        assert_eq!(p.text(), ">> First");
+        assert!(p.raw[0].style().bold());
+    }
+
+    #[test]
+    fn test_normalize() {
+        let mut p = StyledParagraph::new();
+        p.add(StyledText::new("a".to_string(), Style::new()));
+        p.add(StyledText::new("".to_string(), Style::new().switch_bold()));
+        p.add(StyledText::new("b".to_string(), Style::new()));
+        p.add(StyledText::new("c".to_string(), Style::new().switch_bold()));
+        p.normalize();
+
+        assert_eq!(p.chunk_count(), 2);
+        assert_eq!(p.raw[0].text, "ab");
+        assert_eq!(p.raw[1].text, "c");
+    }
+
+    #[test]
+    fn test_delete_range_multibyte() {
+        let mut p = StyledParagraph::new();
+        p.add(StyledText::new("añ🦀b".to_string(), Style::new()));
+        assert_eq!(p.delete_range(1, 3).unwrap(), "ñ🦀");
+        assert_eq!(p.text(), "ab");
+        assert_eq!(p.chunk_count(), 1);
+        assert!(p.delete_range(1, 3).is_err());
+    }
+
+    #[test]
+    fn test_as_tagged() {
         let mut p = StyledParagraph::new();
-        let style1 = Style::new();
         let style2 = Style::new()
             .switch_bold()
             .set_underline(Some(UnderlineStyle::Double));
-        let st1 = StyledText::new("Plain ".to_string(), style1.clone());
+        let st1 = StyledText::new("Plain ".to_string(), Style::new());
         let st2 = StyledText::new("BoldUnderline".to_string(), style2.clone());
         p.add(st1);
         p.add(st2);
 
+        assert_eq!(
+            StyledParagraph::from_tagged_text(&p.as_tagged()).unwrap(),
+            p
+        );
+
         // Expected format depends on StyledText::apply_style_tagging
-        let tag1 = format!("{}", style1);
-        let tag2 = format!("{}", style2);
-        let expected = format!(
-            "[[{0}]]Plain [[/{0}]][[{1}]]BoldUnderline[[/{1}]]",
-            tag1, tag2
+        let tag2 = style2.to_tag();
+        let expected = format!("Plain [[{0}]]BoldUnderline[[/{0}]]", tag2);
+
+        assert_eq!(p.as_tagged(), expected);
+    }
+
+    #[test]
+    fn test_tagged_roundtrip_adversarial() {
+        let texts = [
+            "[[bold(true)]]x[[/bold(true)]]",
+            "[[/",
+            "a [[ b ]] c",
+            "back\\slash\\",
+            "\\[[",
+            "[",
+            "]]",
+            "100%",
+        ];
+        let fonts = [
+            "Semi;colon",
+            "Open (Paren",
+            "Close) Paren",
+            "x)];font(y",
+            "]] [[",
+            "Percent %3B",
+            "Back\\slash",
+        ];
+        let mut p = StyledParagraph::new();
+        for (i, text) in texts.iter().enumerate() {
+            p.add(StyledText::new(text.to_string(), Style::new()));
+            let style = Style::new()
+                .switch_italic()
+                .set_font_unchecked(fonts[i % fonts.len()]);
+            p.add(StyledText::new(text.to_string(), style));
+        }
+        // Consecutive default-styled chunks would merge, so compare the chunks one by one
+        let tagged = p.as_tagged();
+        let parsed = StyledParagraph::from_tagged_text(&tagged).unwrap();
+        assert_eq!(parsed.chunks(), p.chunks(), "{}", tagged);
+
+        // Hand-written backslashes before other characters are kept
+        let plain = StyledParagraph::from_tagged_text(r"C:\path \n").unwrap();
+        assert_eq!(plain.text(), r"C:\path \n");
+    }
+
+    #[test]
+    fn test_tagged_segments() {
+        let mut p = StyledParagraph::new();
+        let italic = Style::new().switch_italic();
+        p.add(StyledText::new("Plain ".to_string(), Style::new()));
+        p.add(StyledText::new("slanted".to_string(), italic.clone()));
+
+        let opening = format!("[[{}]]", italic.to_tag());
+        assert_eq!(
+            p.tagged_segments(),
+            [(String::new(), "Plain "), (opening.clone(), "slanted")]
+        );
+        assert_eq!(p.chunks()[1].style_tag(), opening);
+        assert!(
+            p.as_tagged()
+                .starts_with(&format!("Plain {}slanted", opening))
+        );
+    }
+
+    #[test]
+    fn test_from_tagged_text_roundtrip_all_styles() {
+        let underlines = std::iter::once(None).chain(UnderlineStyle::ALL.into_iter().map(Some));
+        for (n, underline) in underlines.enumerate() {
+            let mut p = StyledParagraph::new();
+            for bold in [false, true] {
+                for italic in [false, true] {
+                    for highlight in [None, Some("#FFFF0080".to_string())] {
+                        let caps = [None, Some(CapsStyle::AllCaps), Some(CapsStyle::SmallCaps)];
+                        let mut style = Style::new()
+                            .set_underline(underline.clone())
+                            .set_caps(caps[n % 3])
+                            .change_size((n * 7 % 256) as u8)
+                            .change_font_color(format!("#{:06X}", n * 0x0F0F0F))
+                            .unwrap()
+                            .change_font_highlight(highlight)
+                            .unwrap();
+                        if bold {
+                            style = style.switch_bold();
+                        }
+                        if italic {
+                            style = style.switch_italic();
+                        }
+                        p.add(StyledText::new(format!("Chunk {} ", n), style));
+                    }
+                }
+            }
+
+            assert_eq!(
+                StyledParagraph::from_tagged_text(&p.as_tagged()).unwrap(),
+                p
+            );
+        }
+    }
+
+    #[test]
+    fn test_from_tagged_text_untagged_is_default() {
+        let p = StyledParagraph::from_tagged_text("Plain text").unwrap();
+        assert_eq!(p.raw.len(), 1);
+        assert_eq!(p.raw[0].text, "Plain text");
+        assert_eq!(p.raw[0].style(), &Style::new());
+    }
+
+    #[test]
+    fn test_from_tagged_text_errors() {
+        let tag = Style::new().to_tag();
+        assert!(matches!(
+            StyledParagraph::from_tagged_text(&format!("[[{}]]Never closed", tag)),
+            Err(TagParseError::UnclosedTag(_))
+        ));
+        assert!(matches!(
+            StyledParagraph::from_tagged_text(&format!("Text[[/{}]]", tag)),
+            Err(TagParseError::UnexpectedClose(_))
+        ));
+        assert!(matches!(
+            StyledParagraph::from_tagged_text("[[bold;pt(11)]]Text[[/bold;pt(11)]]"),
+            Err(TagParseError::MalformedStyle(0, _))
+        ));
+    }
+
+    #[test]
+    fn test_from_tagged_text_error_positions() {
+        let tag = Style::new().to_tag();
+        let input = format!("Plain [[{0}]]ok[[/{0}]] [[{0}]]open", tag);
+        let second_open = input.rfind("[[").unwrap();
+        assert!(matches!(
+            StyledParagraph::from_tagged_text(&input),
+            Err(TagParseError::UnclosedTag(n)) if n == second_open
+        ));
+    }
+
+    #[test]
+    fn test_from_tagged_text_tag_too_long() {
+        let input = format!("[[{}]]text", "x".repeat(MAX_TAG_LEN + 1));
+        assert!(matches!(
+            StyledParagraph::from_tagged_text(&input),
+            Err(TagParseError::TagTooLong(0))
+        ));
+
+        // The end of an overlong tag isn't searched for past the limit, even across multibyte text
+        let input = format!("[[{}]]", "é".repeat(MAX_TAG_LEN));
+        assert!(matches!(
+            StyledParagraph::from_tagged_text(&input),
+            Err(TagParseError::TagTooLong(0))
+        ));
+        assert!(matches!(
+            StyledParagraph::from_tagged_text("[[bold(true)"),
+            Err(TagParseError::UnclosedTag(0))
+        ));
+    }
+
+    #[test]
+    fn test_from_tagged_text_deep_nesting() {
+        let input = "[[".repeat(100_000) + &"]]".repeat(100_000);
+        assert!(StyledParagraph::from_tagged_text(&input).is_err());
+
+        let tag = Style::new().to_tag();
+        let nested = format!("[[{0}]][[{0}]]inner[[/{0}]][[/{0}]]", tag);
+        assert!(matches!(
+            StyledParagraph::from_tagged_text(&nested),
+            Err(TagParseError::UnexpectedClose(_))
+        ));
+    }
+
+    #[test]
+    fn test_from_tagged_text_garbage_never_panics() {
+        let alphabet: Vec<char> = "[]/();#(ñ🦀 ab01\\%".chars().collect();
+        let fragments = ["[[", "]]", "[[/", "bold(true)", "pt(11)", ";", "hc(none)"];
+        // Small deterministic generator, so failures are reproducible
+        let mut seed: u64 = 0x2545_F491_4F6C_DD1D;
+        let mut next = || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed as usize
+        };
+
+        for _ in 0..2_000 {
+            let mut input = String::new();
+            for _ in 0..next() % 64 {
+                if next() % 3 == 0 {
+                    input.push_str(fragments[next() % fragments.len()]);
+                } else {
+                    input.push(alphabet[next() % alphabet.len()]);
+                }
+            }
+            let _ = StyledParagraph::from_tagged_text(&input);
+        }
+
+        for input in ["[[", "[[]]", "[[/]]", "[[]]text[[/]]", "[[[[[]]]]]", "[[ñ"] {
+            assert!(
+                StyledParagraph::from_tagged_text(input).is_err(),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_content_hash() {
+        let build = || {
+            let mut p = StyledParagraph::new();
+            p.add(StyledText::new("Same ".to_string(), Style::new()));
+            p.add(StyledText::new(
+                "text".to_string(),
+                Style::new().switch_bold(),
+            ));
+            p
+        };
+        let mut p = build();
+        assert_eq!(p.content_hash(), build().content_hash());
+
+        p.raw[1].set_style(Style::new().switch_italic());
+        assert_ne!(p.content_hash(), build().content_hash());
+
+        let mut listed = build();
+        listed.style = ParagraphStyle::new().set_list(Some(ListItem::new(ListKind::Bullet, 0)));
+        assert_ne!(listed.content_hash(), build().content_hash());
+    }
+
+    #[test]
+    fn test_replace_chunk() {
+        let mut p = StyledParagraph::new();
+        p.add(StyledText::new("One ".to_string(), Style::new()));
+        p.add(StyledText::new("two".to_string(), Style::new()));
+        p.add(StyledText::new(" three".to_string(), Style::new()));
+
+        let bold = StyledText::new("TWO".to_string(), Style::new().switch_bold());
+        let previous = p.replace_chunk(1, bold.clone()).unwrap();
+        assert_eq!(previous.text, "two");
+        assert_eq!(p.raw[1], bold);
+        assert_eq!(p.text(), "One TWO three");
+
+        assert!(p.replace_chunk(3, bold).is_none());
+        assert_eq!(p.chunk_count(), 3);
+    }
+
+    #[test]
+    fn test_replace_all_within_chunk() {
+        let bold = Style::new().switch_bold();
+        let mut p = StyledParagraph::new();
+        p.add(StyledText::new("cat and cat".to_string(), Style::new()));
+        p.add(StyledText::new(" sat".to_string(), bold.clone()));
+
+        assert_eq!(p.replace_all("cat", "dog"), 2);
+        assert_eq!(p.text(), "dog and dog sat");
+        assert_eq!(p.chunk_count(), 2);
+        assert_eq!(p.raw[0].style(), &Style::new());
+        assert_eq!(p.raw[1], StyledText::new(" sat".to_string(), bold));
+
+        assert_eq!(p.replace_all("missing", "x"), 0);
+        assert_eq!(p.replace_all("", "x"), 0);
+        assert_eq!(p.text(), "dog and dog sat");
+    }
+
+    #[test]
+    fn test_replace_all_spanning_chunks() {
+        let bold = Style::new().switch_bold();
+        let italic = Style::new().switch_italic();
+        let mut p = StyledParagraph::new();
+        p.add(StyledText::new("Hello wo".to_string(), Style::new()));
+        p.add(StyledText::new("rld".to_string(), bold.clone()));
+        p.add(StyledText::new(" again".to_string(), italic.clone()));
+
+        // "world" starts in the plain chunk, so the replacement is plain
+        assert_eq!(p.replace_all("world", "there"), 1);
+        assert_eq!(p.text(), "Hello there again");
+        assert_eq!(
+            p.raw,
+            vec![
+                StyledText::new("Hello there".to_string(), Style::new()),
+                StyledText::new(" again".to_string(), italic.clone()),
+            ]
+        );
+
+        // Starting in the italic chunk: the replacement is italic
+        let mut p = StyledParagraph::new();
+        p.add(StyledText::new("ab".to_string(), italic.clone()));
+        p.add(StyledText::new("cd".to_string(), bold.clone()));
+        assert_eq!(p.replace_all("bc", "X"), 1);
+        assert_eq!(
+            p.raw,
+            vec![
+                StyledText::new("aX".to_string(), italic),
+                StyledText::new("d".to_string(), bold),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_from_and_into_chunks() {
+        let chunks = vec![
+            StyledText::new("Bold".to_string(), Style::new().switch_bold()),
+            StyledText::new(" plain".to_string(), Style::new()),
+        ];
+        let mut p = StyledParagraph::from_chunks(chunks.clone());
+        assert_eq!(p.chunks(), chunks.as_slice());
+
+        p.chunks_mut()[1].text.push('!');
+        assert_eq!(p.text(), "Bold plain!");
+        assert_eq!(p.into_chunks()[1].text, " plain!");
+    }
+
+    #[test]
+    fn test_locate() {
+        let mut p = StyledParagraph::new();
+        p.add(StyledText::new("ab".to_string(), Style::new()));
+        p.add(StyledText::new(
+            "ñé€".to_string(),
+            Style::new().switch_bold(),
+        ));
+        p.add(StyledText::new("z".to_string(), Style::new()));
+
+        assert_eq!(p.locate(0), Some((0, 0)));
+        assert_eq!(p.locate(1), Some((0, 1)));
+        // Chunk boundaries belong to the next chunk
+        assert_eq!(p.locate(2), Some((1, 0)));
+        // Multibyte chunk: ñ and é are 2 bytes each
+        assert_eq!(p.locate(3), Some((1, 2)));
+        assert_eq!(p.locate(4), Some((1, 4)));
+        assert_eq!(p.locate(5), Some((2, 0)));
+        // The end of the paragraph is the end of the last chunk
+        assert_eq!(p.locate(6), Some((2, 1)));
+        assert_eq!(p.locate(7), None);
+        assert_eq!(StyledParagraph::new().locate(0), None);
+    }
+
+    #[test]
+    fn test_utf16_offsets() {
+        let mut p = StyledParagraph::new();
+        p.add(StyledText::new("a😀".to_string(), Style::new()));
+        p.add(StyledText::new(
+            "é!".to_string(),
+            Style::new().switch_bold(),
+        ));
+
+        // The emoji is a surrogate pair, é is a single unit
+        assert_eq!(p.char_to_utf16_offset(0), Some(0));
+        assert_eq!(p.char_to_utf16_offset(1), Some(1));
+        assert_eq!(p.char_to_utf16_offset(2), Some(3));
+        assert_eq!(p.char_to_utf16_offset(4), Some(5));
+        assert_eq!(p.char_to_utf16_offset(5), None);
+
+        assert_eq!(p.utf16_to_char_offset(1), Some(1));
+        assert_eq!(p.utf16_to_char_offset(2), None);
+        assert_eq!(p.utf16_to_char_offset(3), Some(2));
+        assert_eq!(p.utf16_to_char_offset(5), Some(4));
+        assert_eq!(p.utf16_to_char_offset(6), None);
+
+        for offset in 0..=p.char_len() {
+            let units = p.char_to_utf16_offset(offset).unwrap();
+            assert_eq!(p.utf16_to_char_offset(units), Some(offset));
+        }
+    }
+
+    #[test]
+    fn test_chunk_accessors() {
+        let mut p = StyledParagraph::new();
+        p.add(StyledText::new("First".to_string(), Style::new()));
+        p.add(StyledText::new(" second".to_string(), Style::new()));
+
+        assert_eq!(p.get_chunk(1).unwrap().text, " second");
+        assert!(p.get_chunk(2).is_none());
+
+        p.get_chunk_mut(0)
+            .unwrap()
+            .set_style(Style::new().switch_bold());
+        assert!(p.raw[0].style().bold());
+        assert!(p.get_chunk_mut(5).is_none());
+
+        assert!(p.remove_chunk(2).is_none());
+        assert_eq!(p.remove_chunk(0).unwrap().text, "First");
+        assert_eq!(p.chunk_count(), 1);
+        assert_eq!(p.text(), " second");
+    }
+
+    #[test]
+    fn test_clear_style() {
+        let mut p = StyledParagraph::new();
+        p.add(StyledText::new("Plain ".to_string(), Style::new()));
+        p.add(StyledText::new(
+            "bold".to_string(),
+            Style::new().switch_bold(),
+        ));
+        p.add(StyledText::new(
+            " end".to_string(),
+            Style::new().change_size(20),
+        ));
+        assert_eq!(p.chunk_count(), 3);
+        p.clear_style();
+        assert_eq!(
+            p.raw,
+            vec![StyledText::new("Plain bold end".to_string(), Style::new())]
         );
 
-        assert_eq!(p.parse_as_raw_tagged_text(), expected);
+        let mut empty = StyledParagraph::new();
+        empty.clear_style();
+        assert!(empty.raw.is_empty());
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_modify_regex() {
+        let mut p = StyledParagraph::new();
+        p.add(StyledText::new(
+            "Chapter 12, page 3".to_string(),
+            Style::new(),
+        ));
+        p.add(StyledText::new(" of 140".to_string(), Style::new()));
+
+        let bold = Style::new().switch_bold();
+        assert_eq!(p.modify_regex(bold.clone(), r"\d+").unwrap(), 3);
+        assert_eq!(p.text(), "Chapter 12, page 3 of 140");
+        let styled: Vec<&str> = p
+            .raw
+            .iter()
+            .filter(|c| c.style() == &bold)
+            .map(|c| c.text.as_str())
+            .collect();
+        assert_eq!(styled, vec!["12", "3", "140"]);
+
+        assert!(matches!(
+            p.modify_regex(bold, r"(\d+"),
+            Err(ParagraphModifyError::InvalidPattern(_))
+        ));
     }
 }
@@ -1,8 +1,15 @@
-use std::fmt;
+use std::{
+    collections::HashMap,
+    fmt::{self, Write},
+    str::FromStr,
+    sync::{LazyLock, Mutex},
+};
 use thiserror::Error;
 
 use font_kit::{error::SelectionError, source::SystemSource};
 
+use super::structural::ApplicableStyles;
+
 #[derive(Debug, Error)]
 pub enum StyleError {
     #[error("Invalid HEX color format: '{0}'")]
@@ -11,9 +18,12 @@ pub enum StyleError {
     FontNotFound(String),
     #[error("Failed to query system fonts for '{0}': {1}")]
     FontQueryError(String, SelectionError),
+    #[error("Malformed style tag: '{0}'")]
+    MalformedTag(String),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum UnderlineStyle {
     Single,
     Words,
@@ -35,6 +45,98 @@ pub enum UnderlineStyle {
     // Note: "none" is represented by Option::None in the Style struct
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CapsStyle {
+    AllCaps,
+    SmallCaps,
+}
+
+/// Line through the text. Word draws `Double` with two lines
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StrikeStyle {
+    Single,
+    Double,
+}
+
+impl fmt::Display for StrikeStyle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                StrikeStyle::Single => "single",
+                StrikeStyle::Double => "double",
+            }
+        )
+    }
+}
+
+impl fmt::Display for CapsStyle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                CapsStyle::AllCaps => "caps",
+                CapsStyle::SmallCaps => "smallcaps",
+            }
+        )
+    }
+}
+
+impl UnderlineStyle {
+    pub const ALL: [UnderlineStyle; 17] = [
+        UnderlineStyle::Single,
+        UnderlineStyle::Words,
+        UnderlineStyle::Double,
+        UnderlineStyle::Thick,
+        UnderlineStyle::Dotted,
+        UnderlineStyle::DottedHeavy,
+        UnderlineStyle::Dash,
+        UnderlineStyle::DashedHeavy,
+        UnderlineStyle::DashLong,
+        UnderlineStyle::DashLongHeavy,
+        UnderlineStyle::DotDash,
+        UnderlineStyle::DashDotHeavy,
+        UnderlineStyle::DotDotDash,
+        UnderlineStyle::DashDotDotHeavy,
+        UnderlineStyle::Wave,
+        UnderlineStyle::WavyHeavy,
+        UnderlineStyle::WavyDouble,
+    ];
+
+    /// Underline named `s` as `FromStr` reads it, or `None` for "none" and an empty value
+    pub fn parse_optional(s: &str) -> Option<UnderlineStyle> {
+        s.parse().ok()
+    }
+}
+
+/// Error from parsing "none", or nothing at all, as an `UnderlineStyle`
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("'none' or an empty value turns the underline off, it isn't a kind of underline")]
+pub struct NoUnderline;
+
+/// Reads the docx names written by `Display`, ignoring case and surrounding whitespace. Imported
+/// files may use kinds this model doesn't know, so anything else falls back to `Single` rather
+/// than losing the underline. The exceptions are "none" and an empty value, which are errors:
+/// see `UnderlineStyle::parse_optional`
+impl FromStr for UnderlineStyle {
+    type Err = NoUnderline;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() || s.eq_ignore_ascii_case("none") {
+            return Err(NoUnderline);
+        }
+        Ok(UnderlineStyle::ALL
+            .into_iter()
+            .find(|u| u.to_string().eq_ignore_ascii_case(s))
+            .unwrap_or(UnderlineStyle::Single))
+    }
+}
+
 impl fmt::Display for UnderlineStyle {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
@@ -64,17 +166,76 @@ impl fmt::Display for UnderlineStyle {
 }
 
 /// A defined Style for a chunk of text.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "SerializedStyle"))]
 pub struct Style {
     bold: bool,
     italic: bool,
     underline: Option<UnderlineStyle>,
+    caps: Option<CapsStyle>,
+    // Absent from styles saved before strikethrough existed
+    #[cfg_attr(feature = "serde", serde(default))]
+    strike: Option<StrikeStyle>,
     size: u8,
     font: String,
     font_color: String,
     highlight_color: Option<String>,
 }
 
+/// Fields of a serialized `Style`. The colors are checked like in `change_font_color`; the font
+/// is not checked against the system, as in `FromStr`
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct SerializedStyle {
+    bold: bool,
+    italic: bool,
+    underline: Option<UnderlineStyle>,
+    caps: Option<CapsStyle>,
+    #[serde(default)]
+    strike: Option<StrikeStyle>,
+    size: u8,
+    font: String,
+    font_color: String,
+    highlight_color: Option<String>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<SerializedStyle> for Style {
+    type Error = StyleError;
+
+    fn try_from(style: SerializedStyle) -> Result<Self, Self::Error> {
+        Style {
+            bold: style.bold,
+            italic: style.italic,
+            underline: style.underline,
+            caps: style.caps,
+            strike: style.strike,
+            size: style.size,
+            font: style.font,
+            font_color: String::new(),
+            highlight_color: None,
+        }
+        .change_font_color(style.font_color)?
+        .change_font_highlight(style.highlight_color)
+    }
+}
+
+/// Partial style update, as produced by a single toolbar action. Only the `Some` fields are
+/// applied by `Style::merge`; the nested options clear an attribute when set to `Some(None)`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StyleOverride {
+    pub bold: Option<bool>,
+    pub italic: Option<bool>,
+    pub underline: Option<Option<UnderlineStyle>>,
+    pub caps: Option<Option<CapsStyle>>,
+    pub strike: Option<Option<StrikeStyle>>,
+    pub size: Option<u8>,
+    pub font: Option<String>,
+    pub font_color: Option<String>,
+    pub highlight_color: Option<Option<String>>,
+}
+
 impl fmt::Display for Style {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         if self.bold {
@@ -86,6 +247,12 @@ impl fmt::Display for Style {
         if let Some(u_style) = &self.underline {
             write!(f, "underline({});", u_style)?;
         }
+        if let Some(caps) = &self.caps {
+            write!(f, "{};", caps)?;
+        }
+        if let Some(strike) = &self.strike {
+            write!(f, "strike({});", strike)?;
+        }
         if let Some(color) = &self.highlight_color {
             write!(f, "hc({});", color)?;
         }
@@ -94,12 +261,77 @@ impl fmt::Display for Style {
     }
 }
 
+/// Parses the explicit format produced by `Style::to_tag`. Fields may come in any order and
+/// missing ones keep their default value. Fonts are not checked against the system here.
+impl FromStr for Style {
+    type Err = StyleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let malformed = || StyleError::MalformedTag(s.to_string());
+        let mut style = Style::new();
+
+        for field in s.split(';') {
+            let (key, value) = field
+                .strip_suffix(')')
+                .and_then(|f| f.split_once('('))
+                .ok_or_else(malformed)?;
+
+            match key {
+                "bold" => style.bold = value.parse().map_err(|_| malformed())?,
+                "italic" => style.italic = value.parse().map_err(|_| malformed())?,
+                "underline" => style.underline = UnderlineStyle::parse_optional(value),
+                "caps" => {
+                    style.caps = match value {
+                        "none" => None,
+                        "caps" => Some(CapsStyle::AllCaps),
+                        "smallcaps" => Some(CapsStyle::SmallCaps),
+                        _ => return Err(malformed()),
+                    }
+                }
+                "strike" => {
+                    style.strike = match value {
+                        "none" => None,
+                        "single" => Some(StrikeStyle::Single),
+                        "double" => Some(StrikeStyle::Double),
+                        _ => return Err(malformed()),
+                    }
+                }
+                "pt" => style.size = value.parse().map_err(|_| malformed())?,
+                "font" => style.font = unescape_tag_value(value),
+                "fc" => {
+                    check_hex(value)?;
+                    style.font_color = value.to_string();
+                }
+                "hc" => {
+                    style.highlight_color = match value {
+                        "none" => None,
+                        v => {
+                            check_hex(v)?;
+                            Some(v.to_string())
+                        }
+                    }
+                }
+                _ => return Err(malformed()),
+            }
+        }
+        Ok(style)
+    }
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Style {
     pub fn new() -> Self {
         Self {
             bold: false,
             italic: false,
             underline: None,
+            caps: None,
+            strike: None,
             size: 11,
             font: "Arial".into(),
             font_color: "#000000".into(),
@@ -122,6 +354,16 @@ impl Style {
         self
     }
 
+    pub fn set_caps(mut self, caps: Option<CapsStyle>) -> Self {
+        self.caps = caps;
+        self
+    }
+
+    pub fn set_strike(mut self, strike: Option<StrikeStyle>) -> Self {
+        self.strike = strike;
+        self
+    }
+
     pub fn change_size(mut self, new_size: u8) -> Self {
         self.size = new_size;
         self
@@ -150,6 +392,198 @@ impl Style {
         Ok(self)
     }
 
+    /// Set the font without checking that it is installed, for headless or server use where the
+    /// system font source is missing or empty. Making sure the name is right is up to the caller;
+    /// readers fall back to another font when it isn't available
+    pub fn set_font_unchecked(mut self, name: &str) -> Self {
+        self.font = name.to_string();
+        self
+    }
+
+    /// Copy of this style with the `Some` fields of `overrides` applied on top. Colors that are not
+    /// valid hex codes are ignored
+    pub fn merge(&self, overrides: &StyleOverride) -> Style {
+        let mut style = self.clone();
+        if let Some(bold) = overrides.bold {
+            style.bold = bold;
+        }
+        if let Some(italic) = overrides.italic {
+            style.italic = italic;
+        }
+        if let Some(underline) = &overrides.underline {
+            style.underline = underline.clone();
+        }
+        if let Some(caps) = overrides.caps {
+            style.caps = caps;
+        }
+        if let Some(strike) = overrides.strike {
+            style.strike = strike;
+        }
+        if let Some(size) = overrides.size {
+            style.size = size;
+        }
+        if let Some(font) = &overrides.font {
+            style.font = font.clone();
+        }
+        if let Some(color) = &overrides.font_color
+            && check_hex(color).is_ok()
+        {
+            style.font_color = color.clone();
+        }
+        if let Some(highlight) = &overrides.highlight_color
+            && highlight.as_deref().is_none_or(|h| check_hex(h).is_ok())
+        {
+            style.highlight_color = highlight.clone();
+        }
+        style
+    }
+
+    /// Font color as float RGBA channels, ready for `gdk4::RGBA::new`
+    pub fn font_rgba(&self) -> [f32; 4] {
+        // The color is validated on every path that sets it
+        hex_to_rgba(&self.font_color).unwrap_or([0.0, 0.0, 0.0, 1.0])
+    }
+
+    /// WCAG contrast ratio (1.0 to 21.0) between the font color and the highlight, or white
+    /// without one. Translucent colors are blended over the color behind them first
+    pub fn contrast_ratio(&self) -> f64 {
+        let background = self
+            .highlight_color
+            .as_deref()
+            .and_then(|hex| hex_to_rgba(hex).ok())
+            .map_or([1.0; 3], |rgba| blend(rgba, [1.0; 3]));
+        let foreground = blend(self.font_rgba(), background);
+
+        let (fg, bg) = (luminance(foreground), luminance(background));
+        (fg.max(bg) + 0.05) / (fg.min(bg) + 0.05)
+    }
+
+    /// Whether the text meets the WCAG AA contrast ratio for normal text (4.5:1)
+    pub fn is_accessible(&self) -> bool {
+        self.contrast_ratio() >= 4.5
+    }
+
+    /// Whether this is the style new text gets by default (`Style::new()`)
+    pub fn is_default(&self) -> bool {
+        *self == Style::new()
+    }
+
+    /// Whether the styles match on every attribute except the kinds named in `ignore`, e.g. "bold
+    /// whatever the color". Only the variant of each entry matters, not the value it carries
+    pub fn eq_ignoring(&self, other: &Style, ignore: &[ApplicableStyles]) -> bool {
+        let mut other = other.clone();
+        for kind in ignore {
+            match kind {
+                ApplicableStyles::Bold => other.bold = self.bold,
+                ApplicableStyles::Italic => other.italic = self.italic,
+                ApplicableStyles::Underline(_) => other.underline = self.underline.clone(),
+                ApplicableStyles::Size(_) => other.size = self.size,
+                ApplicableStyles::Font(_) => other.font = self.font.clone(),
+                ApplicableStyles::Color(_) => other.font_color = self.font_color.clone(),
+                ApplicableStyles::Highlight(_) => {
+                    other.highlight_color = self.highlight_color.clone()
+                }
+            }
+        }
+        *self == other
+    }
+
+    /// Copy with the size clamped to `min_size..=max_size` and colors that aren't valid hex codes
+    /// reset: the font color to the default, the highlight to none. A font name that can't be a
+    /// family name (empty, or holding control or markup characters) is reset to the default. For
+    /// styles from untrusted input, which may not have gone through the checked setters
+    pub fn sanitized(&self, min_size: u8, max_size: u8) -> Style {
+        let mut style = self.clone();
+        style.size = style.size.max(min_size).min(max_size);
+        let unusable = |c: char| c.is_control() || matches!(c, '<' | '>' | '&' | '"');
+        if style.font.trim().is_empty() || style.font.contains(unusable) {
+            style.font = Style::new().font;
+        }
+        if check_hex(&style.font_color).is_err() {
+            style.font_color = Style::new().font_color;
+        }
+        if style
+            .highlight_color
+            .as_ref()
+            .is_some_and(|color| check_hex(color).is_err())
+        {
+            style.highlight_color = None;
+        }
+        style
+    }
+
+    /// Whether the font family `name` is installed, e.g. to grey out a font picker entry. Lookups
+    /// are cached, so calling this for every entry is cheap
+    pub fn font_is_available(name: &str) -> bool {
+        check_font(name).is_ok()
+    }
+
+    /// Forget every font lookup, e.g. after fonts were installed or removed, so the next checks
+    /// ask the system again
+    pub fn clear_font_cache() {
+        if let Ok(mut cache) = FONT_CACHE.lock() {
+            cache.clear();
+        }
+    }
+
+    /// Style as inline CSS declarations for HTML export. Properties left at their default are
+    /// omitted, so the default style gives an empty string
+    pub fn to_css(&self) -> String {
+        let default = Style::new();
+        let mut css = Vec::new();
+        if self.bold {
+            css.push("font-weight:bold".to_string());
+        }
+        if self.italic {
+            css.push("font-style:italic".to_string());
+        }
+        match (self.underline.is_some(), self.strike.is_some()) {
+            (true, true) => css.push("text-decoration:underline line-through".to_string()),
+            (true, false) => css.push("text-decoration:underline".to_string()),
+            (false, true) => css.push("text-decoration:line-through".to_string()),
+            (false, false) => {}
+        }
+        match self.caps {
+            Some(CapsStyle::AllCaps) => css.push("text-transform:uppercase".to_string()),
+            Some(CapsStyle::SmallCaps) => css.push("font-variant:small-caps".to_string()),
+            None => {}
+        }
+        if self.size != default.size {
+            css.push(format!("font-size:{}pt", self.size));
+        }
+        if self.font != default.font {
+            css.push(format!("font-family:{}", css_string(&self.font)));
+        }
+        if self.font_color != default.font_color {
+            css.push(format!("color:{}", css_color(&self.font_color)));
+        }
+        if let Some(highlight) = &self.highlight_color {
+            css.push(format!("background-color:{}", css_color(highlight)));
+        }
+        css.join(";")
+    }
+
+    /// Tag used by the tagged-text format. Unlike `Display`, every field is emitted (defaults
+    /// included) so the tag can be parsed back into an identical style. Characters of the font
+    /// name that would break up the tag are written as `%XX`. Tags written before strikethrough
+    /// existed still parse, as missing fields keep their default
+    pub fn to_tag(&self) -> String {
+        format!(
+            "bold({});italic({});underline({});caps({});strike({});pt({});font({});fc({});hc({})",
+            self.bold,
+            self.italic,
+            self.underline
+                .as_ref()
+                .map_or("none".to_string(), |u| u.to_string()),
+            self.caps.map_or("none".to_string(), |c| c.to_string()),
+            self.strike.map_or("none".to_string(), |s| s.to_string()),
+            self.size,
+            escape_tag_value(&self.font),
+            self.font_color,
+            self.highlight_color.as_deref().unwrap_or("none"),
+        )
+    }
+
     // Getters for private fields
     pub fn bold(&self) -> bool {
         self.bold
@@ -163,6 +597,14 @@ impl Style {
         self.underline.as_ref()
     }
 
+    pub fn caps(&self) -> Option<CapsStyle> {
+        self.caps
+    }
+
+    pub fn strike(&self) -> Option<StrikeStyle> {
+        self.strike
+    }
+
     pub fn size(&self) -> u8 {
         self.size
     }
@@ -180,8 +622,127 @@ impl Style {
     }
 }
 
+/// Float RGBA channels (0.0..=1.0) of a hex color, the layout `gdk4::RGBA::new` takes.
+/// Colors without an alpha channel are fully opaque
+pub fn hex_to_rgba(hex: &str) -> Result<[f32; 4], StyleError> {
+    check_hex(hex)?;
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap_or(0) as f32 / 255.0;
+    let alpha = if hex.len() == 9 { channel(7) } else { 1.0 };
+    Ok([channel(1), channel(3), channel(5), alpha])
+}
+
+/// Quoted CSS string. Besides the quote and backslash, the characters that would end an HTML
+/// attribute or start markup are written as CSS escapes, so the result can go into `style="..."`
+/// as is
+fn css_string(text: &str) -> String {
+    let mut quoted = String::from("'");
+    for c in text.chars() {
+        match c {
+            c if matches!(c, '\\' | '\'' | '"' | '<' | '>' | '&') || c.is_control() => {
+                let _ = write!(quoted, "\\{:x} ", u32::from(c));
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('\'');
+    quoted
+}
+
+/// Characters of a font name that would break up a style tag: field and value delimiters, the
+/// brackets around the tag and the escape character itself
+const TAG_RESERVED: [char; 6] = ['%', ';', '(', ')', '[', ']'];
+
+/// Write the characters of `TAG_RESERVED` as `%XX`
+fn escape_tag_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if TAG_RESERVED.contains(&c) {
+            let _ = write!(escaped, "%{:02X}", c as u32);
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+/// Undo `escape_tag_value`. Other `%` sequences are kept as they are, so fonts from older tags
+/// read the same
+fn unescape_tag_value(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(c) = rest.chars().next() {
+        let decoded = rest
+            .strip_prefix('%')
+            .and_then(|r| r.get(..2))
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .map(char::from)
+            .filter(|c| TAG_RESERVED.contains(c));
+        match decoded {
+            Some(reserved) => {
+                unescaped.push(reserved);
+                rest = &rest[3..];
+            }
+            None => {
+                unescaped.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    unescaped
+}
+
+/// CSS has no 8 digit hex in older engines, so colors with alpha are emitted as rgba()
+pub(crate) fn css_color(hex: &str) -> String {
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap_or(0);
+    if hex.len() == 9 && check_hex(hex).is_ok() {
+        format!(
+            "rgba({},{},{},{:.2})",
+            channel(1),
+            channel(3),
+            channel(5),
+            channel(7) as f64 / 255.0
+        )
+    } else {
+        hex.to_string()
+    }
+}
+
+/// Color as seen over an opaque `background`
+fn blend(rgba: [f32; 4], background: [f64; 3]) -> [f64; 3] {
+    let alpha = f64::from(rgba[3]);
+    [0, 1, 2].map(|i| f64::from(rgba[i]) * alpha + background[i] * (1.0 - alpha))
+}
+
+/// WCAG relative luminance of an sRGB color
+fn luminance(rgb: [f64; 3]) -> f64 {
+    let [r, g, b] = rgb.map(|c| {
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    });
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+/// Hex color for float RGBA channels, as a GTK color button reports them. Out of range values are
+/// clamped and the alpha channel is only written when the color is not fully opaque
+pub fn rgba_to_hex(rgba: [f32; 4]) -> String {
+    let byte = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+    let mut hex = format!(
+        "#{:02x}{:02x}{:02x}",
+        byte(rgba[0]),
+        byte(rgba[1]),
+        byte(rgba[2])
+    );
+    if byte(rgba[3]) != u8::MAX {
+        hex.push_str(&format!("{:02x}", byte(rgba[3])));
+    }
+    hex
+}
+
 /// Check if the string is a valid HEX color code. They can be # + 6 or 8 depending on alpha channel use
-fn check_hex(s: &str) -> Result<(), StyleError> {
+pub(crate) fn check_hex(s: &str) -> Result<(), StyleError> {
     if !s.starts_with('#') {
         return Err(StyleError::InvalidHexColor(s.to_string()));
     }
@@ -195,12 +756,36 @@ fn check_hex(s: &str) -> Result<(), StyleError> {
     Ok(())
 }
 
+/// Font families already looked up, and whether they were found. Querying the system source is
+/// slow, so answers are kept until `Style::clear_font_cache`
+static FONT_CACHE: LazyLock<Mutex<HashMap<String, bool>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
 /// Check if the selected font exists in the system
 fn check_font(s: &str) -> Result<(), StyleError> {
-    match SystemSource::new().select_family_by_name(s) {
-        Ok(_) => Ok(()),
-        Err(SelectionError::NotFound) => Err(StyleError::FontNotFound(s.to_string())),
-        Err(e) => Err(StyleError::FontQueryError(s.to_string(), e)),
+    let cached = FONT_CACHE
+        .lock()
+        .ok()
+        .and_then(|cache| cache.get(s).copied());
+    let found = match cached {
+        Some(found) => found,
+        None => {
+            let found = match SystemSource::new().select_family_by_name(s) {
+                Ok(_) => true,
+                Err(SelectionError::NotFound) => false,
+                // Not cached, the next call may succeed
+                Err(e) => return Err(StyleError::FontQueryError(s.to_string(), e)),
+            };
+            if let Ok(mut cache) = FONT_CACHE.lock() {
+                cache.insert(s.to_string(), found);
+            }
+            found
+        }
+    };
+    if found {
+        Ok(())
+    } else {
+        Err(StyleError::FontNotFound(s.to_string()))
     }
 }
 
@@ -211,9 +796,10 @@ mod tests {
     #[test]
     fn test_style_new_defaults() {
         let style = Style::new();
-        assert_eq!(style.bold(), false);
-        assert_eq!(style.italic(), false);
+        assert!(!style.bold());
+        assert!(!style.italic());
         assert_eq!(style.underline(), None);
+        assert_eq!(style.caps(), None);
         assert_eq!(style.size(), 11);
         assert_eq!(style.font(), "Arial");
         assert_eq!(style.font_color(), "#000000");
@@ -223,14 +809,14 @@ mod tests {
     #[test]
     fn test_style_toggles() {
         let style = Style::new();
-        assert_eq!(style.bold(), false);
+        assert!(!style.bold());
         let style = style.switch_bold();
-        assert_eq!(style.bold(), true);
+        assert!(style.bold());
         let style = style.switch_bold();
-        assert_eq!(style.bold(), false);
+        assert!(!style.bold());
 
         let style = style.switch_italic();
-        assert_eq!(style.italic(), true);
+        assert!(style.italic());
         let style = style.set_underline(Some(UnderlineStyle::Single));
         assert_eq!(style.underline(), Some(&UnderlineStyle::Single));
     }
@@ -290,13 +876,36 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_style_change_font_highlight_empty() {
+        let result = Style::new().change_font_highlight(Some(String::new()));
+        assert!(matches!(
+            result.unwrap_err(),
+            StyleError::InvalidHexColor(_)
+        ));
+    }
+
+    #[test]
+    fn test_apply_to_raw_skips_invalid_highlight() {
+        // Only reachable by bypassing the setters, e.g. a deserialized value
+        let style = Style {
+            highlight_color: Some(String::new()),
+            ..Style::new()
+        };
+        let run = crate::stylemgr::text::StyledText::new("Text".to_string(), style).apply_to_raw();
+        let xml = crate::stylemgr::text::tests::run_xml(run);
+        assert!(xml.contains("Text"));
+        assert!(!xml.contains("<w:highlight"));
+        assert!(!xml.contains("<w:shd"));
+    }
+
     #[test]
     fn test_style_change_font_valid() {
         // Assuming common fonts are available. Might fail in minimal environments.
         let result = Style::new().change_font("Times New Roman".to_string());
         // This check depends on the font being installed on the system running tests
-        if result.is_ok() {
-            assert_eq!(result.unwrap().font(), "Times New Roman");
+        if let Ok(style) = result {
+            assert_eq!(style.font(), "Times New Roman");
         } else {
             // If font isn't found, don't fail the test, just acknowledge
             println!("Test skipped: 'Times New Roman' not found.");
@@ -310,6 +919,195 @@ mod tests {
         assert!(matches!(result.unwrap_err(), StyleError::FontNotFound(_)));
     }
 
+    #[test]
+    fn test_set_font_unchecked() {
+        let name = "UncheckedFontName456";
+        let style = Style::new().set_font_unchecked(name);
+        assert_eq!(style.font(), name);
+        // A lookup would have left the answer in the cache
+        assert!(!FONT_CACHE.lock().unwrap().contains_key(name));
+        assert!(Style::new().change_font(name.to_string()).is_err());
+    }
+
+    #[test]
+    fn test_clear_font_cache() {
+        // Pretend the font was installed after a failed lookup
+        let name = "ClearedFontName789";
+        FONT_CACHE.lock().unwrap().insert(name.to_string(), true);
+        assert!(Style::font_is_available(name));
+        Style::clear_font_cache();
+        assert!(!FONT_CACHE.lock().unwrap().contains_key(name));
+    }
+
+    #[test]
+    fn test_sanitized() {
+        let mut style = Style::new().change_size(200).switch_bold();
+        style.font_color = "red".to_string();
+        style.highlight_color = Some("#12".to_string());
+        style.font = "Evil\"><script>".to_string();
+        let clean = style.sanitized(6, 96);
+        assert_eq!(clean.font(), Style::new().font());
+        assert_eq!(clean.size(), 96);
+        assert_eq!(clean.font_color(), Style::new().font_color());
+        assert_eq!(clean.highlight_color(), None);
+        assert!(clean.bold());
+
+        let valid = Style::new()
+            .change_size(1)
+            .change_font_highlight(Some("#ffff00".to_string()))
+            .unwrap();
+        let clean = valid
+            .clone()
+            .set_font_unchecked("Noto Sans")
+            .sanitized(6, 96);
+        assert_eq!(clean.font(), "Noto Sans");
+        let clean = valid.sanitized(6, 96);
+        assert_eq!(clean.size(), 6);
+        assert_eq!(clean.highlight_color(), Some("#ffff00"));
+    }
+
+    #[test]
+    fn test_css_color() {
+        assert_eq!(css_color("#ff000080"), "rgba(255,0,0,0.50)");
+        assert_eq!(css_color("#ff0000"), "#ff0000");
+        // Nine bytes but not a hex code: passed through instead of sliced mid-character
+        assert_eq!(css_color("#aéééb"), "#aéééb");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_validates_colors() {
+        let style = Style::new()
+            .change_font_highlight(Some("#ffff00".to_string()))
+            .unwrap()
+            .set_strike(Some(StrikeStyle::Double));
+        let json = serde_json::to_string(&style).unwrap();
+        assert_eq!(serde_json::from_str::<Style>(&json).unwrap(), style);
+
+        for bad in [
+            json.replace("#000000", "javascript:alert(1)"),
+            json.replace("#ffff00", "#aéééb"),
+        ] {
+            assert!(serde_json::from_str::<Style>(&bad).is_err());
+        }
+        // Styles saved before strikethrough existed still load
+        let legacy = serde_json::to_string(&Style::new())
+            .unwrap()
+            .replace(r#""strike":null,"#, "");
+        assert_eq!(
+            serde_json::from_str::<Style>(&legacy).unwrap(),
+            Style::new()
+        );
+    }
+
+    #[test]
+    fn test_parse_underline() {
+        for underline in UnderlineStyle::ALL {
+            assert_eq!(underline.to_string().parse(), Ok(underline));
+        }
+        assert_eq!("WAVYDOUBLE".parse(), Ok(UnderlineStyle::WavyDouble));
+        assert_eq!("thickDash".parse(), Ok(UnderlineStyle::Single));
+        assert_eq!(" wave".parse(), Ok(UnderlineStyle::Wave));
+        assert_eq!("double\n".parse(), Ok(UnderlineStyle::Double));
+        assert_eq!("None".parse::<UnderlineStyle>(), Err(NoUnderline));
+        assert_eq!(" none ".parse::<UnderlineStyle>(), Err(NoUnderline));
+        assert_eq!("".parse::<UnderlineStyle>(), Err(NoUnderline));
+        assert_eq!(UnderlineStyle::parse_optional("  "), None);
+        assert_eq!(UnderlineStyle::parse_optional("none"), None);
+        assert_eq!(
+            UnderlineStyle::parse_optional("dash"),
+            Some(UnderlineStyle::Dash)
+        );
+    }
+
+    #[test]
+    fn test_eq_ignoring() {
+        let red = Style::new()
+            .switch_bold()
+            .change_font_color("#ff0000".to_string())
+            .unwrap();
+        let black = Style::new().switch_bold();
+        let color = [ApplicableStyles::Color(String::new())];
+        assert!(red.eq_ignoring(&black, &color));
+        assert!(!red.eq_ignoring(&black, &[]));
+        assert!(!red.eq_ignoring(&black, &[ApplicableStyles::Bold]));
+
+        let plain = Style::new();
+        assert!(!black.eq_ignoring(&plain, &color));
+        assert!(black.eq_ignoring(&plain, &[ApplicableStyles::Bold]));
+    }
+
+    #[test]
+    fn test_font_is_available() {
+        // Any installed family will do; minimal environments may have none
+        match SystemSource::new()
+            .all_families()
+            .ok()
+            .and_then(|f| f.into_iter().next())
+        {
+            Some(family) => {
+                assert!(Style::font_is_available(&family));
+                // Second lookup is answered from the cache
+                assert!(Style::font_is_available(&family));
+            }
+            None => println!("Test skipped: no fonts installed."),
+        }
+        assert!(!Style::font_is_available("DefinitelyNotAFontName123"));
+        assert!(!Style::font_is_available("DefinitelyNotAFontName123"));
+    }
+
+    #[test]
+    fn test_style_is_default() {
+        assert!(Style::new().is_default());
+        assert!(Style::default().is_default());
+        assert!(!Style::new().switch_bold().is_default());
+        assert!(
+            !Style::new()
+                .switch_bold()
+                .switch_bold()
+                .change_size(12)
+                .is_default()
+        );
+    }
+
+    #[test]
+    fn test_style_to_tag_includes_defaults() {
+        assert_eq!(
+            Style::new().to_tag(),
+            "bold(false);italic(false);underline(none);caps(none);strike(none);pt(11);font(Arial);fc(#000000);hc(none)"
+        );
+    }
+
+    #[test]
+    fn test_style_from_tag_roundtrip() {
+        let style = Style::new()
+            .switch_bold()
+            .set_underline(Some(UnderlineStyle::DashDotHeavy))
+            .set_caps(Some(CapsStyle::SmallCaps))
+            .change_size(30)
+            .change_font_highlight(Some("#FFFF00".to_string()))
+            .unwrap();
+        assert_eq!(style.to_tag().parse::<Style>().unwrap(), style);
+    }
+
+    #[test]
+    fn test_style_from_tag_malformed() {
+        for tag in ["bold(yes)", "pt(11", "size(11)", "caps(tiny)", "pt(999)"] {
+            assert!(matches!(
+                tag.parse::<Style>().unwrap_err(),
+                StyleError::MalformedTag(_)
+            ));
+        }
+        assert_eq!(
+            "underline(squiggly)".parse::<Style>().unwrap().underline(),
+            Some(&UnderlineStyle::Single)
+        );
+        assert!(matches!(
+            "fc(red)".parse::<Style>().unwrap_err(),
+            StyleError::InvalidHexColor(_)
+        ));
+    }
+
     #[test]
     fn test_style_display_format() {
         let style = Style::new();
@@ -327,11 +1125,157 @@ mod tests {
         );
 
         let style = Style::new()
+            .set_caps(Some(CapsStyle::SmallCaps))
             .set_underline(Some(UnderlineStyle::Single))
             .change_size(20);
         assert_eq!(
             format!("{}", style),
-            "underline(single);pt(20);Arial;fc(#000000)"
+            "underline(single);smallcaps;pt(20);Arial;fc(#000000)"
         );
     }
+
+    #[test]
+    fn test_style_merge() {
+        let bold = Style::new().switch_bold();
+        let merged = bold.merge(&StyleOverride {
+            size: Some(18),
+            ..Default::default()
+        });
+        assert!(merged.bold());
+        assert_eq!(merged.size(), 18);
+        assert_eq!(merged.font(), bold.font());
+
+        let merged = merged.merge(&StyleOverride {
+            bold: Some(false),
+            highlight_color: Some(Some("#ffff00".to_string())),
+            font_color: Some("not a color".to_string()),
+            ..Default::default()
+        });
+        assert!(!merged.bold());
+        assert_eq!(merged.highlight_color(), Some("#ffff00"));
+        assert_eq!(merged.font_color(), "#000000");
+
+        let cleared = merged.merge(&StyleOverride {
+            highlight_color: Some(None),
+            ..Default::default()
+        });
+        assert_eq!(cleared.highlight_color(), None);
+        assert_eq!(bold.merge(&StyleOverride::default()), bold);
+    }
+
+    #[test]
+    fn test_rgba_conversions() {
+        let close = |a: [f32; 4], b: [f32; 4]| a.iter().zip(b).all(|(x, y)| (x - y).abs() < 0.005);
+
+        let style = Style::new()
+            .change_font_color("#ff8000".to_string())
+            .unwrap();
+        assert!(close(style.font_rgba(), [1.0, 0.5, 0.0, 1.0]));
+        assert!(close(
+            hex_to_rgba("#0000ff80").unwrap(),
+            [0.0, 0.0, 1.0, 0.5]
+        ));
+        assert!(hex_to_rgba("blue").is_err());
+
+        assert_eq!(rgba_to_hex([1.0, 0.5, 0.0, 1.0]), "#ff8000");
+        assert_eq!(rgba_to_hex([0.0, 0.0, 1.0, 0.5]), "#0000ff80");
+        assert_eq!(rgba_to_hex([1.5, -0.2, 0.0, 1.0]), "#ff0000");
+        for hex in ["#123456", "#abcdef", "#12345678"] {
+            assert_eq!(rgba_to_hex(hex_to_rgba(hex).unwrap()), hex);
+        }
+    }
+
+    #[test]
+    fn test_strike() {
+        let style = Style::new().set_strike(Some(StrikeStyle::Double));
+        assert_eq!(style.strike(), Some(StrikeStyle::Double));
+        assert!(!style.is_default());
+        assert!(style.to_string().contains("strike(double);"));
+        assert!(!Style::new().to_string().contains("strike"));
+
+        assert!(style.to_tag().contains(";strike(double);"));
+        assert_eq!(style.to_tag().parse::<Style>().unwrap(), style);
+        assert!(Style::new().to_tag().contains(";strike(none);"));
+        // Tags from before strikethrough existed
+        let legacy = "bold(true);italic(false);underline(none);caps(none);pt(11);font(Arial);fc(#000000);hc(none)";
+        assert_eq!(legacy.parse::<Style>().unwrap(), Style::new().switch_bold());
+        assert!("strike(triple)".parse::<Style>().is_err());
+
+        let cleared = style.merge(&StyleOverride {
+            strike: Some(None),
+            ..Default::default()
+        });
+        assert!(cleared.is_default());
+        assert_eq!(style.to_css(), "text-decoration:line-through");
+    }
+
+    #[test]
+    fn test_to_css() {
+        assert_eq!(Style::new().to_css(), "");
+        assert_eq!(Style::new().change_size(14).to_css(), "font-size:14pt");
+
+        // The font is set directly since the test machine may lack it
+        let style = Style {
+            font: "Georgia".into(),
+            ..Style::new()
+        }
+        .switch_bold()
+        .switch_italic()
+        .set_underline(Some(UnderlineStyle::Double))
+        .set_caps(Some(CapsStyle::SmallCaps))
+        .change_size(18)
+        .change_font_color("#ff0000".to_string())
+        .unwrap()
+        .change_font_highlight(Some("#FFFF0080".to_string()))
+        .unwrap();
+        assert_eq!(
+            style.to_css(),
+            "font-weight:bold;font-style:italic;text-decoration:underline;\
+             font-variant:small-caps;font-size:18pt;font-family:'Georgia';color:#ff0000;\
+             background-color:rgba(255,255,0,0.50)"
+        );
+    }
+
+    #[test]
+    fn test_to_css_hostile_font() {
+        // Fonts aren't checked when parsed from tags or deserialized
+        let style = Style::new().set_font_unchecked("x'\"><script>alert(1)</script>");
+        let css = style.to_css();
+        assert_eq!(
+            css,
+            r"font-family:'x\27 \22 \3e \3c script\3e alert(1)\3c /script\3e '"
+        );
+        assert!(!css.contains(['"', '<', '>', '&']));
+
+        let html = crate::stylemgr::text::StyledText::new("Hi".to_string(), style).to_html();
+        assert!(html.starts_with("<span style=\"font-family:'x\\27 "));
+        assert!(!html.contains("<script"));
+    }
+
+    #[test]
+    fn test_contrast_ratio() {
+        let black_on_white = Style::new();
+        assert!((black_on_white.contrast_ratio() - 21.0).abs() < 0.01);
+        assert!(black_on_white.is_accessible());
+
+        let light_gray = Style::new()
+            .change_font_color("#cccccc".to_string())
+            .unwrap();
+        assert!((light_gray.contrast_ratio() - 1.61).abs() < 0.01);
+        assert!(!light_gray.is_accessible());
+
+        // The highlight is the background; the ratio is symmetric
+        let white_on_black = Style::new()
+            .change_font_color("#ffffff".to_string())
+            .unwrap()
+            .change_font_highlight(Some("#000000".to_string()))
+            .unwrap();
+        assert!((white_on_black.contrast_ratio() - 21.0).abs() < 0.01);
+
+        // A fully transparent font color disappears into the background
+        let invisible = Style::new()
+            .change_font_color("#00000000".to_string())
+            .unwrap();
+        assert!((invisible.contrast_ratio() - 1.0).abs() < 0.01);
+    }
 }
@@ -0,0 +1,225 @@
+//! Tables: a grid of cells, each cell holding its own paragraphs
+use docx_rs::{Paragraph, TableCell, TableRow};
+
+use super::structural::StyledParagraph;
+use super::style::Style;
+
+/// Width shared by the columns of an exported table, in twips (about the text width of a page)
+const TABLE_WIDTH: usize = 9_000;
+
+/// Cell of a table: the paragraphs it contains
+pub type Cell = Vec<StyledParagraph>;
+
+/// Rows of cells. Rows can be shorter than the widest one; missing cells export as empty
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Table {
+    rows: Vec<Vec<Cell>>,
+}
+
+impl Table {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_row(mut self, row: Vec<Cell>) -> Self {
+        self.rows.push(row);
+        self
+    }
+
+    pub fn rows(&self) -> &[Vec<Cell>] {
+        &self.rows
+    }
+
+    pub fn row_count(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// Number of cells in the widest row
+    pub fn column_count(&self) -> usize {
+        self.rows.iter().map(Vec::len).max().unwrap_or(0)
+    }
+
+    pub fn cell(&self, row: usize, column: usize) -> Option<&Cell> {
+        self.rows.get(row)?.get(column)
+    }
+
+    pub fn cell_mut(&mut self, row: usize, column: usize) -> Option<&mut Cell> {
+        self.rows.get_mut(row)?.get_mut(column)
+    }
+
+    /// Every paragraph of the table, row by row
+    pub fn paragraphs(&self) -> impl Iterator<Item = &StyledParagraph> {
+        self.rows.iter().flatten().flatten()
+    }
+
+    pub fn paragraphs_mut(&mut self) -> impl Iterator<Item = &mut StyledParagraph> {
+        self.rows.iter_mut().flatten().flatten()
+    }
+
+    /// Plain text, with cells separated by tabs and rows (and paragraphs within a cell) by newlines
+    pub fn text(&self) -> String {
+        self.rows
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|cell| {
+                        cell.iter()
+                            .map(StyledParagraph::text)
+                            .collect::<Vec<_>>()
+                            .join("\n")
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\t")
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Table as an HTML `<table>` element
+    pub fn to_html(&self) -> String {
+        let mut buffer = String::from("<table>\n");
+        for row in &self.rows {
+            buffer.push_str("<tr>");
+            for cell in row {
+                buffer.push_str("<td>");
+                for sp in cell {
+                    buffer.push_str(&sp.to_html());
+                }
+                buffer.push_str("</td>");
+            }
+            buffer.push_str("</tr>\n");
+        }
+        buffer.push_str("</table>");
+        buffer
+    }
+
+    /// Table as a GFM pipe table, using the first row as the header. Paragraphs and line breaks
+    /// within a cell become `<br>` since a Markdown cell is a single line
+    pub fn to_markdown(&self) -> String {
+        let columns = self.column_count();
+        if columns == 0 {
+            return String::new();
+        }
+        let line = |row: &[Cell]| {
+            let mut line = String::from("|");
+            for idx in 0..columns {
+                let text = row.get(idx).map_or_else(String::new, |cell| {
+                    cell.iter()
+                        .map(|sp| {
+                            sp.to_markdown()
+                                .replace("  \n", "<br>")
+                                .replace('\n', "<br>")
+                                .replace('|', "\\|")
+                        })
+                        .collect::<Vec<_>>()
+                        .join("<br>")
+                });
+                line.push(' ');
+                line.push_str(&text);
+                line.push_str(" |");
+            }
+            line
+        };
+
+        let mut lines = vec![line(&self.rows[0])];
+        lines.push(format!("|{}", " --- |".repeat(columns)));
+        lines.extend(self.rows[1..].iter().map(|row| line(row)));
+        lines.join("\n")
+    }
+
+    /// Table as a `tabular` with ruled columns sharing the line width. Paragraphs within a cell
+    /// are joined with a line break; the columns are `p{}` so `\newline` works inside them
+    #[cfg(feature = "latex")]
+    pub fn to_latex(&self) -> String {
+        let columns = self.column_count();
+        if columns == 0 {
+            return String::new();
+        }
+        let column = format!("p{{\\dimexpr\\linewidth/{}-2\\tabcolsep\\relax}}|", columns);
+        let mut latex = format!(
+            "\\begin{{tabular}}{{|{}}}\n\\hline\n",
+            column.repeat(columns)
+        );
+        for row in &self.rows {
+            let cells: Vec<String> = (0..columns)
+                .map(|idx| {
+                    row.get(idx).map_or_else(String::new, |cell| {
+                        cell.iter()
+                            .map(StyledParagraph::to_latex)
+                            .collect::<Vec<_>>()
+                            .join(" \\newline ")
+                    })
+                })
+                .collect();
+            latex.push_str(&cells.join(" & "));
+            latex.push_str(" \\\\ \\hline\n");
+        }
+        latex.push_str("\\end{tabular}");
+        latex
+    }
+
+    pub fn apply_to_raw(&self) -> docx_rs::Table {
+        self.apply_to_raw_with_defaults(None)
+    }
+
+    /// See `StyledText::apply_to_raw_with_defaults`
+    pub fn apply_to_raw_with_defaults(&self, defaults: Option<&Style>) -> docx_rs::Table {
+        let columns = self.column_count();
+        let rows = self
+            .rows
+            .iter()
+            .map(|row| {
+                let cells = (0..columns)
+                    .map(|idx| {
+                        let paragraphs = row.get(idx).map_or(&[][..], Vec::as_slice);
+                        // A docx cell needs at least one paragraph
+                        if paragraphs.is_empty() {
+                            return TableCell::new().add_paragraph(Paragraph::new());
+                        }
+                        paragraphs.iter().fold(TableCell::new(), |cell, sp| {
+                            cell.add_paragraph(sp.apply_to_raw_with_defaults(defaults))
+                        })
+                    })
+                    .collect();
+                TableRow::new(cells)
+            })
+            .collect();
+        let width = TABLE_WIDTH / columns.max(1);
+        docx_rs::Table::new(rows).set_grid(vec![width; columns])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stylemgr::text::StyledText;
+
+    fn cell(text: &str) -> Cell {
+        let mut sp = StyledParagraph::new();
+        sp.add(StyledText::new(text.to_string(), Style::new()));
+        vec![sp]
+    }
+
+    #[test]
+    fn test_table_text_and_markdown() {
+        let table = Table::new()
+            .add_row(vec![cell("Name"), cell("Qty")])
+            .add_row(vec![cell("a|b")]);
+        assert_eq!(table.row_count(), 2);
+        assert_eq!(table.column_count(), 2);
+        assert_eq!(table.text(), "Name\tQty\na|b");
+        assert_eq!(
+            table.to_markdown(),
+            "| Name | Qty |\n| --- | --- |\n| a\\|b |  |"
+        );
+        assert_eq!(Table::new().to_markdown(), "");
+
+        // A line break must not end the table row
+        let mut two_lines = cell("one");
+        two_lines[0].add(StyledText::line_break(Style::new()));
+        two_lines[0].add(StyledText::new("two".to_string(), Style::new()));
+        let table = Table::new().add_row(vec![two_lines, cell("x")]);
+        assert_eq!(table.to_markdown(), "| one<br>two | x |\n| --- | --- |");
+    }
+}
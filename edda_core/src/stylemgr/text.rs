@@ -1,38 +1,239 @@
-use docx_rs::{Run, RunFonts};
+use std::collections::BTreeMap;
+#[cfg(feature = "compact-styles")]
+use std::collections::HashSet;
+use std::sync::Arc;
+#[cfg(feature = "compact-styles")]
+use std::sync::{LazyLock, Mutex};
+
+use docx_rs::{BreakType, Run, RunFonts};
+use font_kit::{
+    family_name::FamilyName,
+    properties::{Properties, Style as FontStyle, Weight},
+    source::SystemSource,
+};
 
 use super::{
     structural::ApplicableStyles,
-    style::{Style, StyleError},
+    style::{CapsStyle, StrikeStyle, Style, StyleError, check_hex},
 };
 
+/// Marker for a soft line break (Shift+Enter) inside a paragraph, as opposed to a paragraph break
+pub const LINE_BREAK: char = '\n';
+
+/// Size the style table may reach before its first sweep
+#[cfg(feature = "compact-styles")]
+const MIN_SWEEP: usize = 64;
+
+/// Styles handed out by `intern`
+#[cfg(feature = "compact-styles")]
+struct StyleTable {
+    styles: HashSet<Arc<Style>>,
+    sweep_at: usize,
+}
+
+#[cfg(feature = "compact-styles")]
+static STYLES: LazyLock<Mutex<StyleTable>> = LazyLock::new(|| {
+    Mutex::new(StyleTable {
+        styles: HashSet::new(),
+        sweep_at: MIN_SWEEP,
+    })
+});
+
+/// Shared copy of `style`, so chunks with equal styles point at the same allocation instead of
+/// each holding its own font and color strings. Styles no chunk uses anymore are dropped from the
+/// table whenever it has doubled in size since the last sweep
+#[cfg(feature = "compact-styles")]
+pub(crate) fn intern(style: Style) -> Arc<Style> {
+    let Ok(mut table) = STYLES.lock() else {
+        return Arc::new(style);
+    };
+    if let Some(shared) = table.styles.get(&style) {
+        return Arc::clone(shared);
+    }
+    if table.styles.len() >= table.sweep_at {
+        table.styles.retain(|s| Arc::strong_count(s) > 1);
+        table.sweep_at = (table.styles.len() * 2).max(MIN_SWEEP);
+    }
+    let shared = Arc::new(style);
+    table.styles.insert(Arc::clone(&shared));
+    shared
+}
+
+/// Without the `compact-styles` feature every chunk keeps its own style
+#[cfg(not(feature = "compact-styles"))]
+pub(crate) fn intern(style: Style) -> Arc<Style> {
+    Arc::new(style)
+}
+
+#[cfg(feature = "serde")]
+fn deserialize_interned<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Arc<Style>, D::Error> {
+    <Style as serde::Deserialize>::deserialize(deserializer).map(intern)
+}
+
 /// Chunk of text attached to a certain style
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct StyledText {
     pub text: String,
-    pub style: Style,
+    /// With the `compact-styles` feature, shared with every other chunk built with an equal
+    /// style, see `intern`
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "deserialize_interned"))]
+    style: Arc<Style>,
+    /// Free-form key/value data for consumers (indexing, annotations). Not exported to docx or
+    /// HTML. A BTreeMap rather than a HashMap so chunks stay hashable
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    tags: Option<BTreeMap<String, String>>,
 }
 
-impl Default for StyledText {
-    fn default() -> Self {
+impl StyledText {
+    pub fn new(text: String, style: Style) -> Self {
         StyledText {
-            text: String::new(),
-            style: Style::new(),
+            text,
+            style: intern(style),
+            tags: None,
         }
     }
-}
 
-impl StyledText {
-    pub fn new(text: String, style: Style) -> Self {
-        StyledText { text, style }
+    /// Chunk reusing an already shared style, skipping the table lookup
+    pub(crate) fn shared(text: String, style: Arc<Style>) -> Self {
+        StyledText {
+            text,
+            style,
+            tags: None,
+        }
+    }
+
+    pub fn style(&self) -> &Style {
+        &self.style
+    }
+
+    /// The style as stored, for building chunks that share it
+    pub(crate) fn shared_style(&self) -> &Arc<Style> {
+        &self.style
+    }
+
+    pub fn set_style(&mut self, style: Style) {
+        self.style = intern(style);
+    }
+
+    /// Attach `value` under `key`, returning the previous value
+    pub fn set_tag(&mut self, key: impl Into<String>, value: impl Into<String>) -> Option<String> {
+        self.tags
+            .get_or_insert_with(BTreeMap::new)
+            .insert(key.into(), value.into())
     }
 
+    pub fn get_tag(&self, key: &str) -> Option<&str> {
+        self.tags.as_ref()?.get(key).map(String::as_str)
+    }
+
+    /// Remove the tag under `key`. The map goes back to None once it is empty
+    pub fn remove_tag(&mut self, key: &str) -> Option<String> {
+        let tags = self.tags.as_mut()?;
+        let removed = tags.remove(key);
+        if tags.is_empty() {
+            self.tags = None;
+        }
+        removed
+    }
+
+    pub fn tags(&self) -> Option<&BTreeMap<String, String>> {
+        self.tags.as_ref()
+    }
+
+    /// Whether both chunks carry the same style and tags, so they can be merged into one
+    pub(crate) fn same_format(&self, other: &StyledText) -> bool {
+        self.style == other.style && self.tags == other.tags
+    }
+
+    /// Length of the text in Unicode scalar values. Use this for anything cursor or selection related
+    pub fn char_count(&self) -> usize {
+        self.text.chars().count()
+    }
+
+    /// Length of the text in bytes (UTF-8)
+    pub fn byte_count(&self) -> usize {
+        self.text.len()
+    }
+
+    /// Split the chunk at a character offset. `self` keeps the text before it and the rest is
+    /// returned as a new chunk with the same style and tags. None if the offset is past the end
+    pub fn split_at(&mut self, char_offset: usize) -> Option<StyledText> {
+        let byte = match self.text.char_indices().nth(char_offset) {
+            Some((byte, _)) => byte,
+            None if char_offset == self.char_count() => self.text.len(),
+            None => return None,
+        };
+        let tail = self.text.split_off(byte);
+        Some(StyledText {
+            text: tail,
+            style: self.style.clone(),
+            tags: self.tags.clone(),
+        })
+    }
+
+    /// Whether the text is empty or only whitespace (soft line breaks included)
+    pub fn is_whitespace(&self) -> bool {
+        self.text.chars().all(char::is_whitespace)
+    }
+
+    pub fn trim_start(&mut self) {
+        let start = self.text.len() - self.text.trim_start().len();
+        self.text.drain(..start);
+    }
+
+    pub fn trim_end(&mut self) {
+        self.text.truncate(self.text.trim_end().len());
+    }
+
+    pub fn trim(&mut self) {
+        self.trim_end();
+        self.trim_start();
+    }
+
+    /// Chunk holding a single soft line break
+    pub fn line_break(style: Style) -> Self {
+        StyledText::new(LINE_BREAK.into(), style)
+    }
+
+    pub fn is_line_break(&self) -> bool {
+        self.text.chars().eq(std::iter::once(LINE_BREAK))
+    }
+
+    /// Run for the chunk. Properties docx-rs can't write are left as a `RUN_MARKER` run style,
+    /// which only `Document`'s docx export swaps for the real elements
     pub fn apply_to_raw(&self) -> docx_rs::Run {
-        let mut run = Run::new().add_text(&self.text);
+        self.apply_to_raw_with_defaults(None)
+    }
 
-        run = run.fonts(RunFonts::new().ascii(self.style.font()));
-        run = run.size(self.style.size() as usize);
-        // docx-rs Run::color expects hex string without the leading '#'
-        run = run.color(&self.style.font_color()[1..]);
+    /// Run for a docx whose document defaults were set from `defaults`: the font and size are
+    /// left out where they match, so the run inherits them
+    pub fn apply_to_raw_with_defaults(&self, defaults: Option<&Style>) -> docx_rs::Run {
+        let mut run = Run::new();
+        for (n, line) in self.text.split(LINE_BREAK).enumerate() {
+            if n > 0 {
+                run = run.add_break(BreakType::TextWrapping);
+            }
+            if !line.is_empty() {
+                run = run.add_text(line);
+            }
+        }
+
+        if defaults.is_none_or(|d| d.font() != self.style.font()) {
+            run = run.fonts(RunFonts::new().ascii(self.style.font()));
+        }
+        if defaults.is_none_or(|d| d.size() != self.style.size()) {
+            // w:sz is measured in half-points
+            run = run.size(self.style.size() as usize * 2);
+        }
+        if let Some(color) = docx_hex(self.style.font_color()) {
+            run = run.color(color);
+        }
         if self.style.bold() {
             run = run.bold();
         }
@@ -42,42 +243,354 @@ impl StyledText {
         if let Some(u_style) = self.style.underline() {
             run = run.underline(format!("{}", u_style).as_str());
         }
-        if let Some(highlight) = self.style.highlight_color() {
-            // docx-rs Run::highlight expects hex string without the leading '#'
-            run = run.highlight(&highlight[1..]);
+        let mut unsupported = Vec::new();
+        match self.style.caps() {
+            Some(CapsStyle::AllCaps) => run.run_property = run.run_property.caps(),
+            Some(CapsStyle::SmallCaps) => unsupported.push("smallCaps"),
+            None => {}
+        }
+        if let Some(highlight) = self.style.highlight_color().and_then(docx_highlight) {
+            run = run.highlight(highlight);
+        }
+        match self.style.strike() {
+            Some(StrikeStyle::Single) => run.run_property = run.run_property.strike(),
+            Some(StrikeStyle::Double) => unsupported.push("dstrike"),
+            None => {}
+        }
+        if !unsupported.is_empty() {
+            let marker = format!("{}.{}", RUN_MARKER, unsupported.join("."));
+            run.run_property = run.run_property.style(&marker);
         }
 
         run
     }
 
-    // TODO: this is just an initial idea.
+    /// Approximate width of the chunk on one line, in points, from the advance widths of the
+    /// style's font. When the font can't be loaded every character counts as 0.6 em, about the
+    /// width of a monospace glyph. Kerning and line breaks are ignored
+    pub fn estimated_width(&self) -> f64 {
+        let size = f64::from(self.style.size());
+        let text: String = match self.style.caps() {
+            Some(CapsStyle::AllCaps) => self.text.to_uppercase(),
+            _ => self.text.clone(),
+        };
+        let chars = text.chars().filter(|&c| c != LINE_BREAK);
+
+        let mut properties = Properties::new();
+        if self.style.bold() {
+            properties.weight(Weight::BOLD);
+        }
+        if self.style.italic() {
+            properties.style(FontStyle::Italic);
+        }
+        let font = SystemSource::new()
+            .select_best_match(
+                &[FamilyName::Title(self.style.font().to_string())],
+                &properties,
+            )
+            .ok()
+            .and_then(|handle| handle.load().ok());
+        let Some(font) = font else {
+            return chars.count() as f64 * 0.6 * size;
+        };
+
+        let units_per_em = f64::from(font.metrics().units_per_em.max(1));
+        let fallback_units = 0.6 * units_per_em;
+        let units: f64 = chars
+            .map(|c| {
+                font.glyph_for_char(c)
+                    .and_then(|glyph| font.advance(glyph).ok())
+                    .map_or(fallback_units, |advance| f64::from(advance.x()))
+            })
+            .sum();
+        units / units_per_em * size
+    }
+
+    /// Chunk as an HTML `<span>` with its style inlined as CSS. Default-styled text is left bare
+    pub fn to_html(&self) -> String {
+        let text = escape_html(&self.text).replace(LINE_BREAK, "<br>");
+        if self.style.is_default() {
+            return text;
+        }
+        format!("<span style=\"{}\">{}</span>", self.style.to_css(), text)
+    }
+
+    /// Chunk as Markdown. Only bold and italic have a Markdown equivalent; other styling is dropped
+    pub fn to_markdown(&self) -> String {
+        let marker = match (self.style.bold(), self.style.italic()) {
+            (true, true) => "***",
+            (true, false) => "**",
+            (false, true) => "*",
+            (false, false) => "",
+        };
+        let text = escape_markdown(&self.text).replace(LINE_BREAK, "  \n");
+        let core = text.trim();
+        if marker.is_empty() || core.is_empty() {
+            return text;
+        }
+        // Emphasis markers must hug the text, so surrounding whitespace stays outside
+        let start = text.len() - text.trim_start().len();
+        let end = start + core.len();
+        format!(
+            "{}{}{}{}{}",
+            &text[..start],
+            marker,
+            core,
+            marker,
+            &text[end..]
+        )
+    }
+
+    /// Chunk as LaTeX, with bold, italic and underline as `\textbf`, `\textit` and `\underline`.
+    /// A font color other than the default black becomes `\textcolor[HTML]`, which needs the
+    /// `xcolor` package. Line breaks are `\newline` rather than `\\`, which would end the row
+    /// inside a table
+    #[cfg(feature = "latex")]
+    pub fn to_latex(&self) -> String {
+        let mut latex = escape_latex(&self.text).replace(LINE_BREAK, "\\newline\n");
+        if latex.trim().is_empty() {
+            return latex;
+        }
+        if self.style.underline().is_some() {
+            latex = format!("\\underline{{{}}}", latex);
+        }
+        if self.style.italic() {
+            latex = format!("\\textit{{{}}}", latex);
+        }
+        if self.style.bold() {
+            latex = format!("\\textbf{{{}}}", latex);
+        }
+        if self.style.font_color() != Style::new().font_color()
+            && let Some(color) = docx_hex(self.style.font_color())
+        {
+            latex = format!("\\textcolor[HTML]{{{}}}{{{}}}", color, latex);
+        }
+        latex
+    }
+
+    /// Chunk with ANSI SGR escapes for a terminal preview, reset at the end. The font color maps
+    /// to the nearest of the 256 xterm colors, except the default black, which would vanish on a
+    /// dark terminal
+    pub fn to_ansi(&self) -> String {
+        let mut codes = Vec::new();
+        if self.style.bold() {
+            codes.push("1".to_string());
+        }
+        if self.style.italic() {
+            codes.push("3".to_string());
+        }
+        if self.style.underline().is_some() {
+            codes.push("4".to_string());
+        }
+        if self.style.font_color() != Style::new().font_color() {
+            let [r, g, b, _] = self.style.font_rgba().map(|c| (c * 255.0).round() as u8);
+            codes.push(format!("38;5;{}", ansi_256([r, g, b])));
+        }
+        if codes.is_empty() {
+            return self.text.clone();
+        }
+        let mut buffer: String = codes.iter().map(|c| format!("\x1b[{}m", c)).collect();
+        buffer.push_str(&self.text);
+        buffer.push_str("\x1b[0m");
+        buffer
+    }
+
+    /// Chunk in the tagged-text format. Default-styled text is left untagged, which the parser
+    /// reads back as default style. The text is escaped, see `escape_tagged`
     pub fn apply_style_tagging(&self) -> String {
-        format!("[[{}]]{}[[/{}]]", self.style, self.text, self.style)
+        let text = escape_tagged(&self.text);
+        if self.style.is_default() {
+            return text;
+        }
+        let tag = self.style.to_tag();
+        format!("[[{}]]{}[[/{}]]", tag, text, tag)
+    }
+
+    /// Opening tag of the chunk in the tagged-text format, e.g. `[[bold(true);...]]`. Empty for
+    /// the default style, which `apply_style_tagging` leaves untagged
+    pub fn style_tag(&self) -> String {
+        if self.style.is_default() {
+            return String::new();
+        }
+        format!("[[{}]]", self.style.to_tag())
     }
 
     /// Change self style of written section calling on certain commands
     // TODO: Maybe this would be optimal receiving an enum
     pub fn change_style(&mut self, command: ApplicableStyles) -> Result<(), StyleError> {
-        self.style = match command {
-            ApplicableStyles::Bold => self.style.clone().switch_bold(),
-            ApplicableStyles::Italic => self.style.clone().switch_italic(),
-            ApplicableStyles::Underline(style_opt) => self.style.clone().set_underline(style_opt),
-            ApplicableStyles::Size(n) => self.style.clone().change_size(n),
-            ApplicableStyles::Color(s) => self.style.clone().change_font_color(s.to_string())?,
-            ApplicableStyles::Highlight(s) => {
-                self.style.clone().change_font_highlight(s.clone())?
+        self.set_style(command.apply_to(self.style().clone())?);
+        Ok(())
+    }
+
+    /// Apply several commands in order. Toggles are applied one after the other, so two `Bold`s
+    /// cancel out. If any command fails the style is left unchanged
+    pub fn apply_commands(&mut self, commands: &[ApplicableStyles]) -> Result<(), StyleError> {
+        let original = self.style.clone();
+        for command in commands {
+            if let Err(e) = self.change_style(command.clone()) {
+                self.style = original;
+                return Err(e);
             }
-            ApplicableStyles::Font(s) => self.style.clone().change_font(s.to_string())?,
-        };
+        }
         Ok(())
     }
 }
 
+/// Escape text for the tagged-text format: `\` and `[` get a backslash in front, so no `[[` is
+/// left for the parser to take as a tag
+pub(crate) fn escape_tagged(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '[') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Undo `escape_tagged`. A backslash before anything else is kept, so hand-written text with
+/// backslashes reads as it did before escaping existed
+pub(crate) fn unescape_tagged(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some(&next @ ('\\' | '['))) => {
+                unescaped.push(next);
+                chars.next();
+            }
+            _ => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+/// Prefix of the run style id standing in for the run properties docx-rs has no setter for. The
+/// element names follow, separated by dots (`EddaRun.dstrike`), and the docx XML is patched to
+/// hold those elements instead
+pub(crate) const RUN_MARKER: &str = "EddaRun";
+
+/// Color as docx wants it: six hex digits without '#'. Word has no alpha, so it is dropped.
+/// Invalid colors yield None instead of panicking on the slice
+pub(crate) fn docx_hex(color: &str) -> Option<&str> {
+    check_hex(color).ok().map(|_| &color[1..7])
+}
+
+/// Highlight colors Word accepts (w:highlight can't take arbitrary hex values)
+const DOCX_HIGHLIGHTS: [(&str, [u8; 3]); 16] = [
+    ("black", [0x00, 0x00, 0x00]),
+    ("blue", [0x00, 0x00, 0xff]),
+    ("cyan", [0x00, 0xff, 0xff]),
+    ("green", [0x00, 0xff, 0x00]),
+    ("magenta", [0xff, 0x00, 0xff]),
+    ("red", [0xff, 0x00, 0x00]),
+    ("yellow", [0xff, 0xff, 0x00]),
+    ("white", [0xff, 0xff, 0xff]),
+    ("darkBlue", [0x00, 0x00, 0x80]),
+    ("darkCyan", [0x00, 0x80, 0x80]),
+    ("darkGreen", [0x00, 0x80, 0x00]),
+    ("darkMagenta", [0x80, 0x00, 0x80]),
+    ("darkRed", [0x80, 0x00, 0x00]),
+    ("darkYellow", [0x80, 0x80, 0x00]),
+    ("darkGray", [0x80, 0x80, 0x80]),
+    ("lightGray", [0xc0, 0xc0, 0xc0]),
+];
+
+/// Closest Word highlight color to a hex color
+fn docx_highlight(color: &str) -> Option<&'static str> {
+    let hex = docx_hex(color)?;
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap_or(0) as i32;
+    let rgb = [channel(0), channel(2), channel(4)];
+    DOCX_HIGHLIGHTS
+        .iter()
+        .min_by_key(|(_, named)| {
+            named
+                .iter()
+                .zip(rgb)
+                .map(|(&n, c)| (n as i32 - c).pow(2))
+                .sum::<i32>()
+        })
+        .map(|(name, _)| *name)
+}
+
+/// Nearest xterm 256-color index: either the 6x6x6 color cube or the 24-step gray ramp
+fn ansi_256(rgb: [u8; 3]) -> u8 {
+    const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    let nearest_level = |c: u8| (0..6).min_by_key(|&i| LEVELS[i].abs_diff(c)).unwrap_or(0);
+    let distance = |a: [u8; 3]| {
+        a.iter()
+            .zip(rgb)
+            .map(|(&x, y)| u32::from(x.abs_diff(y)).pow(2))
+            .sum::<u32>()
+    };
+
+    let [r, g, b] = rgb.map(nearest_level);
+    let cube = [LEVELS[r], LEVELS[g], LEVELS[b]];
+    let average = (rgb.iter().map(|&c| u32::from(c)).sum::<u32>() / 3) as u8;
+    let step = (average.saturating_sub(3) / 10).min(23);
+    let gray = 8 + 10 * step;
+    if distance([gray; 3]) < distance(cube) {
+        232 + step
+    } else {
+        16 + 36 * r as u8 + 6 * g as u8 + b as u8
+    }
+}
+
+/// Backslash-escape the characters Markdown would otherwise treat as markup
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '_' | '`' | '[' | ']' | '<' | '>' | '#') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Escape the LaTeX special characters. The ones that can't take a plain backslash get a command
+#[cfg(feature = "latex")]
+pub(crate) fn escape_latex(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            '~' => escaped.push_str("\\textasciitilde{}"),
+            '^' => escaped.push_str("\\textasciicircum{}"),
+            '#' | '$' | '%' | '&' | '_' | '{' | '}' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub(crate) fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            // Visible in the source, where it would otherwise look like a plain space
+            '\u{a0}' => escaped.push_str("&nbsp;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
     use crate::stylemgr::structural::ApplicableStyles;
     use crate::stylemgr::style::{Style, UnderlineStyle};
+    use docx_rs::{Break, Docx, Paragraph, RunChild};
 
     #[test]
     fn test_styled_text_new() {
@@ -102,19 +615,256 @@ mod tests {
         assert_eq!(st.style.font_color(), "#000000");
     }
 
+    #[test]
+    fn test_tags() {
+        let mut st = StyledText::new("Hello".to_string(), Style::new());
+        assert_eq!(st.tags(), None);
+        assert_eq!(st.set_tag("source", "import"), None);
+        assert_eq!(st.set_tag("source", "paste").as_deref(), Some("import"));
+        assert_eq!(st.get_tag("source"), Some("paste"));
+        assert_eq!(st.get_tag("missing"), None);
+
+        // Tags stay out of the exported run and follow a split
+        let xml = run_xml(st.apply_to_raw());
+        assert!(!xml.contains("paste"));
+        let tail = st.split_at(2).unwrap();
+        assert_eq!(tail.get_tag("source"), Some("paste"));
+
+        assert_eq!(st.remove_tag("source").as_deref(), Some("paste"));
+        assert_eq!(st.tags(), None);
+        assert_eq!(st, StyledText::new("He".to_string(), Style::new()));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_tags_json_roundtrip() {
+        let mut st = StyledText::new("Hello".to_string(), Style::new().switch_bold());
+        let plain = serde_json::to_string(&st).unwrap();
+        assert!(!plain.contains("tags"));
+
+        st.set_tag("note", "check spelling");
+        let json = serde_json::to_string(&st).unwrap();
+        let restored: StyledText = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, st);
+        assert_eq!(restored.get_tag("note"), Some("check spelling"));
+        #[cfg(feature = "compact-styles")]
+        assert!(Arc::ptr_eq(&restored.style, &st.style));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_deserialize_rejects_invalid_color() {
+        let json = serde_json::to_string(&StyledText::new("Hi".to_string(), Style::new()))
+            .unwrap()
+            .replace("#000000", "javascript:alert(1)");
+        assert!(serde_json::from_str::<StyledText>(&json).is_err());
+    }
+
+    #[cfg(feature = "compact-styles")]
+    #[test]
+    fn test_styles_are_shared() {
+        let a = StyledText::new(
+            "One".to_string(),
+            Style::new().switch_bold().change_size(13),
+        );
+        let b = StyledText::new(
+            "Two".to_string(),
+            Style::new().change_size(13).switch_bold(),
+        );
+        assert!(Arc::ptr_eq(&a.style, &b.style));
+
+        let mut c = StyledText::new("Three".to_string(), Style::new());
+        assert!(!Arc::ptr_eq(&a.style, &c.style));
+        c.set_style(Style::new().switch_bold().change_size(13));
+        assert!(Arc::ptr_eq(&a.style, &c.style));
+
+        // Styles only the table still holds are swept once it grows
+        for size in 0..=u8::MAX {
+            StyledText::new(String::new(), Style::new().change_size(size));
+        }
+        // Other tests add styles concurrently, so only check the table didn't keep all of them
+        assert!(STYLES.lock().unwrap().styles.len() <= u8::MAX as usize);
+        let d = StyledText::new(
+            "Four".to_string(),
+            Style::new().switch_bold().change_size(13),
+        );
+        assert!(Arc::ptr_eq(&a.style, &d.style));
+    }
+
+    #[test]
+    fn test_strike_run_property() {
+        let st = StyledText::new(
+            "Gone".to_string(),
+            Style::new().set_strike(Some(StrikeStyle::Single)),
+        );
+        let property = st.apply_to_raw().run_property;
+        assert!(property.strike.is_some());
+        assert!(property.style.is_none());
+
+        let st = StyledText::new(
+            "Gone twice".to_string(),
+            Style::new().set_strike(Some(StrikeStyle::Double)),
+        );
+        let property = st.apply_to_raw().run_property;
+        assert!(property.strike.is_none());
+        assert_eq!(
+            property.style.unwrap().val,
+            format!("{}.dstrike", RUN_MARKER)
+        );
+
+        let plain = StyledText::new("Kept".to_string(), Style::new()).apply_to_raw();
+        assert!(plain.run_property.strike.is_none());
+    }
+
+    #[test]
+    fn test_estimated_width() {
+        // Holds whether or not the font is installed
+        for style in [
+            Style::new(),
+            "font(DefinitelyNotAFontName123)".parse().unwrap(),
+        ] {
+            let short = StyledText::new("Word".to_string(), style.clone());
+            let long = StyledText::new("A longer run of words".to_string(), style.clone());
+            assert!(short.estimated_width() > 0.0);
+            assert!(long.estimated_width() > short.estimated_width());
+
+            let bigger = StyledText::new("Word".to_string(), style.change_size(22));
+            assert!(bigger.estimated_width() > short.estimated_width());
+        }
+        assert_eq!(
+            StyledText::new(String::new(), Style::new()).estimated_width(),
+            0.0
+        );
+    }
+
+    #[test]
+    fn test_apply_commands() {
+        let mut st = StyledText::new("Text".to_string(), Style::new());
+        st.apply_commands(&[
+            ApplicableStyles::Bold,
+            ApplicableStyles::Italic,
+            ApplicableStyles::Size(14),
+        ])
+        .unwrap();
+        assert_eq!(
+            *st.style,
+            Style::new().switch_bold().switch_italic().change_size(14)
+        );
+
+        st.apply_commands(&[ApplicableStyles::Bold, ApplicableStyles::Bold])
+            .unwrap();
+        assert!(st.style.bold());
+
+        // A failing command rolls back the ones before it
+        let before = st.style.clone();
+        let result = st.apply_commands(&[
+            ApplicableStyles::Italic,
+            ApplicableStyles::Color("not a color".to_string()),
+        ]);
+        assert!(result.is_err());
+        assert_eq!(st.style, before);
+    }
+
+    #[test]
+    fn test_to_ansi() {
+        let bold = StyledText::new("Bold".to_string(), Style::new().switch_bold());
+        assert_eq!(bold.to_ansi(), "\x1b[1mBold\x1b[0m");
+        let plain = StyledText::new("plain".to_string(), Style::new());
+        assert_eq!(plain.to_ansi(), "plain");
+
+        let red = Style::new()
+            .switch_italic()
+            .set_underline(Some(UnderlineStyle::Single))
+            .change_font_color("#ff0000".to_string())
+            .unwrap();
+        let st = StyledText::new("red".to_string(), red);
+        assert_eq!(st.to_ansi(), "\x1b[3m\x1b[4m\x1b[38;5;196mred\x1b[0m");
+    }
+
+    #[test]
+    fn test_ansi_256() {
+        assert_eq!(ansi_256([255, 0, 0]), 196);
+        assert_eq!(ansi_256([0, 0, 0]), 16);
+        assert_eq!(ansi_256([255, 255, 255]), 231);
+        // Mid grays land on the gray ramp
+        assert_eq!(ansi_256([128, 128, 128]), 244);
+        assert_eq!(ansi_256([0, 95, 135]), 24);
+    }
+
+    #[test]
+    fn test_char_and_byte_count() {
+        let st = StyledText::new("Hello".to_string(), Style::new());
+        assert_eq!(st.char_count(), 5);
+        assert_eq!(st.byte_count(), 5);
+
+        let st = StyledText::new("añ🦀".to_string(), Style::new());
+        assert_eq!(st.char_count(), 3);
+        assert_eq!(st.byte_count(), 7);
+    }
+
+    #[test]
+    fn test_split_at() {
+        let style = Style::new().switch_italic();
+        let mut st = StyledText::new("añ🦀b".to_string(), style.clone());
+
+        let tail = st.split_at(2).unwrap();
+        assert_eq!(st.text, "añ");
+        assert_eq!(tail.text, "🦀b");
+        assert_eq!(*tail.style, style);
+
+        assert_eq!(st.split_at(2).unwrap().text, "");
+        assert!(st.split_at(3).is_none());
+        assert_eq!(st.text, "añ");
+    }
+
     #[test]
     fn test_apply_style_tagging() {
         let style = Style::new().switch_bold().change_size(14);
         let text = "World".to_string();
         let st = StyledText::new(text.clone(), style);
 
-        // Expected format depends on Style's Display impl
-        let expected_tag = "bold;pt(14);Arial;fc(#000000)";
+        // Expected format depends on Style::to_tag
+        let expected_tag = "bold(true);italic(false);underline(none);caps(none);strike(none);pt(14);font(Arial);fc(#000000);hc(none)";
         let expected_output = format!("[[{}]]{}[[/{}]]", expected_tag, text, expected_tag);
 
         assert_eq!(st.apply_style_tagging(), expected_output);
     }
 
+    #[test]
+    fn test_to_html_highlight() {
+        let style = Style::new()
+            .change_font_highlight(Some("#FFFF00".to_string()))
+            .unwrap();
+        let html = StyledText::new("Marked".to_string(), style).to_html();
+        assert!(html.contains("style=\"background-color:#FFFF00\""));
+
+        let style = Style::new().change_size(12);
+        let html = StyledText::new("Plain".to_string(), style).to_html();
+        assert!(html.contains("style=\"font-size:12pt\""));
+        assert!(!html.contains("background-color"));
+    }
+
+    #[test]
+    fn test_to_html_alpha_and_escaping() {
+        let style = Style::new()
+            .change_font_highlight(Some("#FF000080".to_string()))
+            .unwrap();
+        let html = StyledText::new("a < b & c".to_string(), style).to_html();
+        assert!(html.contains("background-color:rgba(255,0,0,0.50)"));
+        assert!(html.contains(">a &lt; b &amp; c</span>"));
+    }
+
+    #[test]
+    fn test_default_style_is_untagged() {
+        let st = StyledText::new("Plain & simple".to_string(), Style::new());
+        assert_eq!(st.apply_style_tagging(), "Plain & simple");
+        assert_eq!(st.to_html(), "Plain &amp; simple");
+
+        let st = StyledText::new("Bold".to_string(), Style::new().switch_bold());
+        assert!(st.apply_style_tagging().starts_with("[[bold(true);"));
+        assert!(st.to_html().starts_with("<span style="));
+    }
+
     #[test]
     fn test_change_style_simple() {
         let mut st = StyledText::new("Test".to_string(), Style::new());
@@ -173,15 +923,157 @@ mod tests {
         assert!(matches!(result.unwrap_err(), StyleError::FontNotFound(_)));
     }
 
-    // Optional: Basic check for apply_to_raw
+    /// document.xml of a docx holding just `run`. docx-rs doesn't expose the XML of a single run
+    pub(crate) fn run_xml(run: Run) -> String {
+        let xml = Docx::new()
+            .add_paragraph(Paragraph::new().add_run(run))
+            .build()
+            .document;
+        String::from_utf8(xml).unwrap()
+    }
+
     #[test]
     fn test_apply_to_raw_runs() {
-        let st = StyledText::new("Test Run".to_string(), Style::new());
-        let _run = st.apply_to_raw(); // Prefixed with _ to mark as unused
-        // Basic check: Ensure it returns a Run object. More detailed checks are complex.
-        assert!(std::any::TypeId::of::<Run>() == std::any::TypeId::of::<docx_rs::Run>());
-        // We can't easily check the internal state of the Run without more work.
-        // println!("apply_to_raw produced a Run: {:?}", run); // Requires Run to implement Debug - Commented out
+        let plain = run_xml(StyledText::new("Test Run".to_string(), Style::new()).apply_to_raw());
+        assert!(plain.contains("Test Run"));
+        assert!(plain.contains(r#"<w:sz w:val="22" />"#));
+        assert!(plain.contains(r#"<w:color w:val="000000" />"#));
+        assert!(!plain.contains("<w:b />"));
+        assert!(!plain.contains("<w:u "));
+
+        let style = Style::new()
+            .switch_bold()
+            .switch_italic()
+            .set_underline(Some(UnderlineStyle::Double))
+            .change_size(14)
+            .change_font_color("#ff000080".to_string())
+            .unwrap()
+            .change_font_highlight(Some("#f0f010".to_string()))
+            .unwrap();
+        let xml = run_xml(StyledText::new("Styled".to_string(), style).apply_to_raw());
+        assert!(xml.contains("<w:b />"));
+        assert!(xml.contains("<w:i />"));
+        assert!(xml.contains(r#"<w:u w:val="double" />"#));
+        assert!(xml.contains(r#"<w:sz w:val="28" />"#));
+        assert!(xml.contains(r#"<w:color w:val="ff0000" />"#));
+        assert!(xml.contains(r#"<w:highlight w:val="yellow" />"#));
+    }
+
+    #[test]
+    fn test_caps_export() {
+        let st = StyledText::new(
+            "Loud".to_string(),
+            Style::new().set_caps(Some(CapsStyle::AllCaps)),
+        );
+        assert!(st.apply_to_raw().run_property.caps.is_some());
+        assert!(st.to_html().contains("text-transform:uppercase"));
+
+        let st = StyledText::new(
+            "Quiet".to_string(),
+            Style::new().set_caps(Some(CapsStyle::SmallCaps)),
+        );
+        let property = st.apply_to_raw().run_property;
+        assert!(property.caps.is_none());
+        assert_eq!(
+            property.style.unwrap().val,
+            format!("{}.smallCaps", RUN_MARKER)
+        );
+        assert!(st.to_html().contains("font-variant:small-caps"));
+
+        let st = StyledText::new("Plain".to_string(), Style::new());
+        assert!(st.apply_to_raw().run_property.caps.is_none());
+    }
+
+    #[test]
+    fn test_whitespace_and_trim() {
+        let bold = Style::new().switch_bold();
+        let mut spaces = StyledText::new("  \t ".to_string(), bold.clone());
+        assert!(spaces.is_whitespace());
+        spaces.trim();
+        assert!(spaces.text.is_empty());
+        assert_eq!(*spaces.style, bold);
+
+        let mut st = StyledText::new("  ñandú  ".to_string(), bold.clone());
+        assert!(!st.is_whitespace());
+        st.trim_start();
+        assert_eq!(st.text, "ñandú  ");
+        st.trim_end();
+        assert_eq!(st.text, "ñandú");
+
+        let mut st = StyledText::new(" both ".to_string(), bold.clone());
+        st.trim();
+        assert_eq!(st, StyledText::new("both".to_string(), bold));
+    }
+
+    #[cfg(feature = "latex")]
+    #[test]
+    fn test_to_latex() {
+        assert_eq!(
+            escape_latex(r"50% of $5 & #1_{a} \ ~^"),
+            r"50\% of \$5 \& \#1\_\{a\} \textbackslash{} \textasciitilde{}\textasciicircum{}"
+        );
+
+        let style = Style::new().switch_bold().switch_italic();
+        let st = StyledText::new("a_b".to_string(), style.clone());
+        assert_eq!(st.to_latex(), r"\textbf{\textit{a\_b}}");
+
+        let style = style
+            .set_underline(Some(UnderlineStyle::Single))
+            .change_font_color("#ff0000".to_string())
+            .unwrap();
+        let st = StyledText::new("x".to_string(), style);
+        assert_eq!(
+            st.to_latex(),
+            r"\textcolor[HTML]{ff0000}{\textbf{\textit{\underline{x}}}}"
+        );
+        let st = StyledText::new(" ".to_string(), Style::new().switch_bold());
+        assert_eq!(st.to_latex(), " ");
+    }
+
+    #[test]
+    fn test_to_markdown() {
+        let plain = StyledText::new("a*b_c".to_string(), Style::new());
+        assert_eq!(plain.to_markdown(), "a\\*b\\_c");
+
+        let bold = StyledText::new(" bold words ".to_string(), Style::new().switch_bold());
+        assert_eq!(bold.to_markdown(), " **bold words** ");
+
+        let both = Style::new().switch_bold().switch_italic();
+        assert_eq!(
+            StyledText::new("x".to_string(), both.clone()).to_markdown(),
+            "***x***"
+        );
+        assert_eq!(StyledText::new("  ".to_string(), both).to_markdown(), "  ");
+        assert_eq!(
+            StyledText::new("one\ntwo".to_string(), Style::new()).to_markdown(),
+            "one  \ntwo"
+        );
+    }
+
+    #[test]
+    fn test_line_break() {
+        let st = StyledText::line_break(Style::new());
+        assert!(st.is_line_break());
+        assert!(!StyledText::new("Text".to_string(), Style::new()).is_line_break());
+
+        assert_eq!(
+            st.apply_to_raw().children,
+            vec![RunChild::Break(Break::new(BreakType::TextWrapping))]
+        );
+    }
+
+    #[test]
+    fn test_apply_to_raw_splits_on_line_break() {
+        let st = StyledText::new("First\nSecond".to_string(), Style::new());
+        let children = st.apply_to_raw().children;
+        assert_eq!(children.len(), 3);
+        assert!(matches!(&children[0], RunChild::Text(t) if t.text == "First"));
+        assert_eq!(
+            children[1],
+            RunChild::Break(Break::new(BreakType::TextWrapping))
+        );
+        assert!(matches!(&children[2], RunChild::Text(t) if t.text == "Second"));
+        assert!(st.to_html().contains("First<br>Second"));
     }
 
     #[test]
@@ -1,3 +1,7 @@
+// Used by load_css once the GUI lands on this branch
+#[allow(dead_code)]
+mod theme;
+
 fn main() {
     println!("Hello, world!");
 }
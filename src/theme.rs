@@ -0,0 +1,77 @@
+//! Locating the GTK stylesheet. Kept free of GTK so it can be tested on its own
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// Environment variable overriding the stylesheet location
+pub const CSS_ENV: &str = "EDDA_CSS";
+
+const CSS_FILE: &str = "gtk.css";
+
+/// First existing stylesheet among, in order: the `EDDA_CSS` override, `assets/` next to the
+/// executable, `share/edda/` in the install prefix the executable lives in, and `assets/` in the
+/// working directory (running from the project root with cargo). None means the caller should
+/// fall back to the embedded default
+pub fn resolve_css_path(env_override: Option<PathBuf>, exe: Option<&Path>) -> Option<PathBuf> {
+    let mut candidates = Vec::new();
+    candidates.extend(env_override);
+    if let Some(dir) = exe.and_then(Path::parent) {
+        candidates.push(dir.join("assets").join(CSS_FILE));
+        if let Some(prefix) = dir.parent() {
+            candidates.push(prefix.join("share").join("edda").join(CSS_FILE));
+        }
+    }
+    candidates.push(Path::new("assets").join(CSS_FILE));
+
+    candidates.into_iter().find(|path| path.is_file())
+}
+
+/// `resolve_css_path` for the running process
+pub fn css_path() -> Option<PathBuf> {
+    let env_override = env::var_os(CSS_ENV).map(PathBuf::from);
+    resolve_css_path(env_override, env::current_exe().ok().as_deref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn temp_layout(name: &str) -> PathBuf {
+        let root = env::temp_dir().join(format!("edda_theme_{}", name));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("bin").join("assets")).unwrap();
+        fs::create_dir_all(root.join("share").join("edda")).unwrap();
+        root
+    }
+
+    #[test]
+    fn test_resolve_prefers_env_override() {
+        let root = temp_layout("env");
+        let custom = root.join("custom.css");
+        fs::write(&custom, "").unwrap();
+        fs::write(root.join("bin").join("assets").join(CSS_FILE), "").unwrap();
+
+        let exe = root.join("bin").join("edda");
+        assert_eq!(
+            resolve_css_path(Some(custom.clone()), Some(&exe)),
+            Some(custom)
+        );
+        // A missing override falls through to the next candidate
+        assert_eq!(
+            resolve_css_path(Some(root.join("missing.css")), Some(&exe)),
+            Some(root.join("bin").join("assets").join(CSS_FILE))
+        );
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_resolve_install_prefix() {
+        let root = temp_layout("prefix");
+        let installed = root.join("share").join("edda").join(CSS_FILE);
+        fs::write(&installed, "").unwrap();
+
+        let exe = root.join("bin").join("edda");
+        assert_eq!(resolve_css_path(None, Some(&exe)), Some(installed));
+        let _ = fs::remove_dir_all(&root);
+    }
+}